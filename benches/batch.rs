@@ -0,0 +1,63 @@
+// Instructions per second for a batch of machines, on one thread and across every
+// core, with each layout.
+//
+//   cargo bench --bench batch --features batch
+//
+// Like the cycle bench each is run a few times and the best is kept.
+
+use paulson_8::batch::{Batch, Layout, Throughput};
+
+const SUITE: &[u8] = include_bytes!("../tests/roms/timendus/chip8-test-suite.ch8");
+const INSTANCES: usize = 4096;
+const FRAMES: usize = 120;
+const RUNS: usize = 3;
+
+// Every instance runs corax+ with its own keys held, so they don't all stay in step
+fn batch(layout: Layout) -> Batch {
+    let mut batch = Batch::new(SUITE, INSTANCES, layout).unwrap();
+    for instance in 0..INSTANCES {
+        batch.edit(instance, |chip8| chip8.write_memory(0x1FF, &[2]));
+        batch.set_keys(instance, 1 << (instance % 16));
+    }
+    batch
+}
+
+fn best(layout: Layout, threads: usize) -> Throughput {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    (0..RUNS)
+        .map(|_| {
+            let mut batch = batch(layout);
+            pool.install(|| batch.run(FRAMES))
+        })
+        .max_by(|a, b| a.elapsed.cmp(&b.elapsed).reverse())
+        .unwrap()
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!(
+        "{:<10} {:>18} {:>18} {:>8}",
+        "layout",
+        "1 thread/s",
+        format!("{cores} threads/s"),
+        "speedup"
+    );
+    for (name, layout) in [
+        ("instances", Layout::Instances),
+        ("columns", Layout::Columns),
+    ] {
+        let one = best(layout, 1);
+        let all = best(layout, cores);
+        println!(
+            "{:<10} {:>18.0} {:>18.0} {:>7.2}x",
+            name,
+            one.instructions_per_second(),
+            all.instructions_per_second(),
+            all.instructions_per_second() / one.instructions_per_second()
+        );
+    }
+    println!("{}", best(Layout::Columns, cores));
+}
@@ -0,0 +1,109 @@
+// Instructions per second with and without the decode cache, and through the
+// recompiler when it's built in.
+//
+//   cargo bench --bench cycle
+//   cargo bench --bench cycle --features jit
+//
+// Plain timing rather than a bench harness so it runs on stable, each workload is
+// run a few times and the best is kept.
+
+use std::time::Instant;
+
+use paulson_8::interpreter::Chip8;
+
+const SUITE: &[u8] = include_bytes!("../tests/roms/timendus/chip8-test-suite.ch8");
+const CYCLES: usize = 5_000_000;
+const RUNS: usize = 5;
+
+// Counts V2 up forever, writing the ADD back over itself with FX55 every time round
+const SELF_MODIFYING: [u16; 6] = [0x6072, 0x6101, 0xA206, 0x7201, 0xF155, 0x1206];
+// Register shuffling in a tight loop, nothing that needs the screen or memory
+const ARITHMETIC: [u16; 8] = [
+    0x7001, 0x8104, 0x8316, 0x8213, 0x8425, 0x3000, 0x1200, 0x1200,
+];
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Uncached,
+    Cached,
+    // A frame's worth of cycles at a time through `Chip8::run`
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+fn suite(test: u8) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.load_bytes(SUITE).unwrap();
+    chip8.write_memory(0x1FF, &[test]);
+    chip8
+}
+
+fn program(words: &[u16]) -> Chip8 {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect::<Vec<u8>>();
+    let mut chip8 = Chip8::new();
+    chip8.load_bytes(&bytes).unwrap();
+    chip8
+}
+
+fn instructions_per_second(setup: &dyn Fn() -> Chip8, mode: Mode) -> f64 {
+    (0..RUNS)
+        .map(|_| {
+            let mut chip8 = setup();
+            chip8.set_decode_cache(!matches!(mode, Mode::Uncached));
+
+            let start = Instant::now();
+            match mode {
+                #[cfg(feature = "jit")]
+                Mode::Jit => {
+                    for _ in 0..CYCLES / 20 {
+                        chip8.run(20).unwrap();
+                    }
+                }
+                _ => {
+                    for _ in 0..CYCLES {
+                        chip8.cycle().unwrap();
+                    }
+                }
+            }
+            CYCLES as f64 / start.elapsed().as_secs_f64()
+        })
+        .fold(0.0, f64::max)
+}
+
+fn main() {
+    let workloads: [(&str, &dyn Fn() -> Chip8); 4] = [
+        ("corax+", &|| suite(2)),
+        ("flags", &|| suite(3)),
+        ("self modifying", &|| program(&SELF_MODIFYING)),
+        ("arithmetic", &|| program(&ARITHMETIC)),
+    ];
+
+    print!(
+        "{:<16} {:>14} {:>14} {:>8}",
+        "workload", "uncached/s", "cached/s", "speedup"
+    );
+    #[cfg(feature = "jit")]
+    print!(" {:>14} {:>8}", "jit/s", "speedup");
+    println!();
+
+    for (name, setup) in workloads {
+        let uncached = instructions_per_second(setup, Mode::Uncached);
+        let cached = instructions_per_second(setup, Mode::Cached);
+        print!(
+            "{:<16} {:>14.0} {:>14.0} {:>7.2}x",
+            name,
+            uncached,
+            cached,
+            cached / uncached
+        );
+        #[cfg(feature = "jit")]
+        {
+            let jit = instructions_per_second(setup, Mode::Jit);
+            print!(" {:>14.0} {:>7.2}x", jit, jit / uncached);
+        }
+        println!();
+    }
+}
@@ -0,0 +1,60 @@
+// Pieces for running the interpreter on a microcontroller with a 128x64 SSD1306 style
+// display. Builds against paulson-8 without the std feature, so nothing here or in
+// the interpreter allocates:
+//
+//   cargo build --target thumbv7em-none-eabihf
+//
+// A board wires it up with its own input and clock and sends `Ssd1306::buffer` to
+// the display after every `frontend::frame`. `cargo test` on the host checks the core
+// still draws what the std build does.
+
+#![no_std]
+
+use paulson_8::frontend::Display;
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+
+// The display's memory layout, 8 pages of 8 rows with one byte per column and the top
+// row in the lowest bit. Chip-8 pixels are doubled both ways to fill the screen
+pub struct Ssd1306 {
+    pub buffer: [u8; WIDTH * HEIGHT / 8],
+}
+
+impl Default for Ssd1306 {
+    fn default() -> Self {
+        Ssd1306 {
+            buffer: [0; WIDTH * HEIGHT / 8],
+        }
+    }
+}
+
+impl Ssd1306 {
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.buffer[y / 8 * WIDTH + x] & (1 << (y % 8)) != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize) {
+        self.buffer[y / 8 * WIDTH + x] |= 1 << (y % 8);
+    }
+}
+
+impl Display for Ssd1306 {
+    fn present(&mut self, video: &[u8], width: usize, height: usize) {
+        let scale = (WIDTH / width).min(HEIGHT / height);
+        self.buffer.fill(0);
+
+        for y in 0..height {
+            for x in 0..width {
+                if video[x + y * width] == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        self.set(x * scale + dx, y * scale + dy);
+                    }
+                }
+            }
+        }
+    }
+}
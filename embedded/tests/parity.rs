@@ -0,0 +1,67 @@
+// Runs ROMs through the no_std build and compares the screen against the goldens the
+// std build is tested with in tests/conformance.rs, both from the display buffer and
+// from the interpreter directly.
+
+use paulson_8::frontend::{frame, FixedClock, NullAudio, NullInput};
+use paulson_8::interpreter::{Chip8, Random};
+use paulson_8_embedded::Ssd1306;
+
+const SUITE: &[u8] = include_bytes!("../../tests/roms/timendus/chip8-test-suite.ch8");
+const DIGITS: &[u8] = include_bytes!("../../tests/roms/random/digits.ch8");
+
+fn check(name: &str, chip8: &mut Chip8, frames: usize, golden: &str) {
+    let (mut input, mut clock) = (NullInput::default(), FixedClock::default());
+    let (mut audio, mut display) = (NullAudio::default(), Ssd1306::default());
+    for _ in 0..frames {
+        frame(chip8, &mut input, &mut clock, &mut audio, &mut display).unwrap();
+    }
+
+    let golden = std::fs::read_to_string(golden).unwrap();
+    let video = chip8.get_video_buffer();
+    for (y, row) in golden.lines().enumerate() {
+        for (x, expected) in row.chars().enumerate() {
+            let expected = expected == '#';
+            let lit = video[x + y * Chip8::VIDEO_WIDTH as usize] != 0;
+            assert_eq!(lit, expected, "{} differs at {}, {}", name, x, y);
+            assert_eq!(
+                display.pixel(x * 2 + 1, y * 2 + 1),
+                expected,
+                "{} display differs at {}, {}",
+                name,
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn timendus_matches_std() {
+    // Same tests, options and frame counts as tests/conformance.rs
+    for (name, test, option, frames) in [
+        ("logo", 1, 0, 100),
+        ("corax", 2, 0, 200),
+        ("flags", 3, 0, 400),
+        ("quirks-schip", 4, 2, 300),
+    ] {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(SUITE).unwrap();
+        chip8.write_memory(0x1FE, &[option]);
+        chip8.write_memory(0x1FF, &[test]);
+        let golden = format!("../tests/golden/timendus/{}.txt", name);
+        check(name, &mut chip8, frames, &golden);
+    }
+}
+
+#[test]
+fn seeded_random_matches_std() {
+    let mut chip8 = Chip8::new();
+    chip8.set_random(Random::Seeded(1234));
+    chip8.load_bytes(DIGITS).unwrap();
+    check(
+        "digits",
+        &mut chip8,
+        30,
+        "../tests/golden/random/digits.txt",
+    );
+}
@@ -0,0 +1,112 @@
+/* Generated from src/lib.rs by cbindgen, see tests/header.rs. Don't edit. */
+
+#ifndef PAULSON8_H
+#define PAULSON8_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Width of the screen in pixels.
+#define P8_SCREEN_WIDTH 64
+
+// Height of the screen in pixels.
+#define P8_SCREEN_HEIGHT 32
+
+// What a call did, `P8_STATUS_OK` or why it didn't.
+typedef enum P8Status {
+  P8_STATUS_OK = 0,
+  // A pointer argument was NULL.
+  P8_STATUS_NULL_POINTER,
+  // The ROM doesn't fit in memory after 0x200.
+  P8_STATUS_ROM_TOO_LARGE,
+  // The ROM called a subroutine with all 16 stack entries in use.
+  P8_STATUS_STACK_OVERFLOW,
+  // The ROM returned with nothing on the stack.
+  P8_STATUS_STACK_UNDERFLOW,
+  // Keys are 0 to 15.
+  P8_STATUS_BAD_KEY,
+  // A buffer wasn't the size the call needs.
+  P8_STATUS_BUFFER_SIZE,
+  // Not a save state this version can load.
+  P8_STATUS_BAD_STATE,
+  // A bug in the interpreter, the machine shouldn't be used after this.
+  P8_STATUS_PANIC,
+} P8Status;
+
+// A machine, made with `p8_machine_new` and freed with `p8_machine_free`.
+typedef struct P8Machine P8Machine;
+
+// Registers and timers, as they are before the instruction at `pc` runs.
+typedef struct P8Registers {
+  uint8_t v[16];
+  uint16_t i;
+  uint16_t pc;
+  uint8_t sp;
+  uint8_t delay_timer;
+  uint8_t sound_timer;
+} P8Registers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A new machine with the font loaded and nothing else, or NULL if it couldn't be
+// made.
+struct P8Machine *p8_machine_new(void);
+
+// Frees a machine from `p8_machine_new`, NULL is ignored.
+void p8_machine_free(struct P8Machine *machine);
+
+// Copies `len` bytes of ROM to 0x200.
+enum P8Status p8_load(struct P8Machine *machine, const uint8_t *rom, size_t len);
+
+// CXKK takes its bytes from a generator started at `seed`, so runs repeat. Without
+// this they're random.
+enum P8Status p8_seed(struct P8Machine *machine, uint32_t seed);
+
+// Runs `cycles` instructions, stopping early if the ROM faults. The timers count
+// down once per instruction as well.
+enum P8Status p8_step(struct P8Machine *machine, size_t cycles);
+
+// Counts the delay and sound timers down by `ticks`, stopping at 0.
+enum P8Status p8_tick_timers(struct P8Machine *machine, size_t ticks);
+
+// Presses or releases one key, 0 to 15.
+enum P8Status p8_set_key(struct P8Machine *machine, uint8_t key, bool pressed);
+
+// Sets all 16 keys at once, bit n of `keys` held down for key n.
+enum P8Status p8_set_keys(struct P8Machine *machine, uint16_t keys);
+
+// Copies the screen into `out`, which has to be `P8_SCREEN_WIDTH *
+// P8_SCREEN_HEIGHT` bytes. One byte per pixel from the top left, a row at a time,
+// 0xFF for lit and 0 for dark.
+enum P8Status p8_framebuffer(const struct P8Machine *machine, uint8_t *out, size_t len);
+
+// Fills in `out` with the registers and timers.
+enum P8Status p8_registers(const struct P8Machine *machine, struct P8Registers *out);
+
+// Whether the buzzer should be sounding, i.e. the sound timer is above 0.
+bool p8_sound_playing(const struct P8Machine *machine);
+
+// Size of the buffer `p8_save_state` and `p8_load_state` take.
+size_t p8_state_size(void);
+
+// Saves everything the ROM can see into `out`, `p8_state_size()` bytes.
+enum P8Status p8_save_state(const struct P8Machine *machine, uint8_t *out, size_t len);
+
+// Loads a state from `p8_save_state`. The machine is left as it was if it can't be
+// loaded.
+enum P8Status p8_load_state(struct P8Machine *machine, const uint8_t *state, size_t len);
+
+// What a status means, as a static string that doesn't need freeing. Takes an `int`
+// so any value is safe to pass, ones that aren't a `P8Status` get "Unknown status".
+const char *p8_status_message(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PAULSON8_H */
@@ -0,0 +1,328 @@
+// C ABI over `Chip8`, for embedding the interpreter in C, C++, or anything with a C
+// FFI like Python's ctypes. include/paulson8.h is generated from this file by
+// cbindgen, comments on exported items are `///` so they end up in it too.
+//
+// Nothing here panics across the boundary. Every call that can fail returns a
+// `P8Status`, and anything that would have panicked comes back as `P8_STATUS_PANIC`.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use paulson_8::interpreter::trace::Step;
+use paulson_8::interpreter::{self, Chip8, Error, Random, StateError};
+
+/// What a call did, `P8_STATUS_OK` or why it didn't.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Ok = 0,
+    /// A pointer argument was NULL.
+    NullPointer,
+    /// The ROM doesn't fit in memory after 0x200.
+    RomTooLarge,
+    /// The ROM called a subroutine with all 16 stack entries in use.
+    StackOverflow,
+    /// The ROM returned with nothing on the stack.
+    StackUnderflow,
+    /// Keys are 0 to 15.
+    BadKey,
+    /// A buffer wasn't the size the call needs.
+    BufferSize,
+    /// Not a save state this version can load.
+    BadState,
+    /// A bug in the interpreter, the machine shouldn't be used after this.
+    Panic,
+}
+
+impl Status {
+    const ALL: [Status; 9] = [
+        Status::Ok,
+        Status::NullPointer,
+        Status::RomTooLarge,
+        Status::StackOverflow,
+        Status::StackUnderflow,
+        Status::BadKey,
+        Status::BufferSize,
+        Status::BadState,
+        Status::Panic,
+    ];
+}
+
+impl From<Error> for Status {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::StackOverflow { .. } => Status::StackOverflow,
+            Error::StackUnderflow { .. } => Status::StackUnderflow,
+            Error::RomTooLarge { .. } => Status::RomTooLarge,
+        }
+    }
+}
+
+impl From<StateError> for Status {
+    fn from(e: StateError) -> Self {
+        match e {
+            StateError::Size { .. } => Status::BufferSize,
+            StateError::Unrecognised => Status::BadState,
+        }
+    }
+}
+
+/// A machine, made with `p8_machine_new` and freed with `p8_machine_free`.
+pub struct Machine {
+    chip8: Chip8,
+}
+
+/// Registers and timers, as they are before the instruction at `pc` runs.
+#[repr(C)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+// Written out rather than taken from `Chip8` so cbindgen can put them in the header
+/// Width of the screen in pixels.
+pub const SCREEN_WIDTH: usize = 64;
+/// Height of the screen in pixels.
+pub const SCREEN_HEIGHT: usize = 32;
+const _: () = assert!(
+    SCREEN_WIDTH == Chip8::VIDEO_WIDTH as usize && SCREEN_HEIGHT == Chip8::VIDEO_HEIGHT as usize
+);
+
+// Turns an error or a panic from `f` into a status
+fn status(f: impl FnOnce() -> Result<(), Status>) -> Status {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => Status::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => Status::Panic,
+    }
+}
+
+// Runs `f` with the machine behind `machine`, NULL is an error
+unsafe fn with(
+    machine: *mut Machine,
+    f: impl FnOnce(&mut Machine) -> Result<(), Status>,
+) -> Status {
+    match machine.as_mut() {
+        Some(machine) => status(|| f(machine)),
+        None => Status::NullPointer,
+    }
+}
+
+// The same for calls that only look at the machine
+unsafe fn with_ref(
+    machine: *const Machine,
+    f: impl FnOnce(&Machine) -> Result<(), Status>,
+) -> Status {
+    match machine.as_ref() {
+        Some(machine) => status(|| f(machine)),
+        None => Status::NullPointer,
+    }
+}
+
+// A slice from C, empty ones are allowed to be NULL
+unsafe fn slice<'a>(data: *const u8, len: usize) -> Result<&'a [u8], Status> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(Status::NullPointer),
+        (false, _) => Ok(std::slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn slice_mut<'a>(data: *mut u8, len: usize) -> Result<&'a mut [u8], Status> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&mut []),
+        (true, _) => Err(Status::NullPointer),
+        (false, _) => Ok(std::slice::from_raw_parts_mut(data, len)),
+    }
+}
+
+/// A new machine with the font loaded and nothing else, or NULL if it couldn't be
+/// made.
+#[no_mangle]
+pub extern "C" fn p8_machine_new() -> *mut Machine {
+    catch_unwind(|| {
+        Box::into_raw(Box::new(Machine {
+            chip8: Chip8::new(),
+        }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Frees a machine from `p8_machine_new`, NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn p8_machine_free(machine: *mut Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// Copies `len` bytes of ROM to 0x200.
+#[no_mangle]
+pub unsafe extern "C" fn p8_load(machine: *mut Machine, rom: *const u8, len: usize) -> Status {
+    with(machine, |machine| {
+        Ok(machine.chip8.load_bytes(slice(rom, len)?)?)
+    })
+}
+
+/// CXKK takes its bytes from a generator started at `seed`, so runs repeat. Without
+/// this they're random.
+#[no_mangle]
+pub unsafe extern "C" fn p8_seed(machine: *mut Machine, seed: u32) -> Status {
+    with(machine, |machine| {
+        machine.chip8.set_random(Random::Seeded(seed));
+        Ok(())
+    })
+}
+
+/// Runs `cycles` instructions, stopping early if the ROM faults. The timers count
+/// down once per instruction as well.
+#[no_mangle]
+pub unsafe extern "C" fn p8_step(machine: *mut Machine, cycles: usize) -> Status {
+    with(machine, |machine| Ok(machine.chip8.run(cycles)?))
+}
+
+/// Counts the delay and sound timers down by `ticks`, stopping at 0.
+#[no_mangle]
+pub unsafe extern "C" fn p8_tick_timers(machine: *mut Machine, ticks: usize) -> Status {
+    with(machine, |machine| {
+        machine.chip8.tick_timers(ticks);
+        Ok(())
+    })
+}
+
+/// Presses or releases one key, 0 to 15.
+#[no_mangle]
+pub unsafe extern "C" fn p8_set_key(machine: *mut Machine, key: u8, pressed: bool) -> Status {
+    with(machine, |machine| {
+        if key >= 16 {
+            return Err(Status::BadKey);
+        }
+        let mut keys = machine.chip8.pressed_keys();
+        keys[key as usize] = pressed;
+        set_keys(&mut machine.chip8, keys);
+        Ok(())
+    })
+}
+
+/// Sets all 16 keys at once, bit n of `keys` held down for key n.
+#[no_mangle]
+pub unsafe extern "C" fn p8_set_keys(machine: *mut Machine, keys: u16) -> Status {
+    with(machine, |machine| {
+        set_keys(
+            &mut machine.chip8,
+            std::array::from_fn(|key| keys & 1 << key != 0),
+        );
+        Ok(())
+    })
+}
+
+fn set_keys(chip8: &mut Chip8, keys: [bool; 16]) {
+    for event in interpreter::key_events(chip8.pressed_keys(), keys) {
+        chip8.handle_key(event);
+    }
+}
+
+/// Copies the screen into `out`, which has to be `P8_SCREEN_WIDTH *
+/// P8_SCREEN_HEIGHT` bytes. One byte per pixel from the top left, a row at a time,
+/// 0xFF for lit and 0 for dark.
+#[no_mangle]
+pub unsafe extern "C" fn p8_framebuffer(
+    machine: *const Machine,
+    out: *mut u8,
+    len: usize,
+) -> Status {
+    with_ref(machine, |machine| {
+        let out = slice_mut(out, len)?;
+        let video = machine.chip8.get_video_buffer();
+        if out.len() != video.len() {
+            return Err(Status::BufferSize);
+        }
+        out.copy_from_slice(&video);
+        Ok(())
+    })
+}
+
+/// Fills in `out` with the registers and timers.
+#[no_mangle]
+pub unsafe extern "C" fn p8_registers(machine: *const Machine, out: *mut Registers) -> Status {
+    with_ref(machine, |machine| {
+        let out = out.as_mut().ok_or(Status::NullPointer)?;
+        let step = Step::of(&machine.chip8);
+        *out = Registers {
+            v: step.v,
+            i: step.i,
+            pc: step.pc,
+            sp: step.sp,
+            delay_timer: step.dt,
+            sound_timer: step.st,
+        };
+        Ok(())
+    })
+}
+
+/// Whether the buzzer should be sounding, i.e. the sound timer is above 0.
+#[no_mangle]
+pub unsafe extern "C" fn p8_sound_playing(machine: *const Machine) -> bool {
+    machine
+        .as_ref()
+        .is_some_and(|machine| machine.chip8.sound_playing())
+}
+
+/// Size of the buffer `p8_save_state` and `p8_load_state` take.
+#[no_mangle]
+pub extern "C" fn p8_state_size() -> usize {
+    Chip8::STATE_SIZE
+}
+
+/// Saves everything the ROM can see into `out`, `p8_state_size()` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn p8_save_state(
+    machine: *const Machine,
+    out: *mut u8,
+    len: usize,
+) -> Status {
+    with_ref(machine, |machine| {
+        Ok(machine.chip8.save_state(slice_mut(out, len)?)?)
+    })
+}
+
+/// Loads a state from `p8_save_state`. The machine is left as it was if it can't be
+/// loaded.
+#[no_mangle]
+pub unsafe extern "C" fn p8_load_state(
+    machine: *mut Machine,
+    state: *const u8,
+    len: usize,
+) -> Status {
+    with(machine, |machine| {
+        Ok(machine.chip8.load_state(slice(state, len)?)?)
+    })
+}
+
+/// What a status means, as a static string that doesn't need freeing. Takes an `int`
+/// so any value is safe to pass, ones that aren't a `P8Status` get "Unknown status".
+#[no_mangle]
+pub extern "C" fn p8_status_message(status: c_int) -> *const c_char {
+    let message = match Status::ALL
+        .into_iter()
+        .find(|known| *known as c_int == status)
+    {
+        Some(Status::Ok) => c"Ok",
+        Some(Status::NullPointer) => c"A pointer argument was NULL",
+        Some(Status::RomTooLarge) => c"ROM doesn't fit in memory",
+        Some(Status::StackOverflow) => c"Stack overflow",
+        Some(Status::StackUnderflow) => c"Stack underflow",
+        Some(Status::BadKey) => c"Keys are 0 to 15",
+        Some(Status::BufferSize) => c"Buffer is the wrong size",
+        Some(Status::BadState) => c"Not a save state this version can load",
+        Some(Status::Panic) => c"Interpreter panicked",
+        None => c"Unknown status",
+    };
+    message.as_ptr()
+}
@@ -0,0 +1,134 @@
+// Goes through the C API the way an embedder would, built and run by tests/c.rs.
+// Prints what failed and exits with 1 on the first thing that doesn't match.
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "paulson8.h"
+
+#define CHECK(condition)                                                           \
+    do {                                                                           \
+        if (!(condition)) {                                                        \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #condition);        \
+            exit(1);                                                               \
+        }                                                                          \
+    } while (0)
+
+#define CHECK_STATUS(call, expected)                                               \
+    do {                                                                           \
+        P8Status status = (call);                                                  \
+        if (status != (expected)) {                                                \
+            fprintf(stderr, "%s:%d: %s gave \"%s\"\n", __FILE__, __LINE__, #call,  \
+                    p8_status_message(status));                                    \
+            exit(1);                                                               \
+        }                                                                          \
+    } while (0)
+
+// Waits for a key, draws its digit in the top left and buzzes
+static const uint8_t ROM[] = {
+    0x61, 0xFF, // LD V1, FF
+    0xF0, 0x0A, // LD V0, K
+    0x00, 0xE0, // CLS
+    0xF0, 0x29, // LD F, V0
+    0xD2, 0x25, // DRW V2, V2, 5
+    0xF1, 0x18, // LD ST, V1
+    0x12, 0x02, // JP 202
+};
+
+static int lit(P8Machine *machine, int x, int y) {
+    uint8_t screen[P8_SCREEN_WIDTH * P8_SCREEN_HEIGHT];
+    CHECK_STATUS(p8_framebuffer(machine, screen, sizeof screen), P8_STATUS_OK);
+    return screen[x + y * P8_SCREEN_WIDTH] == 0xFF;
+}
+
+// Presses and lets go, FX0A finishes on the release
+static void tap(P8Machine *machine, uint8_t key) {
+    CHECK_STATUS(p8_set_key(machine, key, true), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 5), P8_STATUS_OK);
+    CHECK_STATUS(p8_set_key(machine, key, false), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 10), P8_STATUS_OK);
+}
+
+static void plays_the_rom(void) {
+    P8Machine *machine = p8_machine_new();
+    CHECK(machine != NULL);
+    CHECK_STATUS(p8_load(machine, ROM, sizeof ROM), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 10), P8_STATUS_OK);
+    CHECK(!lit(machine, 0, 0));
+
+    tap(machine, 5);
+    // The 5 has its fourth row lit on the right only
+    CHECK(lit(machine, 3, 3) && !lit(machine, 0, 3));
+
+    P8Registers registers;
+    CHECK_STATUS(p8_registers(machine, &registers), P8_STATUS_OK);
+    CHECK(registers.v[0] == 5);
+    CHECK(registers.v[1] == 0xFF);
+    // Back at FX0A waiting for the next key
+    CHECK(registers.pc == 0x202);
+    CHECK(registers.sound_timer > 0);
+    CHECK(p8_sound_playing(machine));
+
+    CHECK_STATUS(p8_tick_timers(machine, 255), P8_STATUS_OK);
+    CHECK(!p8_sound_playing(machine));
+
+    // Save, draw a 0 instead, then go back
+    size_t size = p8_state_size();
+    uint8_t *state = malloc(size);
+    CHECK(state != NULL);
+    CHECK_STATUS(p8_save_state(machine, state, size), P8_STATUS_OK);
+
+    CHECK_STATUS(p8_set_keys(machine, 1 << 0x0), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 5), P8_STATUS_OK);
+    CHECK_STATUS(p8_set_keys(machine, 0), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 5), P8_STATUS_OK);
+    CHECK(lit(machine, 0, 3));
+
+    CHECK_STATUS(p8_load_state(machine, state, size), P8_STATUS_OK);
+    CHECK(lit(machine, 3, 3) && !lit(machine, 0, 3));
+
+    memset(state, 0, size);
+    CHECK_STATUS(p8_load_state(machine, state, size), P8_STATUS_BAD_STATE);
+    CHECK_STATUS(p8_save_state(machine, state, size - 1), P8_STATUS_BUFFER_SIZE);
+    free(state);
+
+    p8_machine_free(machine);
+}
+
+static void errors(void) {
+    P8Machine *machine = p8_machine_new();
+    CHECK(machine != NULL);
+
+    uint8_t screen[10];
+    CHECK_STATUS(p8_framebuffer(machine, screen, sizeof screen), P8_STATUS_BUFFER_SIZE);
+    CHECK_STATUS(p8_framebuffer(machine, NULL, 2048), P8_STATUS_NULL_POINTER);
+    CHECK_STATUS(p8_set_key(machine, 16, true), P8_STATUS_BAD_KEY);
+    CHECK_STATUS(p8_registers(machine, NULL), P8_STATUS_NULL_POINTER);
+    CHECK_STATUS(p8_step(NULL, 1), P8_STATUS_NULL_POINTER);
+    CHECK(!p8_sound_playing(NULL));
+
+    static uint8_t too_large[4096];
+    CHECK_STATUS(p8_load(machine, too_large, sizeof too_large), P8_STATUS_ROM_TOO_LARGE);
+
+    // RET with nothing to return to
+    static const uint8_t underflow[] = {0x00, 0xEE};
+    CHECK_STATUS(p8_load(machine, underflow, sizeof underflow), P8_STATUS_OK);
+    CHECK_STATUS(p8_step(machine, 1), P8_STATUS_STACK_UNDERFLOW);
+
+    CHECK(strcmp(p8_status_message(P8_STATUS_OK), "Ok") == 0);
+    CHECK(strlen(p8_status_message(P8_STATUS_PANIC)) > 0);
+    CHECK(strcmp(p8_status_message(-1), "Unknown status") == 0);
+    CHECK(strcmp(p8_status_message(P8_STATUS_PANIC + 1), "Unknown status") == 0);
+
+    p8_machine_free(machine);
+    p8_machine_free(NULL);
+}
+
+int main(void) {
+    plays_the_rom();
+    errors();
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,51 @@
+// Compiles tests/api.c against include/paulson8.h and the built library with the
+// system C compiler (cc, or $CC) and runs it.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+// Cargo doesn't build cdylibs for integration tests, so this does. It goes next to
+// deps/ where this test is, target/debug/deps/c-<hash>
+fn build() -> PathBuf {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo
+        .args(["build", "--lib", "--quiet"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"));
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    assert!(cargo.status().unwrap().success(), "Library didn't build");
+
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn c_program() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let libraries = build();
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("api");
+
+    let cc = std::env::var("CC").unwrap_or("cc".to_string());
+    let status = Command::new(cc)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I"])
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/api.c"))
+        .arg("-o")
+        .arg(&program)
+        .arg("-L")
+        .arg(&libraries)
+        .arg(format!("-Wl,-rpath,{}", libraries.display()))
+        .arg("-lpaulson8")
+        .status()
+        .unwrap();
+    assert!(status.success(), "tests/api.c didn't compile");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "tests/api.c failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
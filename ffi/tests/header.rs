@@ -0,0 +1,29 @@
+// Keeps include/paulson8.h in step with src/lib.rs. After changing the API regenerate
+// it with
+//   BLESS=1 cargo test --test header
+
+const HEADER: &str = "include/paulson8.h";
+
+#[test]
+fn header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let config = cbindgen::Config::from_root_or_default(crate_dir);
+    let mut header = Vec::new();
+    cbindgen::generate_with_config(crate_dir, config)
+        .unwrap()
+        .write(&mut header);
+    let header = String::from_utf8(header).unwrap();
+
+    let path = std::path::Path::new(crate_dir).join(HEADER);
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::write(&path, &header).unwrap();
+        return;
+    }
+
+    let current = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        current == header,
+        "{} is out of date, regenerate it with BLESS=1 cargo test --test header",
+        HEADER
+    );
+}
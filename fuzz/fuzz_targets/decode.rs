@@ -0,0 +1,20 @@
+// Decoding, encoding and decoding again has to land on the same OP.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paulson_8::interpreter::opcodes::OP;
+
+fuzz_target!(|word: u16| {
+    let op = OP::from(word);
+    let encoded = u16::from(op);
+
+    assert_eq!(
+        OP::from(encoded),
+        op,
+        "{:#06X} encoded as {:#06X}",
+        word,
+        encoded
+    );
+    assert_eq!(u16::from(OP::from(encoded)), encoded);
+});
@@ -0,0 +1,45 @@
+// Runs arbitrary ROMs with arbitrary key presses for a bounded number of cycles.
+//
+// Input layout: the first byte is how many key bytes follow, each key byte is a key
+// in the low 7 bits and pressed when the high bit is set. Keys past F are passed on
+// as they are, `handle_key` has to cope with them. Whatever is left is the ROM, so
+// `\0` followed by any .ch8 file is a valid input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paulson_8::interpreter::{Chip8, KeyEvent};
+
+const CYCLES: usize = 20_000;
+// Roughly three frames at the GUI's speed between key changes
+const CYCLES_PER_KEY: usize = 64;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&count, rest)) = data.split_first() else {
+        return;
+    };
+    let (keys, rom) = rest.split_at((count as usize).min(rest.len()));
+
+    let mut chip8 = Chip8::new();
+    if chip8.load_bytes(rom).is_err() {
+        return;
+    }
+
+    let mut keys = keys.iter().map(|byte| match byte & 0x80 {
+        0 => KeyEvent::Released(byte & 0x7F),
+        _ => KeyEvent::Pressed(byte & 0x7F),
+    });
+
+    for cycle in 0..CYCLES {
+        if cycle % CYCLES_PER_KEY == 0 {
+            if let Some(event) = keys.next() {
+                chip8.handle_key(event);
+            }
+        }
+
+        // Stack faults are fine, panics are not
+        if chip8.cycle().is_err() {
+            break;
+        }
+    }
+});
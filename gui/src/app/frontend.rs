@@ -0,0 +1,102 @@
+// The GUI's side of `frontend`: the screen is drawn with macroquad through the
+// filter and palette picked in the menu, keys come from the keyboard and the
+// on-screen keypad, and the pause button stops the clock.
+
+use super::keypad;
+use super::ui::UserInterface;
+use macroquad::prelude::*;
+use paulson_8::frontend::{Clock, Display, Input};
+use paulson_8::upscale::{self, Palette, Pipeline};
+use std::{cell::Cell, rc::Rc};
+
+pub struct Screen {
+    texture: Option<Texture2D>,
+    // Set from the menu every frame
+    pub pipeline: Pipeline,
+    pub palette: Palette,
+    // Width on the window, the height follows from the aspect ratio
+    pub width: f32,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen {
+            texture: None,
+            pipeline: Pipeline::new(),
+            palette: Palette::PRESETS[0].1,
+            width: 0.0,
+        }
+    }
+}
+
+impl Display for Screen {
+    fn present(&mut self, video: &[u8], width: usize, height: usize) {
+        let image = self.pipeline.render(video, width, height, self.palette);
+        let texture = upload_screen(&mut self.texture, &image);
+        let pixel_size = self.width / width as f32;
+
+        draw_texture_ex(
+            texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(pixel_size * width as f32, pixel_size * height as f32)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+// Reuses the texture between frames, it only gets recreated when the filter changes size
+fn upload_screen(screen: &mut Option<Texture2D>, image: &upscale::Image) -> Texture2D {
+    let bytes = image.to_rgba8();
+
+    match *screen {
+        Some(texture)
+            if texture.width() as usize == image.width
+                && texture.height() as usize == image.height =>
+        {
+            texture.update(&Image {
+                bytes,
+                width: image.width as u16,
+                height: image.height as u16,
+            });
+            texture
+        }
+        _ => {
+            if let Some(texture) = screen.take() {
+                texture.delete();
+            }
+            let texture = Texture2D::from_rgba8(image.width as u16, image.height as u16, &bytes);
+            texture.set_filter(FilterMode::Nearest);
+            *screen = Some(texture);
+            texture
+        }
+    }
+}
+
+// Bound keys on the keyboard, or buttons held on the keypad in the menu
+impl Input for UserInterface {
+    fn pressed(&mut self) -> [bool; 16] {
+        let keyboard = self.keyboard_pressed();
+        self.set_keyboard(keyboard);
+        keypad::combine(keyboard, self.keypad_held())
+    }
+}
+
+// A fixed number of instructions per frame while the menu says it's running
+pub struct FrameClock {
+    pub running: Rc<Cell<bool>>,
+    pub cycles: usize,
+}
+
+impl Clock for FrameClock {
+    fn cycles(&mut self) -> usize {
+        if self.running.get() {
+            self.cycles
+        } else {
+            0
+        }
+    }
+}
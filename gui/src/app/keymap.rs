@@ -0,0 +1,249 @@
+// Host key to chip-8 key bindings, saved per ROM so every game can have its own layout.
+//
+// Profiles are plain text, one chip-8 key per line:
+//   5 = W, Up
+//   8 = S, Down
+
+use std::{fmt, io, path::PathBuf, str::FromStr};
+
+use macroquad::prelude::KeyCode;
+
+// The layout everyone knows, chip-8 keys 0 through F
+pub const DEFAULT_KEYS: [KeyCode; 16] = {
+    use KeyCode::*;
+    [X, Key1, Key2, Key3, Q, W, E, A, S, D, Z, C, Key4, R, F, V]
+};
+
+// Escape isn't here on purpose, it cancels binding
+#[rustfmt::skip]
+const BINDABLE: [KeyCode; 119] = {
+    use KeyCode::*;
+    [
+        Space, Apostrophe, Comma, Minus, Period, Slash, Key0, Key1, Key2, Key3, Key4, Key5,
+        Key6, Key7, Key8, Key9, Semicolon, Equal, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O,
+        P, Q, R, S, T, U, V, W, X, Y, Z, LeftBracket, Backslash, RightBracket, GraveAccent,
+        World1, World2, Enter, Tab, Backspace, Insert, Delete, Right, Left, Down, Up, PageUp,
+        PageDown, Home, End, CapsLock, ScrollLock, NumLock, PrintScreen, Pause, F1, F2, F3, F4,
+        F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19, F20, F21, F22,
+        F23, F24, F25, Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9, KpDecimal, KpDivide,
+        KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual, LeftShift, LeftControl, LeftAlt,
+        LeftSuper, RightShift, RightControl, RightAlt, RightSuper, Menu,
+    ]
+};
+
+pub fn is_bindable(key: KeyCode) -> bool {
+    BINDABLE.contains(&key)
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    BINDABLE
+        .iter()
+        .copied()
+        .find(|key| key_name(*key).eq_ignore_ascii_case(name))
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Keymap {
+    keys: [Vec<KeyCode>; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            keys: DEFAULT_KEYS.map(|key| vec![key]),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn empty() -> Self {
+        Keymap {
+            keys: Default::default(),
+        }
+    }
+
+    pub fn bindings(&self, key: u8) -> &[KeyCode] {
+        &self.keys[key as usize]
+    }
+
+    // A host key only ever drives one chip-8 key, binding it again moves it
+    pub fn bind(&mut self, key: u8, host: KeyCode) {
+        for bindings in self.keys.iter_mut() {
+            bindings.retain(|bound| *bound != host);
+        }
+        self.keys[key as usize].push(host);
+    }
+
+    pub fn clear(&mut self, key: u8) {
+        self.keys[key as usize].clear();
+    }
+
+    pub fn pressed_with<F: Fn(KeyCode) -> bool>(&self, is_down: F) -> [bool; 16] {
+        let mut pressed = [false; 16];
+        for (state, bindings) in pressed.iter_mut().zip(self.keys.iter()) {
+            *state = bindings.iter().any(|key| is_down(*key));
+        }
+        pressed
+    }
+
+    pub fn load(path: &std::path::Path) -> io::Result<Keymap> {
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, bindings) in self.keys.iter().enumerate() {
+            let names = bindings
+                .iter()
+                .map(|key| key_name(*key))
+                .collect::<Vec<String>>();
+            writeln!(f, "{:X} = {}", key, names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    // Keys missing from the profile are left unbound, '#' starts a comment
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keymap = Keymap::empty();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, hosts) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Line {}: expected 'key = host keys'", number + 1))?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("Line {}: {} is not a chip-8 key", number + 1, key))?;
+
+            for host in hosts.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+                let host = key_from_name(host)
+                    .ok_or_else(|| format!("Line {}: unknown key {}", number + 1, host))?;
+                keymap.bind(key, host);
+            }
+        }
+
+        Ok(keymap)
+    }
+}
+
+// FNV-1a, it only needs to tell ROMs apart not be secure
+pub fn rom_hash(rom: &[u8]) -> String {
+    let hash = rom.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn config_dir() -> PathBuf {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    dir.join("paulson-8")
+}
+
+pub fn profile_path(rom_hash: &str) -> PathBuf {
+    config_dir()
+        .join("keymaps")
+        .join(format!("{}.keys", rom_hash))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_layout() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.bindings(0x0), &[KeyCode::X]);
+        assert_eq!(keymap.bindings(0xC), &[KeyCode::Key4]);
+        assert_eq!(keymap.bindings(0xF), &[KeyCode::V]);
+    }
+
+    #[test]
+    fn bind_moves_host_key() {
+        let mut keymap = Keymap::default();
+        keymap.bind(0x8, KeyCode::Down);
+        keymap.bind(0x8, KeyCode::Key2);
+
+        assert_eq!(
+            keymap.bindings(0x8),
+            &[KeyCode::S, KeyCode::Down, KeyCode::Key2]
+        );
+        assert!(keymap.bindings(0x2).is_empty());
+
+        keymap.clear(0x8);
+        assert!(keymap.bindings(0x8).is_empty());
+    }
+
+    #[test]
+    fn pressed_any_binding() {
+        let mut keymap = Keymap::default();
+        keymap.bind(0x5, KeyCode::Up);
+
+        let pressed = keymap.pressed_with(|key| key == KeyCode::Up);
+        assert_eq!(pressed.iter().filter(|p| **p).count(), 1);
+        assert!(pressed[0x5]);
+    }
+
+    #[test]
+    fn profile_round_trip() {
+        let mut keymap = Keymap::default();
+        keymap.bind(0x2, KeyCode::Up);
+        keymap.bind(0x8, KeyCode::Down);
+        keymap.clear(0x0);
+
+        let text = keymap.to_string();
+        assert!(text.contains("2 = Key2, Up\n"));
+        assert!(text.contains("0 = \n"));
+        assert_eq!(text.parse::<Keymap>(), Ok(keymap));
+    }
+
+    #[test]
+    fn parse_profile() {
+        let keymap = "
+            # Movement on the arrows
+            4 = left, Kp4
+            6 = Right
+        "
+        .parse::<Keymap>()
+        .unwrap();
+        assert_eq!(keymap.bindings(0x4), &[KeyCode::Left, KeyCode::Kp4]);
+        assert_eq!(keymap.bindings(0x6), &[KeyCode::Right]);
+        assert!(keymap.bindings(0x0).is_empty());
+
+        assert!("G = A".parse::<Keymap>().is_err());
+        assert!("1 = Escape".parse::<Keymap>().is_err());
+        assert!("1 A".parse::<Keymap>().is_err());
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(rom_hash(&[]), "cbf29ce484222325");
+        assert_ne!(rom_hash(&[0x00, 0xE0]), rom_hash(&[0xE0, 0x00]));
+    }
+}
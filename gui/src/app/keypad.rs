@@ -0,0 +1,88 @@
+// On-screen COSMAC VIP style hex keypad. Buttons can be clicked or held to press
+// chip-8 keys and also light up for keys held down on the physical keyboard.
+
+use egui::{Button, Color32, Grid, Ui};
+
+// Key values laid out the way they are on the VIP
+pub const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+const BUTTON_SIZE: f32 = 32.0;
+const LIT: Color32 = Color32::from_rgb(0xC0, 0x80, 0x20);
+
+#[derive(Default)]
+pub struct HexKeypad {
+    held: [bool; 16],
+}
+
+impl HexKeypad {
+    pub fn new() -> Self {
+        HexKeypad { held: [false; 16] }
+    }
+
+    // Keys pressed with the mouse or a touch screen as of the last `show`
+    pub fn held(&self) -> [bool; 16] {
+        self.held
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, keyboard: [bool; 16]) {
+        Grid::new("hex keypad").spacing([4.0, 4.0]).show(ui, |ui| {
+            for row in LAYOUT {
+                for key in row {
+                    let index = key as usize;
+                    let mut button = Button::new(format!("{:X}", key));
+                    if keyboard[index] || self.held[index] {
+                        button = button.fill(LIT);
+                    }
+
+                    let response = ui.add_sized([BUTTON_SIZE, BUTTON_SIZE], button);
+                    // A quick click can start and end within a frame, count it as held for one
+                    self.held[index] = response.is_pointer_button_down_on() || response.clicked();
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
+
+// Combines the keyboard and the on-screen keypad, either one holding a key is enough
+pub fn combine(keyboard: [bool; 16], keypad: [bool; 16]) -> [bool; 16] {
+    let mut pressed = [false; 16];
+    for (i, state) in pressed.iter_mut().enumerate() {
+        *state = keyboard[i] || keypad[i];
+    }
+    pressed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_has_every_key_once() {
+        let mut seen = [false; 16];
+        for key in LAYOUT.iter().flatten() {
+            assert!(!seen[*key as usize]);
+            seen[*key as usize] = true;
+        }
+        assert_eq!(seen, [true; 16]);
+    }
+
+    #[test]
+    fn combine_keyboard_and_keypad() {
+        let mut keyboard = [false; 16];
+        let mut keypad = [false; 16];
+        keyboard[0x1] = true;
+        keypad[0xF] = true;
+        keypad[0x1] = true;
+
+        let pressed = combine(keyboard, keypad);
+        assert!(pressed[0x1]);
+        assert!(pressed[0xF]);
+        assert_eq!(pressed.iter().filter(|p| **p).count(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+pub mod frontend;
+pub mod keymap;
+pub mod keypad;
+pub mod ui;
+
+use frontend::{FrameClock, Screen};
+use keymap::Keymap;
+use log::{error, info};
+use macroquad::prelude::*;
+use paulson_8::capture::{self, Recorder};
+use paulson_8::frontend::{frame, NullAudio, DEFAULT_CYCLES_PER_FRAME};
+use paulson_8::interpreter::Chip8;
+use paulson_8::logger;
+use paulson_8::script::{Script, ScriptError};
+use paulson_8::upscale;
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::Receiver,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use ui::ScriptRequest;
+
+// Screenshots and recordings are upscaled to at least this
+const CAPTURE_SCALE: usize = 8;
+
+struct State {
+    menu: ui::UserInterface,
+    interpreter: Chip8,
+    screen: Screen,
+    clock: FrameClock,
+    // Nothing to play sound through yet
+    audio: NullAudio,
+    recorder: Option<Recorder>,
+    // Seconds of real time not yet covered by a recorded frame
+    record_time: f32,
+    script: Option<Script>,
+}
+
+impl State {
+    fn new(channel: Receiver<logger::Log>, rom: Option<PathBuf>) -> Self {
+        // This is bad but I want to get POC going
+        let running = Rc::new(Cell::new(false));
+        let ui_running = Rc::clone(&running);
+        let mut menu = ui::UserInterface::new(channel, ui_running);
+
+        let mut chip8 = Chip8::new();
+        if let Some(rom) = rom {
+            match chip8.load(&rom) {
+                Ok(()) => {
+                    let (keymap, profile) = load_profile(&rom);
+                    menu.set_keymap(keymap, profile);
+                }
+                Err(e) => error!("Could not load {}: {}", rom.display(), e),
+            }
+        }
+
+        Self {
+            menu,
+            interpreter: chip8,
+            screen: Screen::default(),
+            // This should be adjustable
+            clock: FrameClock {
+                running,
+                cycles: DEFAULT_CYCLES_PER_FRAME,
+            },
+            audio: NullAudio::default(),
+            recorder: None,
+            record_time: 0.0,
+            script: None,
+        }
+    }
+}
+
+// Every ROM gets its own key profile, keyed by a hash of its contents
+fn load_profile(rom: &Path) -> (Keymap, Option<PathBuf>) {
+    let profile = match std::fs::read(rom) {
+        Ok(bytes) => keymap::profile_path(&keymap::rom_hash(&bytes)),
+        Err(_) => return (Keymap::default(), None),
+    };
+
+    let keymap = match Keymap::load(&profile) {
+        Ok(keymap) => {
+            info!("Loaded key profile {}", profile.display());
+            keymap
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Keymap::default(),
+        Err(e) => {
+            error!("Could not read key profile {}: {}", profile.display(), e);
+            Keymap::default()
+        }
+    };
+    (keymap, Some(profile))
+}
+
+pub async fn run(logs: Receiver<logger::Log>, rom: Option<PathBuf>) {
+    let mut state = State::new(logs, rom);
+    loop {
+        if is_key_pressed(KeyCode::F12) {
+            state.menu.request_screenshot();
+        }
+        if is_key_pressed(KeyCode::F9) {
+            state.menu.toggle_recording();
+        }
+        match state.menu.take_script_request() {
+            Some(ScriptRequest::Load(path)) => load_script(&mut state, &path),
+            Some(ScriptRequest::Unload) => {
+                state.script = None;
+                state.menu.set_script_loaded(false);
+                info!("Unloaded the script");
+            }
+            None => {}
+        }
+
+        clear_background(WHITE);
+
+        // Render egui
+        egui_macroquad::ui(|egui_ctx| {
+            egui::SidePanel::right("right panel")
+                .min_width(screen_width() * 0.55)
+                .resizable(false)
+                .show(egui_ctx, |ui| {
+                    state.menu.side_panel(ui);
+                });
+        });
+
+        // Run a frame and draw the Chip-8 screen
+        state.screen.pipeline = state.menu.pipeline();
+        state.screen.palette = state.menu.palette();
+        state.screen.width = screen_width() * 0.4;
+        let result = match state.script.as_mut() {
+            Some(script) => script.frame(
+                &mut state.interpreter,
+                &mut state.menu,
+                &mut state.clock,
+                &mut state.audio,
+                &mut state.screen,
+            ),
+            None => frame(
+                &mut state.interpreter,
+                &mut state.menu,
+                &mut state.clock,
+                &mut state.audio,
+                &mut state.screen,
+            )
+            .map_err(ScriptError::Fault),
+        };
+        match result {
+            Ok(()) => {}
+            // Already logged by the interpreter, pause so the state can be looked at
+            Err(ScriptError::Fault(_)) => state.clock.running.set(false),
+            Err(e) => {
+                error!("Script failed: {}", e);
+                state.script = None;
+                state.menu.set_script_loaded(false);
+                state.clock.running.set(false);
+            }
+        }
+        if let Some(script) = state.script.as_mut() {
+            if script.take_stop_request() {
+                info!("Paused by the script");
+                state.clock.running.set(false);
+            }
+        }
+        capture_screen(&mut state);
+
+        egui_macroquad::draw();
+
+        next_frame().await;
+    }
+}
+
+// Replaces any script already running, its hooks and held keys go with it
+fn load_script(state: &mut State, path: &Path) {
+    let script = match Script::load(path) {
+        Ok(script) => script,
+        Err(e) => {
+            error!("Could not load {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let mut script = script
+        .cycles_per_frame(state.clock.cycles)
+        .capture(capture_pipeline(state), state.menu.palette());
+    script.on_print(|text| info!("{}", text));
+
+    state.script = None;
+    match script.start(&mut state.interpreter) {
+        Ok(()) => {
+            info!("Loaded script {}", path.display());
+            state.script = Some(script);
+        }
+        Err(e) => error!("Script failed: {}", e),
+    }
+    state.menu.set_script_loaded(state.script.is_some());
+}
+
+// Whatever filter is on screen, topped up with nearest neighbour so the files aren't tiny
+fn capture_pipeline(state: &State) -> upscale::Pipeline {
+    let pipeline = state.menu.pipeline();
+    let factor = (CAPTURE_SCALE / pipeline.scale()).max(1);
+    pipeline.then(upscale::Filter::Nearest(factor))
+}
+
+fn capture_screen(state: &mut State) {
+    let (width, height) = (Chip8::VIDEO_WIDTH as usize, Chip8::VIDEO_HEIGHT as usize);
+    let buffer = state.interpreter.get_video_buffer();
+
+    let pipeline = capture_pipeline(state);
+
+    if state.menu.take_screenshot_request() {
+        let path = capture_path("png");
+        let image = pipeline.render(&buffer, width, height, state.menu.palette());
+        match capture::save_png(&path, &image) {
+            Ok(()) => info!("Saved screenshot to {}", path.display()),
+            Err(e) => error!("Could not save screenshot: {}", e),
+        }
+    }
+
+    // Recordings play back at a fixed rate, so whatever the window's frame rate is the
+    // screen gets sampled every 1/FPS seconds of real time
+    match (state.menu.recording(), state.recorder.as_mut()) {
+        (true, Some(recorder)) => {
+            state.record_time += get_frame_time();
+            let interval = 1.0 / capture::FPS as f32;
+            while state.record_time >= interval {
+                recorder.push(&buffer);
+                state.record_time -= interval;
+            }
+        }
+        (true, None) => {
+            info!("Started recording");
+            let mut recorder = Recorder::new(width, height);
+            recorder.push(&buffer);
+            state.recorder = Some(recorder);
+            state.record_time = 0.0;
+        }
+        (false, Some(_)) => {
+            let recorder = state.recorder.take().unwrap();
+            let path = capture_path("gif");
+            match recorder.save(&path, &pipeline, state.menu.palette()) {
+                Ok(()) => info!("Saved {} frames to {}", recorder.len(), path.display()),
+                Err(e) => error!("Could not save recording: {}", e),
+            }
+        }
+        (false, None) => {}
+    }
+}
+
+fn capture_path(extension: &str) -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    PathBuf::from(format!("paulson-8-{}.{}", time, extension))
+}
@@ -0,0 +1,348 @@
+use std::{
+    cell::Cell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{mpsc::Receiver, Arc},
+};
+
+use egui::{
+    text::LayoutJob, CollapsingHeader, Color32, ComboBox, Galley, Grid, ScrollArea, TextEdit,
+    TextFormat, Ui,
+};
+use log::{error, info, Level};
+use macroquad::prelude::{get_fps, get_last_key_pressed, is_key_down, KeyCode};
+
+use super::keymap::{self, Keymap};
+use super::keypad::HexKeypad;
+use paulson_8::logger;
+use paulson_8::upscale::{Filter, Palette, Pipeline};
+
+const FILTERS: [Filter; 4] = [
+    Filter::Nearest(1),
+    Filter::Scale2x,
+    Filter::Scale3x,
+    Filter::Smooth,
+];
+
+pub enum ScriptRequest {
+    Load(PathBuf),
+    Unload,
+}
+
+pub struct UserInterface {
+    channel: Receiver<logger::Log>,
+    max_log_level: usize,
+    logs: Vec<LogText>,
+    running: Rc<Cell<bool>>,
+    filter: usize,
+    palette: usize,
+    screenshot_requested: bool,
+    recording: bool,
+    keymap: Keymap,
+    profile: Option<PathBuf>,
+    // Chip-8 key waiting for a host key to be pressed
+    binding: Option<u8>,
+    // Host key that was just bound, kept from the machine until it's let go
+    just_bound: Option<KeyCode>,
+    keypad: HexKeypad,
+    // Chip-8 keys held on the physical keyboard, lit up on the keypad
+    keyboard: [bool; 16],
+    script_path: String,
+    script_request: Option<ScriptRequest>,
+    script_loaded: bool,
+}
+
+impl UserInterface {
+    pub fn new(channel: Receiver<logger::Log>, running: Rc<Cell<bool>>) -> Self {
+        UserInterface {
+            channel,
+            max_log_level: 0,
+            logs: Vec::new(),
+            running,
+            filter: 0,
+            palette: 0,
+            screenshot_requested: false,
+            recording: false,
+            keymap: Keymap::default(),
+            profile: None,
+            binding: None,
+            just_bound: None,
+            keypad: HexKeypad::new(),
+            keyboard: [false; 16],
+            script_path: String::new(),
+            script_request: None,
+            script_loaded: false,
+        }
+    }
+
+    pub fn set_keyboard(&mut self, keyboard: [bool; 16]) {
+        self.keyboard = keyboard;
+    }
+
+    // Chip-8 keys held on the keyboard. None while a key is being bound, the key
+    // pressed then is for the binding and not the machine
+    pub fn keyboard_pressed(&mut self) -> [bool; 16] {
+        if self.binding.is_some() {
+            return [false; 16];
+        }
+        if self.just_bound.is_some_and(|host| !is_key_down(host)) {
+            self.just_bound = None;
+        }
+        let just_bound = self.just_bound;
+        self.keymap
+            .pressed_with(|host| Some(host) != just_bound && is_key_down(host))
+    }
+
+    pub fn keypad_held(&self) -> [bool; 16] {
+        self.keypad.held()
+    }
+
+    // Changes to the keymap get written back to the profile
+    pub fn set_keymap(&mut self, keymap: Keymap, profile: Option<PathBuf>) {
+        self.keymap = keymap;
+        self.profile = profile;
+    }
+
+    fn save_keymap(&self) {
+        if let Some(path) = &self.profile {
+            match self.keymap.save(path) {
+                Ok(()) => info!("Saved key profile to {}", path.display()),
+                Err(e) => error!("Could not save key profile: {}", e),
+            }
+        }
+    }
+
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline::new().then(FILTERS[self.filter])
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::PRESETS[self.palette].1
+    }
+
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    // Returns true once per request
+    pub fn take_screenshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot_requested)
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn toggle_recording(&mut self) {
+        self.recording = !self.recording;
+    }
+
+    // Returns a request once, loading a script has to happen where the machine is
+    pub fn take_script_request(&mut self) -> Option<ScriptRequest> {
+        self.script_request.take()
+    }
+
+    pub fn set_script_loaded(&mut self, loaded: bool) {
+        self.script_loaded = loaded;
+    }
+
+    fn recieve_logs(&self) -> Vec<LogLayout> {
+        let mut vec = Vec::new();
+        for log in self.channel.try_iter() {
+            let mut job = LayoutJob::default();
+
+            let format = TextFormat {
+                color: match log.level {
+                    Level::Trace => Color32::GRAY,
+                    Level::Debug => Color32::LIGHT_GRAY,
+                    Level::Info => Color32::LIGHT_GREEN,
+                    Level::Warn => Color32::YELLOW,
+                    Level::Error => Color32::RED,
+                },
+                ..Default::default()
+            };
+
+            job.append("[", 0.0, TextFormat::default());
+            job.append(&format!("{}", log.level), 0.0, format.clone());
+            job.append("] - ", 0.0, TextFormat::default());
+
+            job.append(&log.text, 0.0, format);
+
+            vec.push(LogLayout {
+                level: log.level,
+                job,
+            });
+        }
+        vec
+    }
+
+    pub fn side_panel(&mut self, ui: &mut Ui) {
+        ui.heading(format!("FPS {}", get_fps()));
+        let running = self.running.get();
+        let btn_text = match running {
+            true => "Stop",
+            false => "Start",
+        };
+        if ui.button(btn_text).clicked() {
+            self.running.set(!running);
+        }
+        if ui.button("Clear").clicked() {
+            self.logs.clear();
+        }
+
+        ComboBox::from_label("Filter").show_index(ui, &mut self.filter, FILTERS.len(), |i| {
+            FILTERS[i].to_string()
+        });
+        ComboBox::from_label("Palette").show_index(
+            ui,
+            &mut self.palette,
+            Palette::PRESETS.len(),
+            |i| Palette::PRESETS[i].0.to_string(),
+        );
+
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot (F12)").clicked() {
+                self.request_screenshot();
+            }
+            let record_text = match self.recording {
+                true => "Stop Recording (F9)",
+                false => "Record (F9)",
+            };
+            if ui.button(record_text).clicked() {
+                self.toggle_recording();
+            }
+        });
+
+        CollapsingHeader::new("Keypad")
+            .default_open(true)
+            .show(ui, |ui| self.keypad.show(ui, self.keyboard));
+        self.key_bindings(ui);
+        self.script(ui);
+
+        ui.separator();
+        let log_levels = [
+            Level::Trace,
+            Level::Debug,
+            Level::Info,
+            Level::Warn,
+            Level::Error,
+        ];
+        ComboBox::from_label("Max Level").show_index(
+            ui,
+            &mut self.max_log_level,
+            log_levels.len(),
+            |i| log_levels[i].to_string(),
+        );
+
+        let unbaked_logs = self.recieve_logs();
+
+        // Bake logs into properly layout text
+        self.logs
+            .extend(unbaked_logs.into_iter().map(|log_layout| LogText {
+                level: log_layout.level,
+                galley: ui.fonts().layout_job(log_layout.job),
+            }));
+
+        ScrollArea::vertical().show(ui, |ui| {
+            for log in self
+                .logs
+                .iter()
+                .filter(|log| log.level <= log_levels[self.max_log_level])
+            {
+                ui.painter().galley(
+                    ui.next_widget_position() + egui::Vec2::new(20.0f32, 0.0f32),
+                    Arc::clone(&log.galley),
+                );
+                // Create dummy to advance next widget position
+                ui.label("");
+            }
+        });
+    }
+
+    fn key_bindings(&mut self, ui: &mut Ui) {
+        if let Some(key) = self.binding {
+            match get_last_key_pressed() {
+                Some(KeyCode::Escape) => self.binding = None,
+                Some(host) if keymap::is_bindable(host) => {
+                    self.keymap.bind(key, host);
+                    self.binding = None;
+                    self.just_bound = Some(host);
+                    self.save_keymap();
+                }
+                _ => {}
+            }
+        }
+
+        CollapsingHeader::new("Key Bindings").show(ui, |ui| {
+            let mut changed = false;
+
+            Grid::new("key bindings").striped(true).show(ui, |ui| {
+                for key in 0..16u8 {
+                    ui.label(format!("{:X}", key));
+
+                    let names = self
+                        .keymap
+                        .bindings(key)
+                        .iter()
+                        .map(|host| keymap::key_name(*host))
+                        .collect::<Vec<String>>();
+                    ui.label(match names.is_empty() {
+                        true => "-".to_owned(),
+                        false => names.join(", "),
+                    });
+
+                    let bind_text = match self.binding == Some(key) {
+                        true => "Press a key (Esc cancels)",
+                        false => "Add",
+                    };
+                    if ui.button(bind_text).clicked() {
+                        self.binding = Some(key);
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.keymap.clear(key);
+                        changed = true;
+                    }
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Reset to default").clicked() {
+                self.keymap = Keymap::default();
+                changed = true;
+            }
+
+            if changed {
+                self.save_keymap();
+            }
+        });
+    }
+
+    fn script(&mut self, ui: &mut Ui) {
+        CollapsingHeader::new("Script").show(ui, |ui| {
+            ui.add(TextEdit::singleline(&mut self.script_path).hint_text("path/to/script.rhai"));
+            ui.horizontal(|ui| {
+                let load_text = match self.script_loaded {
+                    true => "Reload",
+                    false => "Load",
+                };
+                if ui.button(load_text).clicked() && !self.script_path.is_empty() {
+                    self.script_request =
+                        Some(ScriptRequest::Load(self.script_path.clone().into()));
+                }
+                if self.script_loaded && ui.button("Unload").clicked() {
+                    self.script_request = Some(ScriptRequest::Unload);
+                }
+            });
+        });
+    }
+}
+
+struct LogLayout {
+    level: log::Level,
+    job: LayoutJob,
+}
+
+struct LogText {
+    level: log::Level,
+    galley: Arc<Galley>,
+}
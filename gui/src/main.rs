@@ -1,4 +1,6 @@
-use paulson_8::app::{self, logger};
+mod app;
+
+use paulson_8::logger;
 
 use macroquad::prelude::*;
 
@@ -15,5 +17,6 @@ fn window_conf() -> Conf {
 async fn main() {
     // Make this based on env variable
     let log = logger::init(log::LevelFilter::Trace).expect("Logger faild to initalize");
-    app::run(log).await;
+    let rom = std::env::args_os().nth(1).map(std::path::PathBuf::from);
+    app::run(log, rom).await;
 }
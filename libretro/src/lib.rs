@@ -0,0 +1,414 @@
+// A libretro core, so RetroArch and other libretro frontends can run ROMs:
+//
+//   cargo build --release
+//   retroarch -L target/release/libpaulson8_libretro.so rom.ch8
+//
+// libretro cores are a set of C functions with global state, the frontend hands over
+// its callbacks then calls `retro_run` once a frame. The machine itself is in
+// `machine`, this is the C side of it. tests/frontend.rs loads the built library and
+// plays the part of a frontend.
+//
+// The joypad covers all 16 keys, the d-pad and A are 2 4 6 8 and 5 which is what
+// most games move and fire with, see `BUTTONS` for the rest.
+
+#![allow(clippy::missing_safety_doc)]
+
+mod api;
+mod machine;
+
+use std::ffi::{c_char, c_uint, c_void, CStr, CString};
+use std::sync::Mutex;
+
+use api::*;
+use machine::{Core, Options, FPS, HEIGHT, SAMPLES_PER_FRAME, SAMPLE_RATE, WIDTH};
+use paulson_8::interpreter::Chip8;
+
+// Chip-8 key for each joypad button, with what the frontend shows for it
+const BUTTONS: [(c_uint, u8, &CStr); 16] = [
+    (DEVICE_ID_JOYPAD_UP, 0x2, c"2 (up)"),
+    (DEVICE_ID_JOYPAD_LEFT, 0x4, c"4 (left)"),
+    (DEVICE_ID_JOYPAD_RIGHT, 0x6, c"6 (right)"),
+    (DEVICE_ID_JOYPAD_DOWN, 0x8, c"8 (down)"),
+    (DEVICE_ID_JOYPAD_A, 0x5, c"5"),
+    (DEVICE_ID_JOYPAD_B, 0x0, c"0"),
+    (DEVICE_ID_JOYPAD_X, 0x1, c"1"),
+    (DEVICE_ID_JOYPAD_Y, 0x3, c"3"),
+    (DEVICE_ID_JOYPAD_L, 0x7, c"7"),
+    (DEVICE_ID_JOYPAD_R, 0x9, c"9"),
+    (DEVICE_ID_JOYPAD_START, 0xA, c"A"),
+    (DEVICE_ID_JOYPAD_SELECT, 0xB, c"B"),
+    (DEVICE_ID_JOYPAD_L2, 0xC, c"C"),
+    (DEVICE_ID_JOYPAD_R2, 0xD, c"D"),
+    (DEVICE_ID_JOYPAD_L3, 0xE, c"E"),
+    (DEVICE_ID_JOYPAD_R3, 0xF, c"F"),
+];
+
+// Core options, the first value is the default
+const WAIT_FOR_RELEASE: &CStr = c"paulson8_wait_for_release";
+const CYCLES_PER_FRAME: &CStr = c"paulson8_cycles_per_frame";
+const VARIABLES: [(&CStr, &CStr); 2] = [
+    (
+        WAIT_FOR_RELEASE,
+        c"FX0A waits for the key to be released; on|off",
+    ),
+    (
+        CYCLES_PER_FRAME,
+        c"Instructions per frame; 20|10|15|30|50|100|200|500|1000",
+    ),
+];
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+    log: Option<LogPrintfFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    log: None,
+});
+// The loaded game
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+fn core() -> std::sync::MutexGuard<'static, Option<Core>> {
+    CORE.lock().unwrap()
+}
+
+unsafe fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match callbacks().environment {
+        Some(environment) => environment(cmd, data),
+        None => false,
+    }
+}
+
+// Through the frontend's log when it has one, stderr otherwise. Like the other
+// callbacks it must not be called with `CORE` locked, the frontend may call back in
+fn log_error(message: impl std::fmt::Display) {
+    match callbacks().log {
+        Some(log) => {
+            let message = CString::new(message.to_string()).unwrap_or_default();
+            unsafe { log(LOG_ERROR, c"[paulson-8] %s\n".as_ptr(), message.as_ptr()) };
+        }
+        None => eprintln!("[paulson-8] {}", message),
+    }
+}
+
+// The frontend's value for a core option
+unsafe fn variable(key: &CStr) -> Option<&'static CStr> {
+    let mut variable = Variable {
+        key: key.as_ptr(),
+        value: std::ptr::null(),
+    };
+    let found = environment(
+        ENVIRONMENT_GET_VARIABLE,
+        &mut variable as *mut Variable as *mut c_void,
+    );
+    (found && !variable.value.is_null()).then(|| CStr::from_ptr(variable.value))
+}
+
+// Options as the frontend has them, defaults for anything it doesn't know about
+unsafe fn options() -> Options {
+    let mut options = Options::default();
+    if let Some(value) = variable(WAIT_FOR_RELEASE) {
+        options.wait_for_release = value != c"off";
+    }
+    if let Some(cycles) =
+        variable(CYCLES_PER_FRAME).and_then(|value| value.to_str().ok()?.parse().ok())
+    {
+        options.cycles_per_frame = cycles;
+    }
+    options
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+
+    let mut log = LogCallback { log: None };
+    if environment(
+        ENVIRONMENT_GET_LOG_INTERFACE,
+        &mut log as *mut LogCallback as *mut c_void,
+    ) {
+        CALLBACKS.lock().unwrap().log = log.log;
+    }
+
+    // Both lists are copied by the frontend before this returns
+    let mut variables = VARIABLES
+        .iter()
+        .map(|(key, value)| Variable {
+            key: key.as_ptr(),
+            value: value.as_ptr(),
+        })
+        .collect::<Vec<Variable>>();
+    variables.push(Variable {
+        key: std::ptr::null(),
+        value: std::ptr::null(),
+    });
+    environment(
+        ENVIRONMENT_SET_VARIABLES,
+        variables.as_mut_ptr() as *mut c_void,
+    );
+
+    let mut descriptors = BUTTONS
+        .iter()
+        .map(|(id, _, description)| InputDescriptor {
+            port: 0,
+            device: DEVICE_JOYPAD,
+            index: 0,
+            id: *id,
+            description: description.as_ptr(),
+        })
+        .collect::<Vec<InputDescriptor>>();
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: std::ptr::null(),
+    });
+    environment(
+        ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr() as *mut c_void,
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+// Everything goes through the batch one
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c"Paulson-8".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"ch8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: FPS as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+// Only the one controller
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = core().as_mut() {
+        // Loaded fine the first time so it will again
+        let _ = core.reset();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let callbacks = callbacks();
+
+    let mut updated = false;
+    environment(
+        ENVIRONMENT_GET_VARIABLE_UPDATE,
+        &mut updated as *mut bool as *mut c_void,
+    );
+    let options = updated.then(|| options());
+
+    let mut pressed = [false; 16];
+    if let (Some(poll), Some(state)) = (callbacks.input_poll, callbacks.input_state) {
+        poll();
+        for (id, key, _) in BUTTONS {
+            pressed[key as usize] = state(0, DEVICE_JOYPAD, 0, id) != 0;
+        }
+    }
+
+    // Copied out so the lock is let go before calling back into the frontend
+    let mut pixels = [0; WIDTH * HEIGHT];
+    let mut samples = [0; SAMPLES_PER_FRAME * 2];
+    let result = {
+        let mut core = core();
+        let Some(core) = core.as_mut() else {
+            return;
+        };
+        if let Some(options) = options {
+            core.set_options(options);
+        }
+        let result = core.run_frame(pressed);
+        pixels.copy_from_slice(core.pixels());
+        samples.copy_from_slice(core.samples());
+        result
+    };
+    if let Err(e) = result {
+        log_error(format_args!("{}, stopped", e));
+    }
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(
+            pixels.as_ptr() as *const c_void,
+            WIDTH as c_uint,
+            HEIGHT as c_uint,
+            WIDTH * 4,
+        );
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(samples.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    Chip8::STATE_SIZE
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let out = std::slice::from_raw_parts_mut(data as *mut u8, size);
+    match core().as_ref() {
+        Some(core) => core.save_state(out).is_ok(),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    let result = core().as_mut().map(|core| core.load_state(state));
+    match result {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            log_error(e);
+            false
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut format as *mut c_uint as *mut c_void,
+    ) {
+        log_error("Frontend doesn't do XRGB8888");
+        return false;
+    }
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+
+    let rom = std::slice::from_raw_parts(game.data as *const u8, game.size);
+    match Core::new(rom, options()) {
+        Ok(loaded) => {
+            *core() = Some(loaded);
+            true
+        }
+        Err(e) => {
+            log_error(e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// Memory isn't handed out, writes to it would get past the decode cache
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,158 @@
+// The machine behind the C API, a `Chip8` driven through `frontend::frame` with the
+// frontend traits turning its output into what libretro wants: XRGB8888 pixels and a
+// frame's worth of 16-bit stereo samples.
+
+use paulson_8::frontend::{frame, Audio, Display, FixedClock, Input, DEFAULT_CYCLES_PER_FRAME};
+use paulson_8::interpreter::{Chip8, Error, Quirks, StateError};
+
+pub const WIDTH: usize = Chip8::VIDEO_WIDTH as usize;
+pub const HEIGHT: usize = Chip8::VIDEO_HEIGHT as usize;
+pub const FPS: usize = 60;
+pub const SAMPLE_RATE: usize = 44_100;
+pub const SAMPLES_PER_FRAME: usize = SAMPLE_RATE / FPS;
+
+const LIT: u32 = 0x00FF_FFFF;
+const DARK: u32 = 0x0000_0000;
+// The buzzer, a quiet square wave
+const TONE: usize = 440;
+const VOLUME: i16 = 0x0800;
+
+// What the core options can change
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Options {
+    pub wait_for_release: bool,
+    pub cycles_per_frame: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            wait_for_release: Quirks::default().wait_for_release,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+        }
+    }
+}
+
+struct Screen {
+    pixels: [u32; WIDTH * HEIGHT],
+}
+
+impl Display for Screen {
+    fn present(&mut self, video: &[u8], _width: usize, _height: usize) {
+        for (pixel, lit) in self.pixels.iter_mut().zip(video) {
+            *pixel = if *lit != 0 { LIT } else { DARK };
+        }
+    }
+}
+
+struct Buzzer {
+    // Where the wave is at, carried over between frames so it doesn't click
+    phase: usize,
+    samples: [i16; SAMPLES_PER_FRAME * 2],
+}
+
+impl Audio for Buzzer {
+    fn buzz(&mut self, on: bool) {
+        for frame in self.samples.chunks_mut(2) {
+            let sample = match on {
+                false => 0,
+                true if self.phase < SAMPLE_RATE / 2 => VOLUME,
+                true => -VOLUME,
+            };
+            frame.fill(sample);
+            self.phase = (self.phase + TONE) % SAMPLE_RATE;
+        }
+    }
+}
+
+struct Joypad([bool; 16]);
+
+impl Input for Joypad {
+    fn pressed(&mut self) -> [bool; 16] {
+        self.0
+    }
+}
+
+pub struct Core {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    options: Options,
+    // Set when the ROM faults, the last screen stays up until a reset
+    stopped: bool,
+    screen: Screen,
+    buzzer: Buzzer,
+}
+
+impl Core {
+    pub fn new(rom: &[u8], options: Options) -> Result<Core, Error> {
+        let mut core = Core {
+            chip8: Chip8::new(),
+            rom: rom.to_vec(),
+            options,
+            stopped: false,
+            screen: Screen {
+                pixels: [DARK; WIDTH * HEIGHT],
+            },
+            buzzer: Buzzer {
+                phase: 0,
+                samples: [0; SAMPLES_PER_FRAME * 2],
+            },
+        };
+        core.reset()?;
+        Ok(core)
+    }
+
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.chip8 = Chip8::new();
+        self.set_options(self.options);
+        self.stopped = false;
+        self.chip8.load_bytes(&self.rom)
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        self.options = options;
+        self.chip8.set_quirks(Quirks {
+            wait_for_release: options.wait_for_release,
+        });
+    }
+
+    // A fault stops the machine until it's reset or a state is loaded
+    pub fn run_frame(&mut self, pressed: [bool; 16]) -> Result<(), Error> {
+        let cycles = if self.stopped {
+            0
+        } else {
+            self.options.cycles_per_frame
+        };
+        let result = frame(
+            &mut self.chip8,
+            &mut Joypad(pressed),
+            &mut FixedClock(cycles),
+            &mut self.buzzer,
+            &mut self.screen,
+        );
+        if result.is_err() {
+            self.stopped = true;
+        }
+        result
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.screen.pixels
+    }
+
+    // Interleaved left and right
+    pub fn samples(&self) -> &[i16] {
+        &self.buzzer.samples
+    }
+
+    pub fn save_state(&self, out: &mut [u8]) -> Result<(), StateError> {
+        self.chip8.save_state(out)
+    }
+
+    // A state from before a fault starts the machine going again
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.chip8.load_state(state)?;
+        self.stopped = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+// A tiny libretro frontend, loads the built core the way RetroArch would and checks
+// what comes out of it frame by frame.
+//
+// The core's state is global, and so is what the callbacks here collect, so only one
+// `Frontend` exists at a time.
+
+use std::ffi::{c_char, c_uint, c_void, CStr, CString};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard};
+
+use libloading::{Library, Symbol};
+use paulson_8::interpreter::Chip8;
+
+// Waits for a key, draws its digit in the top left and buzzes
+const ROM: [u16; 7] = [
+    0x61FF, // LD V1, FF
+    0xF00A, // LD V0, K
+    0x00E0, // CLS
+    0xF029, // LD F, V0
+    0xD225, // DRW V2, V2, 5
+    0xF118, // LD ST, V1
+    0x1202, // JP 202
+];
+
+const JOYPAD_B: c_uint = 0;
+const JOYPAD_A: c_uint = 8;
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct AvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+// Everything the callbacks see and hand back
+#[derive(Default)]
+struct Shared {
+    pixel_format: Option<c_uint>,
+    // Core options as the core described them, and what this frontend has them set to
+    variables: Vec<(String, String)>,
+    options: Vec<(CString, CString)>,
+    options_updated: bool,
+    buttons: Vec<c_uint>,
+    screen: Vec<u32>,
+    audio: Vec<i16>,
+    // Levels and messages the core logged
+    logs: Vec<(c_uint, String)>,
+    // Called from the video callback once, and what it returned
+    call_back_in: Option<unsafe extern "C" fn(*mut c_void, usize) -> bool>,
+    called_back_in: Option<bool>,
+}
+
+static SHARED: Mutex<Option<Shared>> = Mutex::new(None);
+static ONE_AT_A_TIME: Mutex<()> = Mutex::new(());
+
+fn with<T>(f: impl FnOnce(&mut Shared) -> T) -> T {
+    f(SHARED.lock().unwrap().as_mut().unwrap())
+}
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        // SET_PIXEL_FORMAT
+        10 => {
+            with(|shared| shared.pixel_format = Some(*(data as *const c_uint)));
+            true
+        }
+        // SET_INPUT_DESCRIPTORS
+        11 => true,
+        // GET_VARIABLE, the value has to outlive the call so it points into `options`
+        15 => {
+            let variable = &mut *(data as *mut Variable);
+            let key = CStr::from_ptr(variable.key);
+            with(|shared| {
+                let option = shared
+                    .options
+                    .iter()
+                    .find(|(name, _)| name.as_c_str() == key);
+                match option {
+                    Some((_, value)) => {
+                        variable.value = value.as_ptr();
+                        true
+                    }
+                    None => false,
+                }
+            })
+        }
+        // SET_VARIABLES
+        16 => {
+            let mut variable = data as *const Variable;
+            while !(*variable).key.is_null() {
+                let key = CStr::from_ptr((*variable).key)
+                    .to_string_lossy()
+                    .into_owned();
+                let value = CStr::from_ptr((*variable).value)
+                    .to_string_lossy()
+                    .into_owned();
+                with(|shared| shared.variables.push((key, value)));
+                variable = variable.add(1);
+            }
+            true
+        }
+        // GET_VARIABLE_UPDATE
+        17 => {
+            *(data as *mut bool) = with(|shared| std::mem::take(&mut shared.options_updated));
+            true
+        }
+        // GET_LOG_INTERFACE, a struct holding just the function
+        27 => {
+            *(data as *mut *const c_void) = log as *const c_void;
+            true
+        }
+        _ => false,
+    }
+}
+
+// retro_log_printf_t is variadic, which Rust can't define. The core only ever logs
+// with "%s" and one string, which on x86-64 and AArch64 Linux arrives the same way as
+// these three arguments
+unsafe extern "C" fn log(level: c_uint, _format: *const c_char, message: *const c_char) {
+    let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+    with(|shared| shared.logs.push((level, message)));
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    assert_eq!(pitch, width as usize * 4);
+    let pixels = std::slice::from_raw_parts(data as *const u32, (width * height) as usize);
+    with(|shared| shared.screen = pixels.to_vec());
+
+    if let Some(serialize) = with(|shared| shared.call_back_in.take()) {
+        let mut state = vec![0; Chip8::STATE_SIZE];
+        let saved = serialize(state.as_mut_ptr() as *mut c_void, state.len());
+        with(|shared| shared.called_back_in = Some(saved));
+    }
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = std::slice::from_raw_parts(data, frames * 2);
+    with(|shared| shared.audio = samples.to_vec());
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    // Port 0's joypad
+    (port == 0 && device == 1 && with(|shared| shared.buttons.contains(&id))) as i16
+}
+
+struct Frontend {
+    library: Library,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Frontend {
+    // Cargo doesn't build cdylibs for integration tests, so this does. It goes next to
+    // deps/ where this test is, target/debug/deps/frontend-<hash>
+    fn build() -> PathBuf {
+        let mut cargo = Command::new(env!("CARGO"));
+        cargo
+            .args(["build", "--lib", "--quiet"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"));
+        if !cfg!(debug_assertions) {
+            cargo.arg("--release");
+        }
+        assert!(cargo.status().unwrap().success(), "Core didn't build");
+
+        let exe = std::env::current_exe().unwrap();
+        let target = exe.parent().unwrap().parent().unwrap();
+        target.join(libloading::library_filename("paulson8_libretro"))
+    }
+
+    // Loads the core and the test ROM with core options already set to `options`
+    fn load(options: &[(&str, &str)]) -> Frontend {
+        let lock = ONE_AT_A_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        *SHARED.lock().unwrap() = Some(Shared {
+            options: options
+                .iter()
+                .map(|(key, value)| (CString::new(*key).unwrap(), CString::new(*value).unwrap()))
+                .collect(),
+            ..Shared::default()
+        });
+
+        let library = unsafe { Library::new(Frontend::build()) }.unwrap();
+        let frontend = Frontend {
+            library,
+            _lock: lock,
+        };
+        unsafe {
+            assert_eq!(
+                frontend.call::<unsafe extern "C" fn() -> c_uint>("retro_api_version")(),
+                1
+            );
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_environment")(
+                environment as unsafe extern "C" fn(_, _) -> _,
+            );
+            frontend.call::<unsafe extern "C" fn()>("retro_init")();
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_video_refresh")(
+                video_refresh as unsafe extern "C" fn(_, _, _, _),
+            );
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_audio_sample")(
+                audio_sample as unsafe extern "C" fn(_, _),
+            );
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_audio_sample_batch")(
+                audio_sample_batch as unsafe extern "C" fn(_, _) -> _,
+            );
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_input_poll")(
+                input_poll as unsafe extern "C" fn(),
+            );
+            frontend.call::<unsafe extern "C" fn(_)>("retro_set_input_state")(
+                input_state as unsafe extern "C" fn(_, _, _, _) -> _,
+            );
+
+            let rom = ROM
+                .iter()
+                .flat_map(|word| word.to_be_bytes())
+                .collect::<Vec<u8>>();
+            let game = GameInfo {
+                path: std::ptr::null(),
+                data: rom.as_ptr() as *const c_void,
+                size: rom.len(),
+                meta: std::ptr::null(),
+            };
+            let load =
+                frontend.call::<unsafe extern "C" fn(*const GameInfo) -> bool>("retro_load_game");
+            assert!(load(&game));
+        }
+        frontend
+    }
+
+    unsafe fn call<T>(&self, name: &str) -> Symbol<'_, T> {
+        self.library.get(name.as_bytes()).unwrap()
+    }
+
+    fn av_info(&self) -> AvInfo {
+        let mut info = AvInfo::default();
+        unsafe {
+            self.call::<unsafe extern "C" fn(*mut AvInfo)>("retro_get_system_av_info")(&mut info)
+        };
+        info
+    }
+
+    fn run(&self, frames: usize, buttons: &[c_uint]) {
+        with(|shared| shared.buttons = buttons.to_vec());
+        for _ in 0..frames {
+            unsafe { self.call::<unsafe extern "C" fn()>("retro_run")() };
+        }
+    }
+
+    // Presses and lets go, FX0A finishes on the release by default
+    fn tap(&self, button: c_uint) {
+        self.run(2, &[button]);
+        self.run(2, &[]);
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let size =
+            unsafe { self.call::<unsafe extern "C" fn() -> usize>("retro_serialize_size")() };
+        let mut state = vec![0; size];
+        let serialize = unsafe {
+            self.call::<unsafe extern "C" fn(*mut c_void, usize) -> bool>("retro_serialize")
+        };
+        assert!(unsafe { serialize(state.as_mut_ptr() as *mut c_void, size) });
+        state
+    }
+
+    fn unserialize(&self, state: &[u8]) -> bool {
+        let unserialize = unsafe {
+            self.call::<unsafe extern "C" fn(*const c_void, usize) -> bool>("retro_unserialize")
+        };
+        unsafe { unserialize(state.as_ptr() as *const c_void, state.len()) }
+    }
+
+    fn set_option(&self, key: &str, value: &str) {
+        with(|shared| {
+            let value = CString::new(value).unwrap();
+            match shared
+                .options
+                .iter_mut()
+                .find(|(name, _)| name.to_str() == Ok(key))
+            {
+                Some(option) => option.1 = value,
+                None => shared.options.push((CString::new(key).unwrap(), value)),
+            }
+            shared.options_updated = true;
+        });
+    }
+
+    // The digit in the top left, None for a blank screen
+    fn digit(&self) -> Option<usize> {
+        let screen = with(|shared| shared.screen.clone());
+        assert_eq!(screen.len(), 64 * 32);
+        assert!(screen
+            .iter()
+            .all(|pixel| *pixel == 0 || *pixel == 0x00FF_FFFF));
+        if screen.iter().all(|pixel| *pixel == 0) {
+            return None;
+        }
+        let rows = (0..5)
+            .map(|y| (0..8).fold(0, |row, x| row << 1 | (screen[x + y * 64] != 0) as u8))
+            .collect::<Vec<u8>>();
+        Chip8::FONT.chunks(5).position(|digit| digit == rows)
+    }
+
+    fn buzzing(&self) -> bool {
+        let audio = with(|shared| shared.audio.clone());
+        // A frame of 44.1kHz stereo at 60fps
+        assert_eq!(audio.len(), 735 * 2);
+        audio.iter().any(|sample| *sample != 0)
+    }
+}
+
+impl Drop for Frontend {
+    fn drop(&mut self) {
+        unsafe {
+            self.call::<unsafe extern "C" fn()>("retro_unload_game")();
+            self.call::<unsafe extern "C" fn()>("retro_deinit")();
+        }
+    }
+}
+
+#[test]
+fn plays_the_rom() {
+    let frontend = Frontend::load(&[]);
+    let info = frontend.av_info();
+    assert_eq!((info.base_width, info.base_height), (64, 32));
+    assert_eq!((info.fps, info.sample_rate), (60.0, 44_100.0));
+    assert_eq!(with(|shared| shared.pixel_format), Some(1));
+
+    frontend.run(3, &[]);
+    assert_eq!(frontend.digit(), None);
+    assert!(!frontend.buzzing());
+
+    // Waits for the release
+    frontend.run(2, &[JOYPAD_A]);
+    assert_eq!(frontend.digit(), None);
+    frontend.run(1, &[]);
+    assert_eq!(frontend.digit(), Some(5));
+    assert!(frontend.buzzing());
+
+    // ST was FF and counts down once an instruction
+    frontend.run(15, &[]);
+    assert!(!frontend.buzzing());
+
+    frontend.tap(JOYPAD_B);
+    assert_eq!(frontend.digit(), Some(0));
+}
+
+#[test]
+fn save_states() {
+    let frontend = Frontend::load(&[]);
+    // FX0A only takes keys pressed after it starts waiting
+    frontend.run(1, &[]);
+    frontend.tap(JOYPAD_A);
+    assert_eq!(frontend.digit(), Some(5));
+    let state = frontend.serialize();
+
+    frontend.tap(JOYPAD_B);
+    assert_eq!(frontend.digit(), Some(0));
+    assert!(frontend.unserialize(&state));
+    frontend.run(1, &[]);
+    assert_eq!(frontend.digit(), Some(5));
+
+    assert!(!frontend.unserialize(&state[1..]));
+    assert!(!frontend.unserialize(&vec![0; state.len()]));
+    // Both failures went to the frontend's log as errors
+    let logs = with(|shared| shared.logs.clone());
+    assert_eq!(logs.len(), 2);
+    assert!(logs.iter().all(|(level, _)| *level == 3));
+
+    // Null buffers are refused rather than read or written
+    let (serialize, unserialize) = unsafe {
+        (
+            frontend.call::<unsafe extern "C" fn(*mut c_void, usize) -> bool>("retro_serialize"),
+            frontend
+                .call::<unsafe extern "C" fn(*const c_void, usize) -> bool>("retro_unserialize"),
+        )
+    };
+    assert!(!unsafe { serialize(std::ptr::null_mut(), state.len()) });
+    assert!(!unsafe { unserialize(std::ptr::null(), state.len()) });
+}
+
+#[test]
+fn core_options() {
+    let frontend = Frontend::load(&[("paulson8_wait_for_release", "off")]);
+    let variables = with(|shared| shared.variables.clone());
+    let keys = variables
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        keys,
+        ["paulson8_wait_for_release", "paulson8_cycles_per_frame"]
+    );
+    assert!(variables.iter().all(|(_, value)| value.contains("; ")));
+
+    // Goes on the press with the quirk off
+    frontend.run(1, &[JOYPAD_A]);
+    assert_eq!(frontend.digit(), Some(5));
+    frontend.run(1, &[]);
+
+    // Picked up while running, with one instruction a frame FX0A to DRW takes four
+    frontend.set_option("paulson8_cycles_per_frame", "1");
+    frontend.run(1, &[JOYPAD_B]);
+    frontend.run(2, &[]);
+    assert_eq!(frontend.digit(), None);
+    frontend.run(2, &[]);
+    assert_eq!(frontend.digit(), Some(0));
+}
+
+#[test]
+fn callbacks_can_call_back_in() {
+    let frontend = Frontend::load(&[]);
+    let serialize = unsafe {
+        *frontend.call::<unsafe extern "C" fn(*mut c_void, usize) -> bool>("retro_serialize")
+    };
+    with(|shared| shared.call_back_in = Some(serialize));
+    frontend.run(1, &[]);
+    assert_eq!(with(|shared| shared.called_back_in), Some(true));
+}
@@ -0,0 +1,150 @@
+// What a program translated ahead of time by paulson-8-aot runs on. The translation
+// is a `Program`, a table of Rust functions for the basic blocks of its ROM. `run`
+// steps a machine through them, and through the interpreter from paulson-8 for
+// anything the translator couldn't see or that has been written over since loading.
+//
+// With the window feature `window` plays a program with the default key layout.
+
+#![cfg_attr(not(any(feature = "window", test)), no_std)]
+
+pub use paulson_8::interpreter::{Chip8, Error};
+
+const START_ADDRESS: u16 = 0x200;
+
+// A translated block, runs the instructions and leaves PC on whatever comes next
+pub type Block = fn(&mut Chip8) -> Result<(), Error>;
+
+pub trait Program {
+    const NAME: &'static str;
+    const ROM: &'static [u8];
+
+    // Block starting at `address` and how many instructions it runs
+    fn block(address: u16) -> Option<(usize, Block)>;
+}
+
+// Fresh machine with the program's ROM loaded
+pub fn machine<P: Program>() -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8
+        .load_bytes(P::ROM)
+        .expect("Translated ROMs always fit");
+    chip8
+}
+
+// Same as `Chip8::run`, through the translated blocks where it can
+pub fn run<P: Program>(chip8: &mut Chip8, cycles: usize) -> Result<(), Error> {
+    let mut left = cycles;
+    while left > 0 {
+        let address = chip8.program_counter();
+        let translated = P::block(address).filter(|(len, _)| {
+            let rom = (address - START_ADDRESS) as usize;
+            *len <= left && chip8.code_matches(address, &P::ROM[rom..rom + len * 2])
+        });
+
+        match translated {
+            Some((len, block)) => {
+                block(chip8)?;
+                left -= len;
+            }
+            None => {
+                chip8.cycle()?;
+                left -= 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Plays the program in a window with the default key layout, Escape quits
+#[cfg(feature = "window")]
+pub fn window<P: Program + 'static>() {
+    use macroquad::prelude::*;
+    use paulson_8::frontend::DEFAULT_CYCLES_PER_FRAME;
+    use paulson_8::interpreter::key_events;
+
+    // The layout everyone knows, chip-8 keys 0 through F
+    const KEYS: [KeyCode; 16] = {
+        use KeyCode::*;
+        [X, Key1, Key2, Key3, Q, W, E, A, S, D, Z, C, Key4, R, F, V]
+    };
+
+    macroquad::Window::new(P::NAME, async {
+        let mut chip8 = machine::<P>();
+
+        while !is_key_pressed(KeyCode::Escape) {
+            let before = chip8.pressed_keys();
+            for event in key_events(before, KEYS.map(is_key_down)) {
+                chip8.handle_key(event);
+            }
+            if let Err(e) = run::<P>(&mut chip8, DEFAULT_CYCLES_PER_FRAME) {
+                eprintln!("{}", e);
+                break;
+            }
+
+            clear_background(BLACK);
+            let size = screen_width() / Chip8::VIDEO_WIDTH as f32;
+            let width = Chip8::VIDEO_WIDTH as usize;
+            for (n, pixel) in chip8.get_video_buffer().iter().enumerate() {
+                if *pixel != 0 {
+                    let (x, y) = ((n % width) as f32, (n / width) as f32);
+                    draw_rectangle(x * size, y * size, size, size, WHITE);
+                }
+            }
+            next_frame().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Hand written stand in for a translation of LD V0, 1 / JP 202
+    struct Marked;
+
+    impl Program for Marked {
+        const NAME: &'static str = "Marked";
+        const ROM: &'static [u8] = &[0x60, 0x01, 0x12, 0x02];
+
+        fn block(address: u16) -> Option<(usize, Block)> {
+            match address {
+                0x200 => Some((1, |chip8| {
+                    // Different from what the ROM does so it shows when this ran
+                    let (mut v, i) = chip8.registers();
+                    v[0] = 0xAA;
+                    chip8.set_registers(v, i);
+                    chip8.set_program_counter(0x202);
+                    Ok(())
+                })),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn runs_translated_blocks() {
+        let mut chip8 = machine::<Marked>();
+        run::<Marked>(&mut chip8, 3).unwrap();
+        assert_eq!(chip8.registers().0[0], 0xAA);
+        assert_eq!(chip8.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn changed_code_falls_back() {
+        let mut chip8 = machine::<Marked>();
+        chip8.write_memory(0x201, &[0x02]);
+        run::<Marked>(&mut chip8, 1).unwrap();
+        assert_eq!(chip8.registers().0[0], 0x02);
+    }
+
+    #[test]
+    fn untranslated_code_falls_back() {
+        // JP V0 + 200 onto the JP that was never translated on its own
+        let mut chip8 = machine::<Marked>();
+        chip8.set_registers([0x02; 16], 0);
+        chip8.set_program_counter(0x202);
+        chip8.write_memory(0x202, &[0xB2, 0x00]);
+        run::<Marked>(&mut chip8, 1).unwrap();
+        assert_eq!(chip8.program_counter(), 0x202);
+    }
+}
@@ -0,0 +1,439 @@
+// Ahead of time translation of a ROM into Rust source.
+//
+// `translate` follows every path it can from the start address, splits what it finds
+// into basic blocks and writes one Rust function per block. Arithmetic, jumps and
+// skips become plain Rust on V0-VF and I held in locals, everything else calls back
+// into the interpreter one instruction at a time through `Chip8::execute_at`.
+//
+// The output implements `Program` from paulson-8-runtime (runtime/), and `package` wraps
+// it in a crate of its own that plays it in a window. Code the walk couldn't see, like
+// the targets of BNNN, and blocks whose bytes have changed since the ROM was loaded,
+// go through the interpreter, and translated blocks are picked up again as soon as the
+// program lands on one.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::interpreter::opcodes::OP;
+use crate::interpreter::Chip8;
+
+const START_ADDRESS: u16 = 0x200;
+// Same trade off as the jit, blocks only run when the cycle budget has room for them
+const MAX_BLOCK: usize = 16;
+
+// How an instruction carries on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Flow {
+    Next,
+    Jump(u16),
+    Call(u16),
+    // Next instruction or the one after
+    Skip,
+    // Nowhere the walk can follow, RET and BNNN
+    Stop,
+}
+
+fn flow(op: OP) -> Flow {
+    match op {
+        OP::JP { addr } => Flow::Jump(addr),
+        OP::CALL { addr } => Flow::Call(addr),
+        OP::SE { .. }
+        | OP::SNE { .. }
+        | OP::SER { .. }
+        | OP::SNER { .. }
+        | OP::SKP { .. }
+        | OP::SKNP { .. } => Flow::Skip,
+        OP::RET | OP::JPR { .. } => Flow::Stop,
+        _ => Flow::Next,
+    }
+}
+
+// Instructions after which the block has to hand back to `run`, either because PC
+// went somewhere or because memory changed and the next block might not match anymore
+fn ends_block(op: OP) -> bool {
+    flow(op) != Flow::Next || matches!(op, OP::LDK { .. } | OP::LDB { .. } | OP::LDIA { .. })
+}
+
+struct Rom<'a>(&'a [u8]);
+
+impl Rom<'_> {
+    // Whole instruction inside the ROM
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = (address as usize).checked_sub(START_ADDRESS as usize)?;
+        let bytes = self.0.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+// Every reachable instruction and the addresses blocks start at
+fn walk(rom: &Rom) -> (BTreeSet<u16>, BTreeSet<u16>) {
+    let mut code = BTreeSet::new();
+    let mut leaders = BTreeSet::from([START_ADDRESS]);
+    let mut work = vec![START_ADDRESS];
+
+    while let Some(address) = work.pop() {
+        let Some(word) = rom.word(address) else {
+            continue;
+        };
+        if !code.insert(address) {
+            continue;
+        }
+
+        let op = OP::from(word);
+        let next = address.wrapping_add(2);
+        let targets = match flow(op) {
+            Flow::Next => vec![next],
+            Flow::Jump(addr) => vec![addr],
+            Flow::Call(addr) => vec![addr, next],
+            Flow::Skip => vec![next, next.wrapping_add(2)],
+            Flow::Stop => vec![],
+        };
+
+        if ends_block(op) {
+            leaders.extend(&targets);
+        }
+        work.extend(targets);
+    }
+
+    leaders.retain(|address| code.contains(address));
+    (code, leaders)
+}
+
+// Rust for instructions that don't need the interpreter, None for the rest
+fn native(op: OP) -> Option<String> {
+    Some(match op {
+        OP::LD { vx, byte } => format!("v[0x{:X}] = 0x{:02X};", vx, byte),
+        OP::ADD { vx, byte } => {
+            format!("v[0x{0:X}] = v[0x{0:X}].wrapping_add(0x{1:02X});", vx, byte)
+        }
+        OP::LDR { vx, vy } => format!("v[0x{:X}] = v[0x{:X}];", vx, vy),
+        OP::OR { vx, vy } => format!("v[0x{:X}] |= v[0x{:X}];", vx, vy),
+        OP::AND { vx, vy } => format!("v[0x{:X}] &= v[0x{:X}];", vx, vy),
+        OP::XOR { vx, vy } => format!("v[0x{:X}] ^= v[0x{:X}];", vx, vy),
+        OP::ADDR { vx, vy } => format!(
+            "let (sum, carry) = v[0x{0:X}].overflowing_add(v[0x{1:X}]);\n\
+             v[0x{0:X}] = sum;\n\
+             v[0xF] = carry as u8;",
+            vx, vy
+        ),
+        OP::SUB { vx, vy } | OP::SUBN { vx, vy } => {
+            let (from, by) = match op {
+                OP::SUB { .. } => (vx, vy),
+                _ => (vy, vx),
+            };
+            format!(
+                "let (difference, borrow) = v[0x{:X}].overflowing_sub(v[0x{:X}]);\n\
+                 v[0x{:X}] = difference;\n\
+                 v[0xF] = !borrow as u8;",
+                from, by, vx
+            )
+        }
+        OP::SHR { vx, .. } => format!(
+            "let bit = v[0x{0:X}] & 0x01;\nv[0x{0:X}] >>= 1;\nv[0xF] = bit;",
+            vx
+        ),
+        OP::SHL { vx, .. } => format!(
+            "let bit = v[0x{0:X}] >> 7;\nv[0x{0:X}] <<= 1;\nv[0xF] = bit;",
+            vx
+        ),
+        OP::LDI { addr } => format!("i = 0x{:03X};", addr),
+        OP::ADDI { vx } => format!("i = i.wrapping_add(v[0x{:X}] as u16);", vx),
+        OP::LDF { vx } => format!(
+            "i = 0x{:03X} + 5 * v[0x{:X}] as u16;",
+            Chip8::FONT_ADDRESS,
+            vx
+        ),
+        _ => return None,
+    })
+}
+
+// Where a native skip goes, None when the interpreter has to do it
+fn skip_condition(op: OP) -> Option<String> {
+    Some(match op {
+        OP::SE { vx, byte } => format!("v[0x{:X}] == 0x{:02X}", vx, byte),
+        OP::SNE { vx, byte } => format!("v[0x{:X}] != 0x{:02X}", vx, byte),
+        OP::SER { vx, vy } => format!("v[0x{:X}] == v[0x{:X}]", vx, vy),
+        OP::SNER { vx, vy } => format!("v[0x{:X}] != v[0x{:X}]", vx, vy),
+        _ => return None,
+    })
+}
+
+// Lines of one block function and how many instructions it covers
+fn block(rom: &Rom, code: &BTreeSet<u16>, leaders: &BTreeSet<u16>, start: u16) -> (String, usize) {
+    let mut body = String::from("let (mut v, mut i) = chip8.registers();\n");
+    // Instructions run natively since the timers were last ticked
+    let mut pending = 0;
+    let mut len = 0;
+    let mut address = start;
+
+    let sync = |body: &mut String, pending: &mut usize| {
+        if *pending > 0 {
+            let _ = writeln!(body, "chip8.tick_timers({});", pending);
+            *pending = 0;
+        }
+        body.push_str("chip8.set_registers(v, i);\n");
+    };
+
+    loop {
+        let word = rom.word(address).expect("Blocks only cover reachable code");
+        let op = OP::from(word);
+        let next = address.wrapping_add(2);
+        let _ = writeln!(body, "\n// 0x{:03X}  {:04X}  {}", address, word, op);
+        len += 1;
+
+        if let Some(rust) = native(op) {
+            let _ = writeln!(body, "{{\n{}\n}}", rust);
+            pending += 1;
+        } else if let OP::JP { addr } = op {
+            pending += 1;
+            sync(&mut body, &mut pending);
+            let _ = writeln!(body, "chip8.set_program_counter(0x{:03X});", addr);
+            return (body + "Ok(())\n", len);
+        } else if let Some(condition) = skip_condition(op) {
+            pending += 1;
+            sync(&mut body, &mut pending);
+            let _ = writeln!(
+                body,
+                "chip8.set_program_counter(if {} {{ 0x{:03X} }} else {{ 0x{:03X} }});",
+                condition,
+                next.wrapping_add(2),
+                next
+            );
+            return (body + "Ok(())\n", len);
+        } else {
+            sync(&mut body, &mut pending);
+            let _ = writeln!(
+                body,
+                "chip8.execute_at(0x{:03X}, 0x{:04X})?;",
+                address, word
+            );
+            if ends_block(op) {
+                return (body + "Ok(())\n", len);
+            }
+            body.push_str("(v, i) = chip8.registers();\n");
+        }
+
+        // Carry on into the next instruction unless a block starts there anyway
+        if len == MAX_BLOCK || leaders.contains(&next) || !code.contains(&next) {
+            sync(&mut body, &mut pending);
+            let _ = writeln!(body, "chip8.set_program_counter(0x{:03X});", next);
+            return (body + "Ok(())\n", len);
+        }
+        address = next;
+    }
+}
+
+fn indent(text: &str, depth: usize) -> String {
+    let mut out = String::new();
+    let mut level = depth;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('}') {
+            level -= 1;
+        }
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = writeln!(out, "{}{}", "    ".repeat(level), line);
+        }
+        if line.ends_with('{') {
+            level += 1;
+        }
+    }
+    out
+}
+
+// Rust source for a program called `name`, which has to be a valid type name. It's
+// the module that `package` puts in src/program.rs
+pub fn translate(rom: &[u8], name: &str) -> String {
+    let rom = Rom(rom);
+    let (code, mut leaders) = walk(&rom);
+
+    // Long runs get split, the pieces start blocks of their own
+    let mut blocks = Vec::new();
+    let mut todo = leaders.iter().copied().collect::<Vec<u16>>();
+    while let Some(start) = todo.pop() {
+        let (body, len) = block(&rom, &code, &leaders, start);
+        let next = start.wrapping_add(len as u16 * 2);
+        if len == MAX_BLOCK && code.contains(&next) && leaders.insert(next) {
+            todo.push(next);
+        }
+        blocks.push((start, len, body));
+    }
+    blocks.sort_by_key(|(start, _, _)| *start);
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "// Generated by paulson-8-aot from a {} byte ROM, {} blocks over {} instructions.\n\
+         // Regenerate rather than edit.\n\n\
+         #![allow(unused_mut, unused_variables, unused_assignments)]\n\n\
+         use paulson_8_runtime::{{Block, Chip8, Error, Program}};\n\n\
+         pub struct {name};\n\n\
+         impl Program for {name} {{\n\
+         \x20   const NAME: &'static str = \"{name}\";\n\
+         \x20   const ROM: &'static [u8] = &ROM;\n\n\
+         \x20   fn block(address: u16) -> Option<(usize, Block)> {{\n\
+         \x20       Some(match address {{\n",
+        rom.0.len(),
+        blocks.len(),
+        code.len(),
+        name = name,
+    );
+    for (start, len, _) in &blocks {
+        let _ = writeln!(
+            out,
+            "            0x{0:03X} => ({1}, block_{0:03x}),",
+            start, len
+        );
+    }
+    out.push_str(
+        "            _ => return None,\n        \
+         })\n    \
+         }\n\
+         }\n\n",
+    );
+
+    let _ = writeln!(out, "const ROM: [u8; {}] = [", rom.0.len());
+    for line in rom.0.chunks(16) {
+        let bytes = line
+            .iter()
+            .map(|byte| format!("0x{:02X},", byte))
+            .collect::<Vec<String>>();
+        let _ = writeln!(out, "    {}", bytes.join(" "));
+    }
+    out.push_str("];\n");
+
+    for (start, _, body) in &blocks {
+        let _ = write!(
+            out,
+            "\nfn block_{:03x}(chip8: &mut Chip8) -> Result<(), Error> {{\n{}}}\n",
+            start,
+            indent(body, 1)
+        );
+    }
+
+    out
+}
+
+// A crate that plays the program in a window, as paths inside it and what goes there.
+// It depends on paulson-8-runtime at `runtime` and isn't part of any workspace, so it
+// builds wherever it's written with `cargo run --release`
+pub fn package(rom: &[u8], name: &str, runtime: &Path) -> Vec<(&'static str, String)> {
+    let package = package_name(name);
+    let manifest = format!(
+        "# Generated by paulson-8-aot. Regenerate rather than edit.\n\n\
+         [package]\n\
+         name = \"{package}\"\n\
+         version = \"0.1.0\"\n\
+         edition = \"2021\"\n\
+         publish = false\n\n\
+         [[bin]]\n\
+         name = \"{package}\"\n\
+         path = \"src/main.rs\"\n\
+         required-features = [\"window\"]\n\n\
+         [dependencies]\n\
+         paulson-8-runtime = {{ path = '{runtime}', default-features = false }}\n\n\
+         [features]\n\
+         default = [\"window\"]\n\
+         window = [\"paulson-8-runtime/window\"]\n\n\
+         # Standalone even when generated inside another workspace\n\
+         [workspace]\n",
+        package = package,
+        runtime = runtime.display(),
+    );
+    let main = format!(
+        "// Generated by paulson-8-aot, plays {name} in a window. Regenerate rather than edit.\n\n\
+         mod program;\n\n\
+         fn main() {{\n\
+         \x20   paulson_8_runtime::window::<program::{name}>();\n\
+         }}\n",
+        name = name,
+    );
+
+    vec![
+        ("Cargo.toml", manifest),
+        ("src/main.rs", main),
+        ("src/program.rs", translate(rom, name)),
+    ]
+}
+
+// SpaceInvaders becomes space-invaders
+fn package_name(name: &str) -> String {
+    let mut package = String::new();
+    for (n, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && n > 0 {
+            package.push('-');
+        }
+        package.push(c.to_ascii_lowercase());
+    }
+    package
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::testing::rom;
+
+    #[test]
+    fn walks_reachable_code() {
+        let rom = rom(&[
+            0x2208, // 200 CALL 208
+            0x3001, // 202 SE V0, 1
+            0x120C, // 204 JP 20C
+            0x00E0, // 206 CLS when skipped to
+            0x7001, // 208 ADD V0, 1
+            0x00EE, // 20A RET
+            0xB300, // 20C JP V0 + 300, not followed
+            0xFFFF, // 20E never reached
+        ]);
+        let (code, leaders) = walk(&Rom(&rom));
+        assert_eq!(
+            code.into_iter().collect::<Vec<u16>>(),
+            vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C]
+        );
+        assert_eq!(
+            leaders.into_iter().collect::<Vec<u16>>(),
+            vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20C]
+        );
+    }
+
+    #[test]
+    fn blocks_end_at_leaders_and_control_flow() {
+        let rom = rom(&[0x6005, 0x7001, 0xD015, 0x8014, 0x1200]);
+        let rom = Rom(&rom);
+        let (code, leaders) = walk(&rom);
+        let (body, len) = block(&rom, &code, &leaders, 0x200);
+
+        assert_eq!(len, 5);
+        // The native ones get ticked before DRW goes through the interpreter
+        let tick = body.find("chip8.tick_timers(2);").unwrap();
+        let draw = body.find("chip8.execute_at(0x204, 0xD015)?;").unwrap();
+        assert!(tick < draw);
+        assert!(body.contains("chip8.set_program_counter(0x200);"));
+    }
+
+    #[test]
+    fn long_runs_are_split() {
+        let mut words = vec![0x7001; 40];
+        words.push(0x1200);
+        let source = translate(&rom(&words), "Counter");
+        assert!(source.contains("0x200 => (16, block_200),"));
+        assert!(source.contains("0x220 => (16, block_220),"));
+        assert!(source.contains("0x240 => (9, block_240),"));
+    }
+
+    #[test]
+    fn packages_build_on_their_own() {
+        let files = package(&rom(&[0x1200]), "SpaceInvaders", Path::new("/runtime"));
+        let paths = files.iter().map(|(path, _)| *path).collect::<Vec<&str>>();
+        assert_eq!(paths, ["Cargo.toml", "src/main.rs", "src/program.rs"]);
+
+        let manifest = &files[0].1;
+        assert!(manifest.contains("name = \"space-invaders\""));
+        assert!(manifest.contains("paulson-8-runtime = { path = '/runtime'"));
+        assert!(manifest.contains("\n[workspace]\n"));
+        assert!(files[1].1.contains("window::<program::SpaceInvaders>()"));
+    }
+}
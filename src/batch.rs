@@ -0,0 +1,549 @@
+// Steps many machines at once across every core, for fuzzing and RL rollouts where
+// thousands of instances run the same few frames and then get looked at.
+//
+// Nothing allocates once a batch is made. Each frame is the key changes since the
+// last one then `Chip8::run`, and screens are packed into `Observation`s after the
+// last frame of a `run` rather than every frame. The JIT is turned off, every machine
+// would map and compile its own code.
+//
+// The layout is how the machines are kept. `Instances` is an array of whole machines.
+// `Columns` takes them apart into an array per piece, every machine's V0-VF together,
+// then every I, PC, stack, timer and memory, with the keys, screens and faults in
+// arrays of their own too. To run, a chunk of machines at a time is swapped through a
+// scratch `Chip8` on each thread. `cargo bench --bench batch --features batch`
+// compares the two.
+
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::env::Observation;
+use crate::frontend::DEFAULT_CYCLES_PER_FRAME;
+use crate::interpreter::{self, Chip8, Error, Parts, Rest};
+
+// Machines swapped through each scratch machine in a `Columns` run
+const CHUNK: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    // An array of machines, each with its keys, screen and fault
+    Instances,
+    // An array per piece of a machine, and of keys, of screens and of faults
+    Columns,
+}
+
+#[derive(Clone)]
+struct Instance {
+    chip8: Chip8,
+    keys: u16,
+    screen: Observation,
+    fault: Option<Error>,
+}
+
+#[derive(Clone)]
+struct Columns {
+    // One per chunk, what the machines in it are run on
+    scratch: Vec<Chip8>,
+    registers: Vec<[u8; 16]>,
+    index: Vec<u16>,
+    program_counter: Vec<u16>,
+    stack: Vec<[u16; 16]>,
+    stack_pointer: Vec<u8>,
+    delay_timer: Vec<u8>,
+    sound_timer: Vec<u8>,
+    memory: Vec<[u8; 4096]>,
+    rest: Vec<Rest>,
+    keys: Vec<u16>,
+    screens: Vec<Observation>,
+    faults: Vec<Option<Error>>,
+}
+
+// The machine pieces of some run of instances in `Columns`
+struct Pieces<'a> {
+    registers: &'a mut [[u8; 16]],
+    index: &'a mut [u16],
+    program_counter: &'a mut [u16],
+    stack: &'a mut [[u16; 16]],
+    stack_pointer: &'a mut [u8],
+    delay_timer: &'a mut [u8],
+    sound_timer: &'a mut [u8],
+    memory: &'a mut [[u8; 4096]],
+    rest: &'a mut [Rest],
+}
+
+impl Pieces<'_> {
+    fn parts(&mut self, instance: usize) -> Parts<'_> {
+        Parts {
+            registers: &mut self.registers[instance],
+            index: &mut self.index[instance],
+            program_counter: &mut self.program_counter[instance],
+            stack: &mut self.stack[instance],
+            stack_pointer: &mut self.stack_pointer[instance],
+            delay_timer: &mut self.delay_timer[instance],
+            sound_timer: &mut self.sound_timer[instance],
+            memory: &mut self.memory[instance],
+            rest: &mut self.rest[instance],
+        }
+    }
+}
+
+impl Columns {
+    fn new(machines: Vec<Chip8>, screens: Vec<Observation>) -> Columns {
+        let count = machines.len();
+        let mut columns = Columns {
+            scratch: (0..count.div_ceil(CHUNK)).map(|_| scratch()).collect(),
+            registers: vec![[0; 16]; count],
+            index: vec![0; count],
+            program_counter: vec![0; count],
+            stack: vec![[0; 16]; count],
+            stack_pointer: vec![0; count],
+            delay_timer: vec![0; count],
+            sound_timer: vec![0; count],
+            memory: vec![[0; 4096]; count],
+            rest: vec![Rest::default(); count],
+            keys: vec![0; count],
+            screens,
+            faults: vec![None; count],
+        };
+        for (instance, mut chip8) in machines.into_iter().enumerate() {
+            chip8.swap_parts(columns.pieces().parts(instance));
+        }
+        columns
+    }
+
+    fn pieces(&mut self) -> Pieces<'_> {
+        Pieces {
+            registers: &mut self.registers,
+            index: &mut self.index,
+            program_counter: &mut self.program_counter,
+            stack: &mut self.stack,
+            stack_pointer: &mut self.stack_pointer,
+            delay_timer: &mut self.delay_timer,
+            sound_timer: &mut self.sound_timer,
+            memory: &mut self.memory,
+            rest: &mut self.rest,
+        }
+    }
+
+    fn machine(&self, instance: usize) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        let mut pieces = (
+            self.registers[instance],
+            self.index[instance],
+            self.program_counter[instance],
+            self.stack[instance],
+            self.stack_pointer[instance],
+            self.delay_timer[instance],
+            self.sound_timer[instance],
+            self.memory[instance],
+            self.rest[instance].clone(),
+        );
+        chip8.swap_parts(Parts {
+            registers: &mut pieces.0,
+            index: &mut pieces.1,
+            program_counter: &mut pieces.2,
+            stack: &mut pieces.3,
+            stack_pointer: &mut pieces.4,
+            delay_timer: &mut pieces.5,
+            sound_timer: &mut pieces.6,
+            memory: &mut pieces.7,
+            rest: &mut pieces.8,
+        });
+        chip8
+    }
+
+    fn edit(&mut self, instance: usize, edit: impl FnOnce(&mut Chip8)) {
+        let mut scratch = std::mem::take(&mut self.scratch[0]);
+        scratch.swap_parts(self.pieces().parts(instance));
+        edit(&mut scratch);
+        scratch.swap_parts(self.pieces().parts(instance));
+        self.scratch[0] = scratch;
+    }
+}
+
+fn scratch() -> Chip8 {
+    #[allow(unused_mut)]
+    let mut chip8 = Chip8::new();
+    #[cfg(feature = "jit")]
+    chip8.set_jit(false);
+    chip8
+}
+
+#[derive(Clone)]
+enum Storage {
+    Instances(Vec<Instance>),
+    Columns(Box<Columns>),
+}
+
+// How much a `run` got through
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Throughput {
+    pub instances: usize,
+    pub frames: u64,
+    // Frames that faulted count as none
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn frames_per_second(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for Throughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} instances, {} frames in {:.3}s, {:.0} frames/s, {:.0} instructions/s",
+            self.instances,
+            self.frames,
+            self.elapsed.as_secs_f64(),
+            self.frames_per_second(),
+            self.instructions_per_second()
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct Batch {
+    storage: Storage,
+    cycles_per_frame: usize,
+}
+
+impl Batch {
+    // `count` machines all running `rom`
+    pub fn new(rom: &[u8], count: usize, layout: Layout) -> Result<Batch, Error> {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(rom)?;
+        Ok(Batch::from_machines(vec![chip8; count], layout))
+    }
+
+    // Machines set up however they need to be, different ROMs, seeds or quirks
+    pub fn from_machines(machines: Vec<Chip8>, layout: Layout) -> Batch {
+        #[cfg(feature = "jit")]
+        let machines = machines
+            .into_iter()
+            .map(|mut chip8| {
+                chip8.set_jit(false);
+                chip8
+            })
+            .collect::<Vec<Chip8>>();
+        let screens = machines
+            .iter()
+            .map(|chip8| Observation::of(chip8.video()))
+            .collect::<Vec<Observation>>();
+        let storage = match layout {
+            Layout::Instances => Storage::Instances(
+                machines
+                    .into_iter()
+                    .zip(screens)
+                    .map(|(chip8, screen)| Instance {
+                        chip8,
+                        keys: 0,
+                        screen,
+                        fault: None,
+                    })
+                    .collect(),
+            ),
+            Layout::Columns => Storage::Columns(Box::new(Columns::new(machines, screens))),
+        };
+        Batch {
+            storage,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+        }
+    }
+
+    pub fn cycles_per_frame(mut self, cycles: usize) -> Self {
+        self.cycles_per_frame = cycles;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Instances(instances) => instances.len(),
+            Storage::Columns(columns) => columns.keys.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn layout(&self) -> Layout {
+        match self.storage {
+            Storage::Instances(_) => Layout::Instances,
+            Storage::Columns(_) => Layout::Columns,
+        }
+    }
+
+    // A copy of an instance as it is now, `Columns` has no whole machine to lend out
+    pub fn machine(&self, instance: usize) -> Chip8 {
+        match &self.storage {
+            Storage::Instances(instances) => instances[instance].chip8.clone(),
+            Storage::Columns(columns) => columns.machine(instance),
+        }
+    }
+
+    // For setting up one instance differently, say writing a test number to memory
+    pub fn edit(&mut self, instance: usize, edit: impl FnOnce(&mut Chip8)) {
+        match &mut self.storage {
+            Storage::Instances(instances) => edit(&mut instances[instance].chip8),
+            Storage::Columns(columns) => columns.edit(instance, edit),
+        }
+    }
+
+    // Keys held from the next frame on, bit n for key n
+    pub fn set_keys(&mut self, instance: usize, keys: u16) {
+        match &mut self.storage {
+            Storage::Instances(instances) => instances[instance].keys = keys,
+            Storage::Columns(columns) => columns.keys[instance] = keys,
+        }
+    }
+
+    // The screen as of the end of the last `run`
+    pub fn screen(&self, instance: usize) -> &Observation {
+        match &self.storage {
+            Storage::Instances(instances) => &instances[instance].screen,
+            Storage::Columns(columns) => &columns.screens[instance],
+        }
+    }
+
+    // What stopped an instance, it sits out every `run` after
+    pub fn fault(&self, instance: usize) -> Option<Error> {
+        match &self.storage {
+            Storage::Instances(instances) => instances[instance].fault,
+            Storage::Columns(columns) => columns.faults[instance],
+        }
+    }
+
+    // Every instance that hasn't faulted runs `frames` frames, spread over rayon's
+    // thread pool
+    pub fn run(&mut self, frames: usize) -> Throughput {
+        let cycles = self.cycles_per_frame;
+        let start = Instant::now();
+        let (ran, instructions) = match &mut self.storage {
+            Storage::Instances(instances) => instances
+                .par_iter_mut()
+                .map(|instance| {
+                    let ran = advance(
+                        &mut instance.chip8,
+                        instance.keys,
+                        &mut instance.fault,
+                        frames,
+                        cycles,
+                    );
+                    instance.screen = Observation::of(instance.chip8.video());
+                    ran
+                })
+                .reduce(|| (0, 0), add),
+            Storage::Columns(columns) => {
+                let machines = (
+                    columns.registers.par_chunks_mut(CHUNK),
+                    columns.index.par_chunks_mut(CHUNK),
+                    columns.program_counter.par_chunks_mut(CHUNK),
+                    columns.stack.par_chunks_mut(CHUNK),
+                    columns.stack_pointer.par_chunks_mut(CHUNK),
+                    columns.delay_timer.par_chunks_mut(CHUNK),
+                    columns.sound_timer.par_chunks_mut(CHUNK),
+                    columns.memory.par_chunks_mut(CHUNK),
+                    columns.rest.par_chunks_mut(CHUNK),
+                );
+                let around = (
+                    columns.keys.par_chunks(CHUNK),
+                    columns.screens.par_chunks_mut(CHUNK),
+                    columns.faults.par_chunks_mut(CHUNK),
+                );
+                (columns.scratch.par_iter_mut(), machines, around)
+                    .into_par_iter()
+                    .map(|(chip8, machines, (keys, screens, faults))| {
+                        let mut pieces = Pieces {
+                            registers: machines.0,
+                            index: machines.1,
+                            program_counter: machines.2,
+                            stack: machines.3,
+                            stack_pointer: machines.4,
+                            delay_timer: machines.5,
+                            sound_timer: machines.6,
+                            memory: machines.7,
+                            rest: machines.8,
+                        };
+                        let mut ran = (0, 0);
+                        for instance in 0..keys.len() {
+                            if faults[instance].is_some() {
+                                continue;
+                            }
+                            chip8.swap_parts(pieces.parts(instance));
+                            let fault = &mut faults[instance];
+                            ran = add(ran, advance(chip8, keys[instance], fault, frames, cycles));
+                            screens[instance] = Observation::of(chip8.video());
+                            chip8.swap_parts(pieces.parts(instance));
+                        }
+                        ran
+                    })
+                    .reduce(|| (0, 0), add)
+            }
+        };
+        Throughput {
+            instances: self.len(),
+            frames: ran,
+            instructions,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+fn add(a: (u64, u64), b: (u64, u64)) -> (u64, u64) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+// Frames and instructions one instance got through
+fn advance(
+    chip8: &mut Chip8,
+    keys: u16,
+    fault: &mut Option<Error>,
+    frames: usize,
+    cycles: usize,
+) -> (u64, u64) {
+    let pressed = core::array::from_fn(|key| keys & 1 << key != 0);
+    let mut ran = 0;
+    for _ in 0..frames {
+        if fault.is_some() {
+            break;
+        }
+        for event in interpreter::key_events(chip8.pressed_keys(), pressed) {
+            chip8.handle_key(event);
+        }
+        match chip8.run(cycles) {
+            Ok(()) => ran += 1,
+            Err(e) => *fault = Some(e),
+        }
+    }
+    (ran, ran * cycles as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::{frame, FixedClock, Input, NullAudio, NullDisplay};
+    use crate::interpreter::testing::{program, rom};
+    use crate::interpreter::{Quirks, Random};
+
+    // Draws whichever digit key is held, at a spot that moves along every frame
+    fn digits() -> Vec<u8> {
+        rom(&[
+            0x6000, 0x6100, 0xE09E, 0x120E, 0xF029, 0xD125, 0x7104, 0x7001, 0x4010, 0x6000, 0x1204,
+        ])
+    }
+
+    struct Held(u16);
+
+    impl Input for Held {
+        fn pressed(&mut self) -> [bool; 16] {
+            core::array::from_fn(|key| self.0 & 1 << key != 0)
+        }
+    }
+
+    #[test]
+    fn layouts_match_running_one_at_a_time() {
+        let keys = |instance: usize| 1 << (instance % 16) | 1 << (instance * 7 % 16);
+        let mut expected = Vec::new();
+        for instance in 0..40 {
+            let mut chip8 = Chip8::new();
+            chip8.load_bytes(&digits()).unwrap();
+            for _ in 0..30 {
+                frame(
+                    &mut chip8,
+                    &mut Held(keys(instance)),
+                    &mut FixedClock(7),
+                    &mut NullAudio::default(),
+                    &mut NullDisplay::default(),
+                )
+                .unwrap();
+            }
+            expected.push(chip8);
+        }
+
+        for layout in [Layout::Instances, Layout::Columns] {
+            let mut batch = Batch::new(&digits(), 40, layout)
+                .unwrap()
+                .cycles_per_frame(7);
+            assert_eq!(batch.layout(), layout);
+            for instance in 0..batch.len() {
+                batch.set_keys(instance, keys(instance));
+            }
+            let throughput = batch.run(10);
+            batch.run(20);
+            assert_eq!(throughput.frames, 40 * 10);
+            assert_eq!(throughput.instructions, 40 * 10 * 7);
+
+            for (instance, chip8) in expected.iter().enumerate() {
+                assert_eq!(batch.machine(instance).registers(), chip8.registers());
+                assert_eq!(batch.machine(instance).video(), chip8.video());
+                assert_eq!(*batch.screen(instance), Observation::of(chip8.video()));
+                assert_ne!(*batch.screen(instance), Observation::of(&[0; 2048]));
+            }
+        }
+    }
+
+    #[test]
+    fn faulted_instances_sit_out() {
+        for layout in [Layout::Instances, Layout::Columns] {
+            let mut batch = Batch::new(&digits(), 3, layout).unwrap();
+            // RET with nothing to return to, a frame in
+            batch.edit(1, |chip8| chip8.write_memory(0x200, &[0x00, 0xEE]));
+
+            let throughput = batch.run(5);
+            assert_eq!(throughput.frames, 5 + 5);
+            assert_eq!(
+                batch.fault(1),
+                Some(Error::StackUnderflow { address: 0x200 })
+            );
+            assert_eq!(batch.fault(0), None);
+            assert_eq!(batch.run(5).frames, 5 + 5);
+        }
+    }
+
+    fn state(chip8: &Chip8) -> Vec<u8> {
+        let mut state = vec![0; Chip8::STATE_SIZE];
+        chip8.save_state(&mut state).unwrap();
+        state
+    }
+
+    #[test]
+    fn columns_keep_every_piece() {
+        // Calls into a subroutine that fills V0, the timers and I from CXKK then waits
+        let machines = (0..100)
+            .map(|instance| {
+                let mut chip8 = program(&[0x2204, 0x0000, 0xC0FF, 0xF015, 0xF018, 0xA300, 0x120C]);
+                chip8.set_random(Random::Seeded(instance));
+                chip8.set_quirks(Quirks {
+                    wait_for_release: instance % 2 == 0,
+                });
+                chip8.run(instance as usize % 7).unwrap();
+                chip8
+            })
+            .collect::<Vec<Chip8>>();
+
+        let mut columns = Batch::from_machines(machines.clone(), Layout::Columns);
+        let mut instances = Batch::from_machines(machines.clone(), Layout::Instances);
+        for (instance, chip8) in machines.iter().enumerate() {
+            let taken_apart = columns.machine(instance);
+            assert_eq!(state(&taken_apart), state(chip8));
+            assert_eq!(taken_apart.quirks(), chip8.quirks());
+        }
+
+        columns.run(3);
+        instances.run(3);
+        for instance in 0..machines.len() {
+            assert_eq!(
+                state(&columns.machine(instance)),
+                state(&instances.machine(instance))
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Translates a ROM into a Rust program ahead of time, see paulson_8::aot. The output
+// is a crate of its own that builds against paulson-8-runtime:
+//
+//   paulson-8-aot game.ch8 -o game && cargo run --release --manifest-path game/Cargo.toml
+
+use std::path::{Path, PathBuf};
+
+use paulson_8::aot::package;
+
+const USAGE: &str = "Usage: paulson-8-aot <rom> [options]
+
+Options:
+    -o, --output <dir>   Where to write the crate (default the ROM's name)
+    --name <name>        Type name for the program (default from the file name)
+    --runtime <path>     paulson-8-runtime to build against (default the one in
+                         this checkout)";
+
+struct Options {
+    rom: PathBuf,
+    output: Option<PathBuf>,
+    name: Option<String>,
+    runtime: Option<PathBuf>,
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut rom = None;
+    let mut output = None;
+    let mut name = None;
+    let mut runtime = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let value = args.next().ok_or("Missing value for --output")?;
+                output = Some(PathBuf::from(value));
+            }
+            "--name" => name = Some(args.next().ok_or("Missing value for --name")?),
+            "--runtime" => {
+                let value = args.next().ok_or("Missing value for --runtime")?;
+                runtime = Some(PathBuf::from(value));
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        rom: rom.ok_or("No ROM given")?,
+        output,
+        name,
+        runtime,
+    })
+}
+
+// "space-invaders.ch8" becomes SpaceInvaders
+fn type_name(rom: &Path) -> String {
+    let stem = rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word[..1].to_ascii_uppercase() + &word[1..])
+        .collect::<String>();
+
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("Rom{}", name),
+    }
+}
+
+fn main() {
+    let options = match parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let rom = std::fs::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", options.rom.display(), e);
+        std::process::exit(2);
+    });
+    if rom.len() > 4096 - 0x200 {
+        eprintln!("{} is too big to be a CHIP-8 ROM", options.rom.display());
+        std::process::exit(2);
+    }
+
+    let name = options.name.unwrap_or_else(|| type_name(&options.rom));
+    let output = options
+        .output
+        .unwrap_or_else(|| options.rom.with_extension(""));
+    // Generated crates can live anywhere, so the path has to be absolute
+    let runtime = options
+        .runtime
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("runtime"));
+    let runtime = std::path::absolute(&runtime).unwrap_or(runtime);
+
+    for (path, contents) in package(&rom, &name, &runtime) {
+        let path = output.join(path);
+        let written = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, contents));
+        if let Err(e) = written {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use paulson_8::headless::{self, Options};
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, headless::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = headless::run(&options) {
+        eprintln!("Failed to run {}: {}", options.rom.display(), e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,72 @@
+// Compares two instruction traces and shows where they first differ. Either one can
+// be a log exported from another emulator, see interpreter::trace for the formats.
+
+use std::path::PathBuf;
+
+use paulson_8::interpreter::trace::{diverge, Trace};
+
+const USAGE: &str = "Usage: paulson-8-trace-diff <expected> <actual> [options]
+
+Options:
+    --context <n>  Steps to show before the divergence (default 5)";
+
+struct Options {
+    expected: PathBuf,
+    actual: PathBuf,
+    context: usize,
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut context = 5;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("Missing value for --context")?;
+                context = value.parse().map_err(|_| "--context expects a number")?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ if paths.len() < 2 => paths.push(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    if paths.len() < 2 {
+        return Err("Two traces are needed".to_string());
+    }
+    let actual = paths.pop().unwrap();
+    let expected = paths.pop().unwrap();
+    Ok(Options {
+        expected,
+        actual,
+        context,
+    })
+}
+
+fn main() {
+    let options = match parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let load = |path: &PathBuf| {
+        Trace::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    };
+    let expected = load(&options.expected);
+    let actual = load(&options.actual);
+
+    match diverge(&expected, &actual, options.context) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            std::process::exit(1);
+        }
+        None => println!("Traces match for all {} steps", expected.steps.len()),
+    }
+}
@@ -0,0 +1,74 @@
+// Generates single step test vectors, one JSON file per opcode pattern, so other
+// chip-8 implementations can be checked against this one.
+
+use std::path::PathBuf;
+
+use paulson_8::interpreter::vectors::{Vector, PATTERNS};
+use rand::{rngs::StdRng, SeedableRng};
+
+const USAGE: &str = "Usage: paulson-8-vectors <output dir> [options]
+
+Options:
+    --count <n>  Vectors per opcode (default 1000)
+    --seed <n>   Seed for the random states (default 0)";
+
+struct Options {
+    dir: PathBuf,
+    count: usize,
+    seed: u64,
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut dir = None;
+    let mut count = 1000;
+    let mut seed = 0;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+
+        match arg.as_str() {
+            "--count" => count = value()?.parse().map_err(|_| "--count expects a number")?,
+            "--seed" => seed = value()?.parse().map_err(|_| "--seed expects a number")?,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ if dir.is_none() => dir = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        dir: dir.ok_or("No output directory given")?,
+        count,
+        seed,
+    })
+}
+
+fn main() {
+    let options = match parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let result = std::fs::create_dir_all(&options.dir).and_then(|_| {
+        for pattern in PATTERNS {
+            let vectors = (0..options.count)
+                .map(|number| Vector::generate(&mut rng, pattern, number))
+                .collect::<Vec<Vector>>();
+
+            let path = options.dir.join(format!("{}.json", pattern));
+            std::fs::write(&path, serde_json::to_string(&vectors)?)?;
+        }
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        eprintln!("Failed to write vectors: {}", e);
+        std::process::exit(1);
+    }
+}
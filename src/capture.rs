@@ -0,0 +1,231 @@
+// Screenshots and recordings of the chip-8 display.
+//
+// Recordings keep the raw video buffers around and only get upscaled when saved,
+// a buffer is only 2KiB so even a few minutes at 60 fps fits in memory.
+
+use std::{borrow::Cow, fs::File, io, io::BufWriter, path::Path};
+
+use crate::upscale::{Image, Palette, Pipeline, Rgba};
+
+pub const FPS: u16 = 60;
+
+pub fn save_png(path: &Path, image: &Image) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.to_rgba8())?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub struct Recorder {
+    width: usize,
+    height: usize,
+    frames: Vec<Vec<u8>>,
+}
+
+impl Recorder {
+    pub fn new(width: usize, height: usize) -> Self {
+        Recorder {
+            width,
+            height,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, video: &[u8]) {
+        assert_eq!(
+            video.len(),
+            self.width * self.height,
+            "Video buffer size mismatch"
+        );
+        self.frames.push(video.to_vec());
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // The format is picked from the extension, .gif for GIF and anything else is APNG
+    pub fn save(&self, path: &Path, pipeline: &Pipeline, palette: Palette) -> io::Result<()> {
+        if self.frames.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Nothing was recorded",
+            ));
+        }
+
+        let images = self
+            .frames
+            .iter()
+            .map(|video| pipeline.render(video, self.width, self.height, palette))
+            .collect::<Vec<Image>>();
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gif") => save_gif(path, &images),
+            _ => save_apng(path, &images),
+        }
+    }
+}
+
+fn save_apng(path: &Path, images: &[Image]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let (width, height) = (images[0].width as u32, images[0].height as u32);
+
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(images.len() as u32, 0)?;
+    // APNG can store the exact frame rate, no rounding needed
+    encoder.set_frame_delay(1, FPS)?;
+
+    let mut writer = encoder.write_header()?;
+    for image in images {
+        writer.write_image_data(&image.to_rgba8())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn save_gif(path: &Path, images: &[Image]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let (width, height) = (images[0].width as u16, images[0].height as u16);
+
+    // Our filters only ever produce a handful of colors so a global palette always fits
+    let mut colors: Vec<Rgba> = Vec::new();
+    for pixel in images.iter().flat_map(|image| image.pixels.iter()) {
+        if !colors.contains(pixel) {
+            if colors.len() == 256 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Too many colors for a GIF",
+                ));
+            }
+            colors.push(*pixel);
+        }
+    }
+    let global_palette = colors
+        .iter()
+        .flat_map(|color| color[..3].iter().copied())
+        .collect::<Vec<u8>>();
+
+    let mut encoder =
+        gif::Encoder::new(file, width, height, &global_palette).map_err(io::Error::other)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(io::Error::other)?;
+
+    for (i, image) in images.iter().enumerate() {
+        let indices = image
+            .pixels
+            .iter()
+            .map(|pixel| colors.iter().position(|color| color == pixel).unwrap() as u8)
+            .collect::<Vec<u8>>();
+
+        let frame = gif::Frame {
+            width,
+            height,
+            delay: gif_delay(i),
+            buffer: Cow::Owned(indices),
+            ..Default::default()
+        };
+        encoder.write_frame(&frame).map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+// GIF delays are in hundredths of a second so 60 fps can't be stored directly,
+// instead every three frames are spread over five hundredths (2, 2, 1)
+fn gif_delay(frame: usize) -> u16 {
+    match frame % 3 {
+        2 => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::upscale::Filter;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("paulson-8-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn gif_delay_averages_to_60_fps() {
+        let total: u16 = (0..60).map(gif_delay).sum();
+        assert_eq!(total, 100);
+    }
+
+    #[test]
+    fn screenshot_round_trip() {
+        let path = temp_path("screenshot.png");
+        let image = Pipeline::new().then(Filter::Nearest(2)).render(
+            &[0xFF, 0x00, 0x00, 0xFF],
+            2,
+            2,
+            Palette::default(),
+        );
+        save_png(&path, &image).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((info.width, info.height), (4, 4));
+        assert_eq!(&buffer[..info.buffer_size()], &image.to_rgba8()[..]);
+    }
+
+    #[test]
+    fn record_apng_and_gif() {
+        let mut recorder = Recorder::new(2, 1);
+        assert!(recorder
+            .save(
+                &temp_path("empty.png"),
+                &Pipeline::new(),
+                Palette::default()
+            )
+            .is_err());
+
+        recorder.push(&[0xFF, 0x00]);
+        recorder.push(&[0x00, 0xFF]);
+        recorder.push(&[0xFF, 0xFF]);
+        assert_eq!(recorder.len(), 3);
+
+        let apng = temp_path("recording.png");
+        recorder
+            .save(&apng, &Pipeline::new(), Palette::default())
+            .unwrap();
+        let decoder = png::Decoder::new(File::open(&apng).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let animation = reader.info().animation_control.unwrap();
+        std::fs::remove_file(&apng).unwrap();
+        assert_eq!(animation.num_frames, 3);
+
+        let gif_path = temp_path("recording.gif");
+        recorder
+            .save(&gif_path, &Pipeline::new(), Palette::default())
+            .unwrap();
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&gif_path).unwrap()).unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, gif_delay(frames));
+            frames += 1;
+        }
+        std::fs::remove_file(&gif_path).unwrap();
+        assert_eq!(frames, 3);
+    }
+}
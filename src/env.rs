@@ -0,0 +1,349 @@
+// Environments for training agents, in the style of Gym. `reset` starts an episode
+// and gives back what's on screen, `step` holds a set of keys down for a few frames
+// and says what that was worth and whether the episode is over.
+//
+// What counts as reward and the end of a game differs for every ROM, so they come
+// from functions over memory. Most games keep their score in BCD for FX33 to show:
+//
+//   let env = Env::new(&rom)?
+//       .score(env::bcd(0x2F0, 3))
+//       .done(|memory| memory[0x2F8] == 0)
+//       .frame_skip(4)
+//       .sticky_actions(0.25);
+//
+// The reward for a step is how much the score went up. Environments clone cheaply,
+// the ROM and the functions are shared, so one can be set up and reset then cloned
+// for every worker.
+
+use std::sync::Arc;
+
+use crate::frontend::{frame, Display, FixedClock, Input, NullAudio, DEFAULT_CYCLES_PER_FRAME};
+use crate::interpreter::{Chip8, Error, Quirks, Random};
+
+pub type Score = Arc<dyn Fn(&[u8]) -> i64 + Send + Sync>;
+pub type Done = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+// A score written by FX33, one decimal digit per byte from `address` on with the
+// most significant first
+pub fn bcd(address: u16, digits: usize) -> impl Fn(&[u8]) -> i64 + Send + Sync + 'static {
+    move |memory| {
+        (0..digits).fold(0, |score, digit| {
+            score * 10 + memory[(address as usize + digit) % memory.len()] as i64
+        })
+    }
+}
+
+// A score that's just a byte, like a count of lives or a level
+pub fn byte(address: u16) -> impl Fn(&[u8]) -> i64 + Send + Sync + 'static {
+    move |memory| memory[address as usize % memory.len()] as i64
+}
+
+// The screen one bit per pixel, a row at a time from the top with the leftmost pixel
+// in the top bit of each byte, the way sprites are stored
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Observation(pub [u8; Observation::SIZE]);
+
+impl Observation {
+    pub const SIZE: usize = Chip8::VIDEO_WIDTH as usize * Chip8::VIDEO_HEIGHT as usize / 8;
+
+    // From one byte per pixel, like `Chip8::video`
+    pub fn of(video: &[u8]) -> Self {
+        let mut bits = [0; Observation::SIZE];
+        for (byte, pixels) in bits.iter_mut().zip(video.chunks(8)) {
+            *byte = pixels
+                .iter()
+                .fold(0, |byte, pixel| byte << 1 | (*pixel != 0) as u8);
+        }
+        Observation(bits)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let n = x + y * Chip8::VIDEO_WIDTH as usize;
+        self.0[n / 8] & 0x80 >> (n % 8) != 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    // What stopped the ROM if it faulted, which ends the episode too
+    pub fault: Option<Error>,
+}
+
+// Keys for `frame`, bit n for key n
+struct Keys(u16);
+
+impl Input for Keys {
+    fn pressed(&mut self) -> [bool; 16] {
+        core::array::from_fn(|key| self.0 & 1 << key != 0)
+    }
+}
+
+// Observations are read off the machine once a step is over, so frames in between
+// aren't copied anywhere
+struct Blind;
+
+impl Display for Blind {
+    fn present(&mut self, _video: &[u8], _width: usize, _height: usize) {}
+}
+
+#[derive(Clone)]
+pub struct Env {
+    rom: Arc<[u8]>,
+    score: Option<Score>,
+    done: Option<Done>,
+    frame_skip: usize,
+    // Out of 256, the chance each frame of carrying on with the last action
+    sticky: u16,
+    cycles_per_frame: usize,
+    max_frames: Option<usize>,
+
+    chip8: Chip8,
+    // For sticky actions, separate from the machine's so they don't change what the
+    // ROM sees
+    random: Random,
+    action: u16,
+    last_score: i64,
+    frames: usize,
+    over: bool,
+}
+
+impl Env {
+    // Fails if the ROM doesn't fit. Starts out reset with seed 0
+    pub fn new(rom: &[u8]) -> Result<Env, Error> {
+        let mut env = Env {
+            rom: rom.into(),
+            score: None,
+            done: None,
+            frame_skip: 1,
+            sticky: 0,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            max_frames: None,
+            chip8: Chip8::new(),
+            random: Random::Seeded(0),
+            action: 0,
+            last_score: 0,
+            frames: 0,
+            over: false,
+        };
+        env.chip8.load_bytes(&env.rom)?;
+        env.reset(0);
+        Ok(env)
+    }
+
+    // What the rewards are the change in, no reward at all without one
+    pub fn score(mut self, score: impl Fn(&[u8]) -> i64 + Send + Sync + 'static) -> Self {
+        self.score = Some(Arc::new(score));
+        self.last_score = score_of(&self.score, &self.chip8);
+        self
+    }
+
+    // When the game is over, checked after every frame
+    pub fn done(mut self, done: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.done = Some(Arc::new(done));
+        self
+    }
+
+    // Frames each step runs with the same keys, at least 1
+    pub fn frame_skip(mut self, frames: usize) -> Self {
+        self.frame_skip = frames.max(1);
+        self
+    }
+
+    // The chance each frame that the keys from the step before are kept instead, so
+    // agents can't count on frame perfect timing
+    pub fn sticky_actions(mut self, probability: f32) -> Self {
+        self.sticky = (probability.clamp(0.0, 1.0) * 256.0) as u16;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.chip8.set_quirks(quirks);
+        self
+    }
+
+    pub fn cycles_per_frame(mut self, cycles: usize) -> Self {
+        self.cycles_per_frame = cycles;
+        self
+    }
+
+    // Ends episodes that go on longer than this many frames
+    pub fn max_frames(mut self, frames: usize) -> Self {
+        self.max_frames = Some(frames);
+        self
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    // Starts again from the ROM, `seed` decides both what CXKK gives the ROM and which
+    // frames sticky actions stick on
+    pub fn reset(&mut self, seed: u32) -> Observation {
+        let mut chip8 = Chip8::with_quirks(self.chip8.quirks());
+        chip8.set_random(Random::Seeded(seed));
+        chip8
+            .load_bytes(&self.rom)
+            .expect("ROM fitted when the environment was made");
+        self.chip8 = chip8;
+        self.random = Random::Seeded(seed ^ 0x5EED_5EED);
+        self.action = 0;
+        self.last_score = score_of(&self.score, &self.chip8);
+        self.frames = 0;
+        self.over = false;
+        Observation::of(self.chip8.video())
+    }
+
+    // Holds `action` down, bit n for key n, for `frame_skip` frames. Once an episode
+    // is over steps change nothing until `reset`
+    pub fn step(&mut self, action: u16) -> Step {
+        let mut fault = None;
+        let mut audio = NullAudio::default();
+        for _ in 0..self.frame_skip {
+            if self.over {
+                break;
+            }
+            if self.sticky == 0 || self.random.byte() as u16 >= self.sticky {
+                self.action = action;
+            }
+            let mut clock = FixedClock(self.cycles_per_frame);
+            let result = frame(
+                &mut self.chip8,
+                &mut Keys(self.action),
+                &mut clock,
+                &mut audio,
+                &mut Blind,
+            );
+            self.frames += 1;
+
+            fault = result.err();
+            let done = self
+                .done
+                .as_ref()
+                .is_some_and(|done| done(self.chip8.memory()));
+            let out_of_time = self.max_frames.is_some_and(|max| self.frames >= max);
+            self.over = fault.is_some() || done || out_of_time;
+        }
+
+        let score = score_of(&self.score, &self.chip8);
+        let reward = (score - self.last_score) as f32;
+        self.last_score = score;
+        Step {
+            observation: Observation::of(self.chip8.video()),
+            reward,
+            done: self.over,
+            fault,
+        }
+    }
+}
+
+fn score_of(score: &Option<Score>, chip8: &Chip8) -> i64 {
+    score.as_ref().map_or(0, |score| score(chip8.memory()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::testing::{rom, COUNTER};
+
+    // Counts up in V0 while 5 is held, keeping it in BCD at 0x300
+    fn counter() -> Env {
+        Env::new(&rom(COUNTER))
+            .unwrap()
+            .score(bcd(0x300, 3))
+            .done(|memory| bcd(0x300, 3)(memory) >= 100)
+    }
+
+    #[test]
+    fn rewards_follow_the_score() {
+        let mut env = counter();
+        assert_eq!(env.reset(1), Observation([0; Observation::SIZE]));
+
+        let step = env.step(0);
+        assert_eq!((step.reward, step.done), (0.0, false));
+
+        let mut total = 0.0;
+        let mut steps = 0;
+        loop {
+            let step = env.step(1 << 5);
+            assert!(step.reward > 0.0);
+            total += step.reward;
+            steps += 1;
+            if step.done {
+                break;
+            }
+        }
+        assert_eq!(total as i64, bcd(0x300, 3)(env.chip8().memory()));
+        assert!(total >= 100.0 && steps > 1);
+
+        // Nothing moves once it's over
+        assert_eq!(env.step(1 << 5).reward, 0.0);
+        env.reset(1);
+        assert_eq!(env.step(0).reward, 0.0);
+    }
+
+    #[test]
+    fn frame_skip_and_time_limit() {
+        let mut one = counter().max_frames(6);
+        let mut three = counter().frame_skip(3).max_frames(6);
+        let first = (0..3).map(|_| one.step(1 << 5).reward).sum::<f32>();
+        let step = three.step(1 << 5);
+        assert_eq!(step.reward, first);
+        assert!(!step.done);
+        assert!(three.step(0).done);
+
+        assert!(!one.step(0).done && !one.step(0).done);
+        assert!(one.step(0).done);
+    }
+
+    #[test]
+    fn sticky_actions() {
+        // Always sticking means the first action, nothing, is the only one
+        let mut env = counter().sticky_actions(1.0);
+        assert!((0..10).all(|_| env.step(1 << 5).reward == 0.0));
+
+        // Some of the time, so the same seed sticks on the same frames
+        let rewards = |seed| {
+            let mut env = counter().sticky_actions(0.5);
+            env.reset(seed);
+            (0..20)
+                .map(|step| env.step(if step % 2 == 0 { 1 << 5 } else { 0 }).reward)
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(rewards(3), rewards(3));
+        assert_ne!(rewards(3), rewards(4));
+    }
+
+    #[test]
+    fn clones_carry_on_alike() {
+        fn send<T: Send>() {}
+        send::<Env>();
+
+        let mut env = counter().sticky_actions(0.25);
+        env.reset(9);
+        env.step(1 << 5);
+        let mut clone = env.clone();
+        for action in [0, 1 << 5, 1 << 5, 0, 1 << 5] {
+            assert_eq!(env.step(action), clone.step(action));
+        }
+    }
+
+    #[test]
+    fn observations_and_faults() {
+        // Draws the 0 digit in the top left, then returns with nothing on the stack
+        let mut env = Env::new(&rom(&[0xF029, 0xD005, 0x00EE])).unwrap();
+        let step = env.step(0);
+        assert!(step.done);
+        assert_eq!(step.fault, Some(Error::StackUnderflow { address: 0x204 }));
+        let lit = (0..8)
+            .map(|x| step.observation.pixel(x, 1))
+            .collect::<Vec<bool>>();
+        assert_eq!(lit, [true, false, false, true, false, false, false, false]);
+        assert_eq!(step.observation.0[0], 0xF0);
+        assert_eq!(step.observation.0[8], 0x90);
+
+        assert!(Env::new(&[0; 4000]).is_err());
+    }
+}
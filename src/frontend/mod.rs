@@ -0,0 +1,66 @@
+// What the interpreter needs from whatever it's running in: somewhere to show the
+// screen, a buzzer, the state of the 16 keys and how many instructions to run.
+//
+// `frame` drives a `Chip8` through these for one frame, a frontend implements the
+// four traits and calls it from its own loop. The GUI in `app` is one, `null` has
+// implementations that do nothing (or just remember what they were given) for tests
+// and anything else without a window.
+
+pub mod null;
+
+use crate::interpreter::{self, Chip8, Error};
+
+pub use null::{FixedClock, NullAudio, NullDisplay, NullInput};
+
+// What the GUI, headless runner and golden tests run unless told otherwise
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 20;
+
+pub trait Display {
+    // Called once per frame with one byte per pixel, 0xFF for lit and 0 for dark
+    fn present(&mut self, video: &[u8], width: usize, height: usize);
+}
+
+pub trait Audio {
+    // Whether the buzzer should be sounding, called every frame
+    fn buzz(&mut self, on: bool);
+}
+
+pub trait Input {
+    // Chip-8 keys held down right now
+    fn pressed(&mut self) -> [bool; 16];
+}
+
+pub trait Clock {
+    // Instructions to run this frame, 0 leaves the machine paused
+    fn cycles(&mut self) -> usize;
+}
+
+// One frame: keys in, instructions, then sound and picture out. The picture is still
+// presented when the interpreter faults so the state at the fault can be looked at.
+// Paused frames (no cycles) still read the input but don't pass it on, so a key
+// pressed while paused can't end an FX0A once the machine carries on
+pub fn frame(
+    chip8: &mut Chip8,
+    input: &mut impl Input,
+    clock: &mut impl Clock,
+    audio: &mut impl Audio,
+    display: &mut impl Display,
+) -> Result<(), Error> {
+    let pressed = input.pressed();
+    let cycles = clock.cycles();
+    if cycles > 0 {
+        for event in interpreter::key_events(chip8.pressed_keys(), pressed) {
+            chip8.handle_key(event);
+        }
+    }
+
+    let result = chip8.run(cycles);
+
+    audio.buzz(chip8.sound_playing());
+    display.present(
+        chip8.video(),
+        Chip8::VIDEO_WIDTH as usize,
+        Chip8::VIDEO_HEIGHT as usize,
+    );
+    result
+}
@@ -0,0 +1,162 @@
+// Frontend pieces with nothing behind them. They keep what they're given so tests
+// can look at it afterwards, and the input takes whatever keys are set on it.
+
+use super::{Audio, Clock, Display, Input, DEFAULT_CYCLES_PER_FRAME};
+use crate::interpreter::Chip8;
+
+const VIDEO_SIZE: usize = Chip8::VIDEO_WIDTH as usize * Chip8::VIDEO_HEIGHT as usize;
+
+pub struct NullDisplay {
+    // Last presented frame, blank until the first one
+    pub video: [u8; VIDEO_SIZE],
+    pub frames: usize,
+}
+
+impl Default for NullDisplay {
+    fn default() -> Self {
+        NullDisplay {
+            video: [0; VIDEO_SIZE],
+            frames: 0,
+        }
+    }
+}
+
+impl Display for NullDisplay {
+    fn present(&mut self, video: &[u8], _width: usize, _height: usize) {
+        self.video.copy_from_slice(video);
+        self.frames += 1;
+    }
+}
+
+#[derive(Default)]
+pub struct NullAudio {
+    pub on: bool,
+    // Frames the buzzer was on for
+    pub frames: usize,
+}
+
+impl Audio for NullAudio {
+    fn buzz(&mut self, on: bool) {
+        self.on = on;
+        self.frames += on as usize;
+    }
+}
+
+#[derive(Default)]
+pub struct NullInput {
+    pub keys: [bool; 16],
+}
+
+impl Input for NullInput {
+    fn pressed(&mut self) -> [bool; 16] {
+        self.keys
+    }
+}
+
+// Same number of instructions every frame
+pub struct FixedClock(pub usize);
+
+impl Default for FixedClock {
+    fn default() -> Self {
+        FixedClock(DEFAULT_CYCLES_PER_FRAME)
+    }
+}
+
+impl Clock for FixedClock {
+    fn cycles(&mut self) -> usize {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::frame;
+    use super::*;
+    use crate::interpreter::testing::program;
+
+    #[test]
+    fn frames_run_like_the_interpreter() {
+        const SUITE: &[u8] = include_bytes!("../../tests/roms/timendus/chip8-test-suite.ch8");
+        let setup = || {
+            let mut chip8 = Chip8::new();
+            chip8.load_bytes(SUITE).unwrap();
+            chip8.write_memory(0x1FF, &[2]);
+            chip8
+        };
+        let mut framed = setup();
+        let mut direct = setup();
+
+        let (mut input, mut clock) = (NullInput::default(), FixedClock::default());
+        let (mut audio, mut display) = (NullAudio::default(), NullDisplay::default());
+        for _ in 0..100 {
+            frame(
+                &mut framed,
+                &mut input,
+                &mut clock,
+                &mut audio,
+                &mut display,
+            )
+            .unwrap();
+            direct.run(20).unwrap();
+        }
+
+        assert_eq!(display.frames, 100);
+        assert_eq!(display.video, direct.get_video_buffer());
+    }
+
+    #[test]
+    fn keys_and_buzzer() {
+        // Wait for a key then sound the buzzer for that many cycles
+        let mut chip8 = program(&[0xF00A, 0xF018, 0x1204]);
+        let mut input = NullInput::default();
+        let mut clock = FixedClock(4);
+        let (mut audio, mut display) = (NullAudio::default(), NullDisplay::default());
+
+        frame(&mut chip8, &mut input, &mut clock, &mut audio, &mut display).unwrap();
+        input.keys[0x9] = true;
+        frame(&mut chip8, &mut input, &mut clock, &mut audio, &mut display).unwrap();
+        assert!(!audio.on);
+
+        input.keys[0x9] = false;
+        frame(&mut chip8, &mut input, &mut clock, &mut audio, &mut display).unwrap();
+        assert!(audio.on);
+
+        // Paused machines still show their screen
+        frame(
+            &mut chip8,
+            &mut input,
+            &mut FixedClock(0),
+            &mut audio,
+            &mut display,
+        )
+        .unwrap();
+        assert_eq!(display.frames, 4);
+        assert_eq!(audio.frames, 2);
+    }
+
+    #[test]
+    fn paused_frames_take_no_keys() {
+        let mut chip8 = program(&[0xF00A, 0x1202]);
+        let mut input = NullInput::default();
+        let (mut audio, mut display) = (NullAudio::default(), NullDisplay::default());
+        let mut run = |input: &mut NullInput, cycles| {
+            frame(
+                &mut chip8,
+                input,
+                &mut FixedClock(cycles),
+                &mut audio,
+                &mut display,
+            )
+            .unwrap();
+        };
+
+        run(&mut input, 1);
+        input.keys[0x4] = true;
+        run(&mut input, 0);
+        input.keys[0x4] = false;
+        run(&mut input, 0);
+        // Still waiting, the press and release both happened while paused
+        run(&mut input, 4);
+        assert_eq!(chip8.program_counter(), 0x200);
+    }
+}
@@ -0,0 +1,467 @@
+// Golden frame tests: run a ROM headless with scripted input and compare the final
+// screen against an image checked in next to the test.
+//
+// Goldens are either text art, one line per row with '#' lit and '.' unlit, or a plain
+// PBM (P1) when the file ends in .pbm. Set BLESS=1 to write the current screen out as
+// the new golden instead of comparing.
+//
+// Input scripts are one event per line, the frame it happens at then press or release
+// and the chip-8 key in hex:
+//   # Start the game
+//   30 press 5
+//   40 release 5
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::frontend::DEFAULT_CYCLES_PER_FRAME;
+use crate::interpreter::{Chip8, KeyEvent, Random};
+
+const WIDTH: usize = Chip8::VIDEO_WIDTH as usize;
+const HEIGHT: usize = Chip8::VIDEO_HEIGHT as usize;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pixels: Vec<bool>,
+}
+
+impl Frame {
+    pub fn from_video(video: &[u8]) -> Self {
+        assert_eq!(video.len(), WIDTH * HEIGHT, "Video buffer size mismatch");
+        Frame {
+            pixels: video.iter().map(|pixel| *pixel != 0).collect(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[x + y * WIDTH]
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(WIDTH)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.rows() {
+            text.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let rows = text.lines().collect::<Vec<&str>>();
+        if rows.len() != HEIGHT {
+            return Err(format!("Expected {} rows, got {}", HEIGHT, rows.len()));
+        }
+
+        let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != WIDTH {
+                return Err(format!("Row {} isn't {} pixels wide", y, WIDTH));
+            }
+            for c in row.chars() {
+                pixels.push(match c {
+                    '#' => true,
+                    '.' => false,
+                    _ => return Err(format!("Row {}: unexpected '{}'", y, c)),
+                });
+            }
+        }
+        Ok(Frame { pixels })
+    }
+
+    // Plain PBM, 1 is black so lit pixels are 1
+    pub fn to_pbm(&self) -> String {
+        let mut pbm = format!("P1\n{} {}\n", WIDTH, HEIGHT);
+        for row in self.rows() {
+            let bits = row
+                .iter()
+                .map(|lit| if *lit { "1" } else { "0" })
+                .collect::<Vec<&str>>();
+            pbm += &bits.join(" ");
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    pub fn from_pbm(pbm: &str) -> Result<Self, String> {
+        // Comments run to the end of the line
+        let mut tokens = pbm
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace);
+
+        if tokens.next() != Some("P1") {
+            return Err("Only plain PBM (P1) is supported".to_string());
+        }
+        let size = (tokens.next(), tokens.next());
+        if size != (Some(&WIDTH.to_string()[..]), Some(&HEIGHT.to_string()[..])) {
+            return Err(format!("Expected a {}x{} image", WIDTH, HEIGHT));
+        }
+
+        // Pixels don't need whitespace between them
+        let pixels = tokens
+            .flat_map(str::chars)
+            .map(|c| match c {
+                '1' => Ok(true),
+                '0' => Ok(false),
+                _ => Err(format!("Unexpected '{}' in pixel data", c)),
+            })
+            .collect::<Result<Vec<bool>, String>>()?;
+        if pixels.len() != WIDTH * HEIGHT {
+            return Err(format!(
+                "Expected {} pixels, got {}",
+                WIDTH * HEIGHT,
+                pixels.len()
+            ));
+        }
+        Ok(Frame { pixels })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let frame = if is_pbm(path) {
+            Frame::from_pbm(&text)
+        } else {
+            Frame::from_text(&text)
+        };
+        frame.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let text = if is_pbm(path) {
+            self.to_pbm()
+        } else {
+            self.to_text()
+        };
+        std::fs::write(path, text)
+    }
+
+    // Expected and actual next to each other with the differing pixels marked in a
+    // third column, rows that differ get a '<' at the end
+    pub fn diff(&self, actual: &Frame) -> String {
+        let mut text = format!("{:<w$}  {:<w$}  diff\n", "expected", "actual", w = WIDTH);
+        for (y, (expected_row, actual_row)) in self.rows().zip(actual.rows()).enumerate() {
+            let art = |row: &[bool]| {
+                row.iter()
+                    .map(|lit| if *lit { '#' } else { '.' })
+                    .collect::<String>()
+            };
+            let marks = expected_row
+                .iter()
+                .zip(actual_row)
+                .map(|(e, a)| if e == a { '.' } else { 'X' })
+                .collect::<String>();
+
+            let changed = expected_row != actual_row;
+            text += &format!(
+                "{}  {}  {}{}\n",
+                art(expected_row),
+                art(actual_row),
+                marks,
+                if changed {
+                    format!(" < {}", y)
+                } else {
+                    String::new()
+                }
+            );
+        }
+        text
+    }
+}
+
+fn is_pbm(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pbm"))
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct InputScript {
+    events: Vec<(usize, KeyEvent)>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        InputScript::default()
+    }
+
+    pub fn press(mut self, frame: usize, key: u8) -> Self {
+        self.events.push((frame, KeyEvent::Pressed(key)));
+        self
+    }
+
+    pub fn release(mut self, frame: usize, key: u8) -> Self {
+        self.events.push((frame, KeyEvent::Released(key)));
+        self
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn events_at(&self, frame: usize) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.events
+            .iter()
+            .filter(move |(at, _)| *at == frame)
+            .map(|(_, event)| *event)
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script = InputScript::new();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || {
+                format!(
+                    "Line {}: expected '<frame> press|release <key>'",
+                    number + 1
+                )
+            };
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+            let [frame, action, key] = words[..] else {
+                return Err(error());
+            };
+            let frame = frame.parse::<usize>().map_err(|_| error())?;
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(error)?;
+
+            script = match action {
+                "press" => script.press(frame, key),
+                "release" => script.release(frame, key),
+                _ => return Err(error()),
+            };
+        }
+
+        Ok(script)
+    }
+}
+
+impl fmt::Display for InputScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (frame, event) in &self.events {
+            let action = match event {
+                KeyEvent::Pressed(_) => "press",
+                KeyEvent::Released(_) => "release",
+            };
+            writeln!(f, "{} {} {:X}", frame, action, event.key())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn blessing() -> bool {
+    std::env::var_os("BLESS").is_some()
+}
+
+pub struct GoldenTest {
+    pub rom: PathBuf,
+    pub golden: PathBuf,
+    pub frames: usize,
+    pub cycles_per_frame: usize,
+    pub input: InputScript,
+    // Bytes written to memory after loading, i.e to pick a test in a test suite ROM
+    pub pokes: Vec<(u16, Vec<u8>)>,
+    // Seed for CXKK, otherwise ROMs that use it get a different screen every run
+    pub seed: Option<u32>,
+}
+
+impl GoldenTest {
+    pub fn new<R: Into<PathBuf>, G: Into<PathBuf>>(rom: R, golden: G) -> Self {
+        GoldenTest {
+            rom: rom.into(),
+            golden: golden.into(),
+            frames: 60,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            input: InputScript::new(),
+            pokes: Vec::new(),
+            seed: None,
+        }
+    }
+
+    pub fn frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    pub fn cycles_per_frame(mut self, cycles: usize) -> Self {
+        self.cycles_per_frame = cycles;
+        self
+    }
+
+    pub fn input(mut self, input: InputScript) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn poke(mut self, address: u16, bytes: &[u8]) -> Self {
+        self.pokes.push((address, bytes.to_vec()));
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn run(&self) -> io::Result<Frame> {
+        let mut chip8 = Chip8::new();
+        if let Some(seed) = self.seed {
+            chip8.set_random(Random::Seeded(seed));
+        }
+        chip8.load(&self.rom)?;
+        for (address, bytes) in &self.pokes {
+            chip8.write_memory(*address, bytes);
+        }
+
+        for frame in 0..self.frames {
+            for event in self.input.events_at(frame) {
+                chip8.handle_key(event);
+            }
+            chip8.run(self.cycles_per_frame).map_err(io::Error::other)?;
+        }
+
+        Ok(Frame::from_video(&chip8.get_video_buffer()))
+    }
+
+    // Err holds a readable report, with BLESS set the golden is rewritten instead
+    pub fn check(&self) -> Result<(), String> {
+        let actual = self
+            .run()
+            .map_err(|e| format!("Couldn't run {}: {}", self.rom.display(), e))?;
+
+        if blessing() {
+            return actual
+                .save(&self.golden)
+                .map_err(|e| format!("Couldn't write {}: {}", self.golden.display(), e));
+        }
+
+        let expected = Frame::load(&self.golden).map_err(|e| {
+            format!(
+                "Couldn't read {}: {} (run with BLESS=1 to create it)",
+                self.golden.display(),
+                e
+            )
+        })?;
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} doesn't match {}\n{}",
+                self.rom.display(),
+                self.golden.display(),
+                expected.diff(&actual)
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame_with(lit: &[(usize, usize)]) -> Frame {
+        let mut video = [0u8; WIDTH * HEIGHT];
+        for (x, y) in lit {
+            video[x + y * WIDTH] = 0xFF;
+        }
+        Frame::from_video(&video)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let frame = frame_with(&[(0, 0), (63, 31), (10, 5)]);
+        let text = frame.to_text();
+        assert!(text.starts_with("#...."));
+        assert_eq!(Frame::from_text(&text), Ok(frame));
+
+        assert!(Frame::from_text("#.#\n").is_err());
+        assert!(Frame::from_text(&text.replace('#', "o")).is_err());
+    }
+
+    #[test]
+    fn pbm_round_trip() {
+        let frame = frame_with(&[(1, 0), (63, 31)]);
+        let pbm = frame.to_pbm();
+        assert!(pbm.starts_with("P1\n64 32\n0 1 0"));
+        assert_eq!(Frame::from_pbm(&pbm), Ok(frame.clone()));
+
+        // Comments and packed pixels are fine too
+        let packed = pbm
+            .replacen("P1\n", "P1\n# made by hand\n", 1)
+            .replace(' ', "");
+        let packed = packed.replacen("6432", "64 32", 1);
+        assert_eq!(Frame::from_pbm(&packed), Ok(frame));
+
+        assert!(Frame::from_pbm("P4\n64 32\n").is_err());
+        assert!(Frame::from_pbm("P1\n8 8\n0").is_err());
+    }
+
+    #[test]
+    fn diff_marks_changes() {
+        let expected = frame_with(&[(0, 0)]);
+        let actual = frame_with(&[(1, 0)]);
+        let diff = expected.diff(&actual);
+        let lines = diff.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), HEIGHT + 1);
+        assert!(lines[0].starts_with("expected"));
+        assert!(lines[1].starts_with("#."));
+        assert!(lines[1][WIDTH + 2..].starts_with(".#"));
+        assert!(lines[1][2 * WIDTH + 4..].starts_with("XX.."));
+        assert!(lines[1].ends_with(" < 0"));
+        assert!(!lines[2].contains('<'));
+    }
+
+    #[test]
+    fn parse_input_script() {
+        let script = "
+            # Hold 1 for ten frames
+            30 press 1
+            40 release 1
+            45 press f
+        "
+        .parse::<InputScript>()
+        .unwrap();
+        assert_eq!(
+            script,
+            InputScript::new()
+                .press(30, 0x1)
+                .release(40, 0x1)
+                .press(45, 0xF)
+        );
+        assert_eq!(
+            script.to_string().parse::<InputScript>(),
+            Ok(script.clone())
+        );
+        assert_eq!(
+            script.events_at(30).collect::<Vec<_>>(),
+            vec![KeyEvent::Pressed(1)]
+        );
+
+        assert!("30 press".parse::<InputScript>().is_err());
+        assert!("30 hold 1".parse::<InputScript>().is_err());
+        assert!("30 press 10".parse::<InputScript>().is_err());
+        assert!("soon press 1".parse::<InputScript>().is_err());
+    }
+}
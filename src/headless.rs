@@ -0,0 +1,249 @@
+// Runs a ROM without a window for a fixed number of frames, mostly so bug reports
+// can come with a screenshot or a recording attached. A script can press keys and
+// end the run early, see `script`.
+
+use std::{io, path::PathBuf};
+
+use log::info;
+
+use crate::capture::{self, Recorder};
+use crate::frontend::{FixedClock, NullAudio, NullDisplay, NullInput, DEFAULT_CYCLES_PER_FRAME};
+use crate::interpreter::trace::{Step, TraceWriter};
+use crate::interpreter::Chip8;
+use crate::script::Script;
+use crate::upscale::{Palette, Pipeline};
+
+pub const USAGE: &str = "Usage: paulson-8-headless <rom> [options]
+
+Options:
+    --frames <n>         Frames to run for (default 600)
+    --cycles <n>         Instructions per frame (default 20)
+    --filter <filters>   Comma separated upscalers, i.e scale2x,nearest4 (default nearest8)
+    --palette <name>     classic, inverted, amber or phosphor (default classic)
+    --screenshot <path>  Write a PNG of the last frame
+    --record <path>      Record every frame, .gif for GIF otherwise APNG
+    --trace <path>       Write every instruction's state, .csv, .log or .txt for text otherwise binary
+    --script <path>      Run a Rhai script alongside, it can end the run early with stop()";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub frames: usize,
+    pub cycles_per_frame: usize,
+    pub pipeline: Pipeline,
+    pub palette: Palette,
+    pub screenshot: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub trace: Option<PathBuf>,
+    pub script: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn new(rom: PathBuf) -> Self {
+        Options {
+            rom,
+            frames: 600,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            pipeline: "nearest8".parse().unwrap(),
+            palette: Palette::default(),
+            screenshot: None,
+            record: None,
+            trace: None,
+            script: None,
+        }
+    }
+
+    // Takes the arguments without the program name
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut rom = None;
+        let mut options = Options::new(PathBuf::new());
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--frames" => options.frames = parse_number(&arg, &value(&arg)?)?,
+                "--cycles" => options.cycles_per_frame = parse_number(&arg, &value(&arg)?)?,
+                "--filter" => options.pipeline = value(&arg)?.parse()?,
+                "--palette" => {
+                    let name = value(&arg)?;
+                    options.palette = Palette::from_name(&name)
+                        .ok_or_else(|| format!("Unknown palette: {}", name))?;
+                }
+                "--screenshot" => options.screenshot = Some(value(&arg)?.into()),
+                "--record" => options.record = Some(value(&arg)?.into()),
+                "--trace" => options.trace = Some(value(&arg)?.into()),
+                "--script" => options.script = Some(value(&arg)?.into()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        // Scripts can run frames themselves, which traces would miss
+        if options.trace.is_some() && options.script.is_some() {
+            return Err("--trace and --script can't be used together".to_string());
+        }
+        options.rom = rom.ok_or("No ROM given")?;
+        Ok(options)
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", name, value))
+}
+
+pub fn run(options: &Options) -> io::Result<Chip8> {
+    let mut chip8 = Chip8::new();
+    chip8.load(&options.rom)?;
+
+    let (width, height) = (Chip8::VIDEO_WIDTH as usize, Chip8::VIDEO_HEIGHT as usize);
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|_| Recorder::new(width, height));
+    let mut trace = match &options.trace {
+        Some(path) => Some(TraceWriter::create(path)?),
+        None => None,
+    };
+
+    let mut script = match &options.script {
+        Some(path) => {
+            let mut script = Script::load(path)
+                .map_err(|e| io::Error::other(e.to_string()))?
+                .cycles_per_frame(options.cycles_per_frame)
+                .capture(options.pipeline.clone(), options.palette);
+            script
+                .start(&mut chip8)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            Some(script)
+        }
+        None => None,
+    };
+
+    for _ in 0..options.frames {
+        if script
+            .as_mut()
+            .is_some_and(|script| script.take_stop_request())
+        {
+            info!("Stopped by the script");
+            break;
+        }
+
+        match (trace.as_mut(), script.as_mut()) {
+            // Every instruction has to go through `cycle` to be traced
+            (Some(trace), _) => {
+                for _ in 0..options.cycles_per_frame {
+                    trace.write(&Step::of(&chip8))?;
+                    chip8.cycle().map_err(io::Error::other)?;
+                }
+            }
+            (None, Some(script)) => script
+                .frame(
+                    &mut chip8,
+                    &mut NullInput::default(),
+                    &mut FixedClock(options.cycles_per_frame),
+                    &mut NullAudio::default(),
+                    &mut NullDisplay::default(),
+                )
+                .map_err(|e| io::Error::other(e.to_string()))?,
+            (None, None) => chip8
+                .run(options.cycles_per_frame)
+                .map_err(io::Error::other)?,
+        }
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.push(&chip8.get_video_buffer());
+        }
+    }
+
+    if let Some(trace) = trace.as_mut() {
+        trace.flush()?;
+    }
+
+    if let (Some(path), Some(recorder)) = (&options.record, &recorder) {
+        recorder.save(path, &options.pipeline, options.palette)?;
+        info!("Saved {} frames to {}", recorder.len(), path.display());
+    }
+
+    if let Some(path) = &options.screenshot {
+        let image =
+            options
+                .pipeline
+                .render(&chip8.get_video_buffer(), width, height, options.palette);
+        capture::save_png(path, &image)?;
+        info!("Saved screenshot to {}", path.display());
+    }
+
+    Ok(chip8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::upscale::Filter;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(parse(&["rom.ch8"]), Ok(Options::new("rom.ch8".into())));
+    }
+
+    #[test]
+    fn parse_all_options() {
+        let options = parse(&[
+            "--frames",
+            "10",
+            "rom.ch8",
+            "--cycles",
+            "7",
+            "--filter",
+            "smooth,nearest2",
+            "--palette",
+            "amber",
+            "--screenshot",
+            "out.png",
+            "--record",
+            "out.gif",
+            "--trace",
+            "out.csv",
+        ])
+        .unwrap();
+
+        assert_eq!(options.rom, PathBuf::from("rom.ch8"));
+        assert_eq!(options.frames, 10);
+        assert_eq!(options.cycles_per_frame, 7);
+        assert_eq!(
+            options.pipeline,
+            Pipeline::new()
+                .then(Filter::Smooth)
+                .then(Filter::Nearest(2))
+        );
+        assert_eq!(options.palette, Palette::from_name("amber").unwrap());
+        assert_eq!(options.screenshot, Some("out.png".into()));
+        assert_eq!(options.record, Some("out.gif".into()));
+        assert_eq!(options.trace, Some("out.csv".into()));
+
+        let options = parse(&["rom.ch8", "--script", "press.rhai"]).unwrap();
+        assert_eq!(options.script, Some("press.rhai".into()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["rom.ch8", "--frames"]).is_err());
+        assert!(parse(&["rom.ch8", "--frames", "many"]).is_err());
+        assert!(parse(&["rom.ch8", "--palette", "sepia"]).is_err());
+        assert!(parse(&["rom.ch8", "--fullscreen"]).is_err());
+        assert!(parse(&["rom.ch8", "other.ch8"]).is_err());
+        assert!(parse(&["rom.ch8", "--script", "a.rhai", "--trace", "a.csv"]).is_err());
+    }
+}
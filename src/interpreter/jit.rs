@@ -0,0 +1,600 @@
+// x86-64 recompiler for straight line runs of instructions, used by `Chip8::run`.
+//
+// A block starts at some PC and takes instructions until one that changes where the
+// program goes (JP, CALL, RET, JPR or any skip), which is compiled as the last one, or
+// until one the recompiler leaves to the interpreter: anything that draws, reads keys,
+// touches the timers or memory, and RND. Those stop the block before them and then run
+// through `cycle` as usual.
+//
+// None of the compiled instructions touch the timers so the block just takes its
+// length off them afterwards, which is what running them one cycle at a time does.
+// Blocks never write memory either, so the only way code changes under a block is
+// `write_memory`, which drops every block covering the address.
+//
+// The generated code gets pointers to V0-VF, I, PC, the stack and SP in the System V
+// argument registers and only uses caller saved registers past those:
+//
+//   rdi  V0-VF    rsi  I    rdx  PC    rcx  stack    r8  SP
+//   al, r9b       scratch
+
+use super::opcodes::OP;
+use super::Chip8;
+
+// Longer blocks are faster but need a bigger cycle budget left to run
+const MAX_BLOCK: usize = 16;
+const CODE_SIZE: usize = 1 << 20;
+// Blocks written over this many times are left to the interpreter
+const MAX_REWRITES: u8 = 2;
+
+type Entry = unsafe extern "sysv64" fn(*mut u8, *mut u16, *mut u16, *mut u16, *mut u8);
+
+// How an instruction leaves the block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Exit {
+    // Carries on with the next instruction
+    Next,
+    Jump,
+    Call,
+    Ret,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Block {
+    offset: usize,
+    len: usize,
+    exit: Exit,
+}
+
+// Executable memory blocks get appended to, starts over when it fills up
+struct Code {
+    memory: *mut u8,
+    used: usize,
+}
+
+// Only ever touched through the Chip8 that owns it
+unsafe impl Send for Code {}
+
+impl Code {
+    fn new() -> Self {
+        let memory = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                CODE_SIZE,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert!(
+            memory != libc::MAP_FAILED,
+            "Couldn't map memory for the recompiler"
+        );
+        Code {
+            memory: memory as *mut u8,
+            used: 0,
+        }
+    }
+
+    // Offset of the copied code, None when there isn't room
+    fn push(&mut self, code: &[u8]) -> Option<usize> {
+        if self.used + code.len() > CODE_SIZE {
+            return None;
+        }
+
+        let offset = self.used;
+        unsafe {
+            self.protect(libc::PROT_READ | libc::PROT_WRITE);
+            std::ptr::copy_nonoverlapping(code.as_ptr(), self.memory.add(offset), code.len());
+            self.protect(libc::PROT_READ | libc::PROT_EXEC);
+        }
+        self.used += code.len();
+        Some(offset)
+    }
+
+    unsafe fn protect(&self, protection: libc::c_int) {
+        let result = libc::mprotect(self.memory as *mut libc::c_void, CODE_SIZE, protection);
+        assert_eq!(result, 0, "Couldn't change recompiler memory protection");
+    }
+
+    fn entry(&self, offset: usize) -> Entry {
+        unsafe { std::mem::transmute::<*mut u8, Entry>(self.memory.add(offset)) }
+    }
+}
+
+impl Drop for Code {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.memory as *mut libc::c_void, CODE_SIZE);
+        }
+    }
+}
+
+// What's known about the code starting at an address
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Unknown,
+    // Starts with an instruction the interpreter handles, or keeps getting rewritten
+    Interpreted,
+    Compiled(Block),
+}
+
+pub(super) struct Jit {
+    enabled: bool,
+    // Mapped on the first compile so machines that never run a block don't pay for it
+    code: Option<Code>,
+    // By start address
+    slots: Vec<Slot>,
+    // How many blocks cover each byte of memory
+    covered: Vec<u8>,
+    // How many times the block starting at each address was written over
+    rewrites: Vec<u8>,
+}
+
+// Compiled code belongs to one machine, a clone compiles its own as it runs
+impl Clone for Jit {
+    fn clone(&self) -> Self {
+        let mut jit = Jit::new();
+        jit.enabled = self.enabled;
+        jit
+    }
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Jit {
+            enabled: true,
+            code: None,
+            slots: vec![Slot::Unknown; 4096],
+            covered: vec![0; 4096],
+            rewrites: vec![0; 4096],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.fill(Slot::Unknown);
+        self.covered.fill(0);
+        self.rewrites.fill(0);
+        if let Some(code) = self.code.as_mut() {
+            code.used = 0;
+        }
+    }
+
+    pub fn invalidate(&mut self, address: usize) {
+        if self.covered[address] == 0 {
+            return;
+        }
+
+        // Only blocks starting close enough before the address can reach it
+        for start in address.saturating_sub(MAX_BLOCK * 2 - 1)..=address {
+            let Slot::Compiled(block) = self.slots[start] else {
+                continue;
+            };
+            let end = start + block.len * 2;
+            if end <= address {
+                continue;
+            }
+
+            for byte in &mut self.covered[start..end] {
+                *byte -= 1;
+            }
+            // Code that keeps changing would only be recompiled over and over
+            self.rewrites[start] = self.rewrites[start].saturating_add(1);
+            self.slots[start] = match self.rewrites[start] {
+                rewrites if rewrites >= MAX_REWRITES => Slot::Interpreted,
+                _ => Slot::Unknown,
+            };
+        }
+    }
+}
+
+// Machine code for one block
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    // mov word [rdx], pc
+    fn set_pc(&mut self, pc: u16) {
+        self.emit(&[0x66, 0xC7, 0x02]);
+        self.emit(&pc.to_le_bytes());
+    }
+
+    // mov al, [rdi + v]
+    fn load(&mut self, v: u8) {
+        self.emit(&[0x8A, 0x47, v]);
+    }
+
+    // mov [rdi + v], al
+    fn store(&mut self, v: u8) {
+        self.emit(&[0x88, 0x47, v]);
+    }
+
+    // mov [rdi + 0xF], r9b
+    fn store_flag(&mut self) {
+        self.emit(&[0x44, 0x88, 0x4F, 0x0F]);
+    }
+
+    // Skips over the next instruction when the last comparison came out `skip_if_equal`
+    fn skip(&mut self, next: u16, skip_if_equal: bool) {
+        // jne/je over the 5 byte mov
+        self.emit(&[if skip_if_equal { 0x75 } else { 0x74 }, 5]);
+        self.set_pc(next.wrapping_add(2));
+    }
+
+    // Appends `op` found at `address`, None if it's left to the interpreter
+    fn op(&mut self, op: OP, address: u16) -> Option<Exit> {
+        let next = address.wrapping_add(2);
+        match op {
+            OP::LD { vx, byte } => self.emit(&[0xC6, 0x47, vx, byte]),
+            OP::ADD { vx, byte } => self.emit(&[0x80, 0x47, vx, byte]),
+            OP::LDR { vx, vy } => {
+                self.load(vy);
+                self.store(vx);
+            }
+            OP::OR { vx, vy } | OP::AND { vx, vy } | OP::XOR { vx, vy } => {
+                let opcode = match op {
+                    OP::OR { .. } => 0x08,
+                    OP::AND { .. } => 0x20,
+                    _ => 0x30,
+                };
+                self.load(vy);
+                // or/and/xor [rdi + vx], al
+                self.emit(&[opcode, 0x47, vx]);
+            }
+            OP::ADDR { vx, vy } => {
+                self.load(vx);
+                // add al, [rdi + vy]; setc r9b
+                self.emit(&[0x02, 0x47, vy, 0x41, 0x0F, 0x92, 0xC1]);
+                self.store(vx);
+                self.store_flag();
+            }
+            OP::SUB { vx, vy } | OP::SUBN { vx, vy } => {
+                let (from, by) = match op {
+                    OP::SUB { .. } => (vx, vy),
+                    _ => (vy, vx),
+                };
+                self.load(from);
+                // sub al, [rdi + by]; setae r9b, set when nothing was borrowed
+                self.emit(&[0x2A, 0x47, by, 0x41, 0x0F, 0x93, 0xC1]);
+                self.store(vx);
+                self.store_flag();
+            }
+            OP::SHR { vx, .. } | OP::SHL { vx, .. } => {
+                self.load(vx);
+                // shr/shl al, 1; setc r9b
+                let shift = if let OP::SHR { .. } = op { 0xE8 } else { 0xE0 };
+                self.emit(&[0xD0, shift, 0x41, 0x0F, 0x92, 0xC1]);
+                self.store(vx);
+                self.store_flag();
+            }
+            OP::LDI { addr } => {
+                // mov word [rsi], addr
+                self.emit(&[0x66, 0xC7, 0x06]);
+                self.emit(&addr.to_le_bytes());
+            }
+            OP::ADDI { vx } => {
+                // movzx eax, byte [rdi + vx]; add [rsi], ax
+                self.emit(&[0x0F, 0xB6, 0x47, vx, 0x66, 0x01, 0x06]);
+            }
+            OP::LDF { vx } => {
+                // movzx eax, byte [rdi + vx]; imul eax, eax, 5; add eax, FONT_ADDRESS;
+                // mov [rsi], ax
+                self.emit(&[0x0F, 0xB6, 0x47, vx, 0x6B, 0xC0, 0x05, 0x05]);
+                self.emit(&(Chip8::FONT_ADDRESS as u32).to_le_bytes());
+                self.emit(&[0x66, 0x89, 0x06]);
+            }
+
+            OP::JP { addr } => {
+                self.set_pc(addr);
+                return Some(Exit::Jump);
+            }
+            OP::JPR { addr } => {
+                // movzx eax, byte [rdi]; add eax, addr; mov [rdx], ax
+                self.emit(&[0x0F, 0xB6, 0x07, 0x05]);
+                self.emit(&(addr as u32).to_le_bytes());
+                self.emit(&[0x66, 0x89, 0x02]);
+                return Some(Exit::Jump);
+            }
+            OP::SE { vx, byte } | OP::SNE { vx, byte } => {
+                self.set_pc(next);
+                // cmp byte [rdi + vx], byte
+                self.emit(&[0x80, 0x7F, vx, byte]);
+                self.skip(next, matches!(op, OP::SE { .. }));
+                return Some(Exit::Jump);
+            }
+            OP::SER { vx, vy } | OP::SNER { vx, vy } => {
+                self.set_pc(next);
+                self.load(vx);
+                // cmp al, [rdi + vy]
+                self.emit(&[0x3A, 0x47, vy]);
+                self.skip(next, matches!(op, OP::SER { .. }));
+                return Some(Exit::Jump);
+            }
+            OP::CALL { addr } => {
+                // movzx eax, byte [r8]; mov word [rcx + rax * 2], next; inc byte [r8]
+                self.emit(&[0x41, 0x0F, 0xB6, 0x00, 0x66, 0xC7, 0x04, 0x41]);
+                self.emit(&next.to_le_bytes());
+                self.emit(&[0x41, 0xFE, 0x00]);
+                self.set_pc(addr);
+                return Some(Exit::Call);
+            }
+            OP::RET => {
+                // movzx eax, byte [r8]; dec eax; mov [r8], al;
+                // movzx eax, word [rcx + rax * 2]; mov [rdx], ax
+                self.emit(&[0x41, 0x0F, 0xB6, 0x00, 0xFF, 0xC8, 0x41, 0x88, 0x00]);
+                self.emit(&[0x0F, 0xB7, 0x04, 0x41, 0x66, 0x89, 0x02]);
+                return Some(Exit::Ret);
+            }
+            _ => return None,
+        }
+        Some(Exit::Next)
+    }
+}
+
+impl Chip8 {
+    // Runs the block at PC if there is one that fits in `budget` cycles, returns how
+    // many instructions it ran
+    pub(super) fn run_block(&mut self, budget: usize) -> Option<usize> {
+        if !self.jit.enabled {
+            return None;
+        }
+
+        let pc = self.program_counter;
+        let block = match self.jit.slots.get(pc as usize)? {
+            Slot::Compiled(block) => *block,
+            Slot::Interpreted => return None,
+            Slot::Unknown => self.compile(pc)?,
+        };
+
+        let stack_size = self.stack.0.len() as u8;
+        let unsafe_stack = match block.exit {
+            Exit::Call => self.stack_pointer >= stack_size,
+            Exit::Ret => self.stack_pointer == 0,
+            Exit::Next | Exit::Jump => false,
+        };
+        // The interpreter reports the stack fault
+        if block.len > budget || unsafe_stack {
+            return None;
+        }
+
+        let entry = self.jit.code.as_ref()?.entry(block.offset);
+        unsafe {
+            entry(
+                self.registers.as_mut_ptr(),
+                &mut self.index,
+                &mut self.program_counter,
+                self.stack.0.as_mut_ptr(),
+                &mut self.stack_pointer,
+            );
+        }
+
+        self.tick_timers(block.len);
+        Some(block.len)
+    }
+
+    fn compile(&mut self, pc: u16) -> Option<Block> {
+        let mut assembler = Assembler { code: Vec::new() };
+        let mut len = 0;
+        let mut exit = Exit::Next;
+
+        // Blocks stay clear of the end of memory so they never wrap around
+        let mut address = pc;
+        while len < MAX_BLOCK && (address as usize) < self.memory.0.len() - 1 {
+            let word =
+                u16::from_be_bytes([self.read_memory(address), self.read_memory(address + 1)]);
+            let Some(op_exit) = assembler.op(OP::from(word), address) else {
+                break;
+            };
+            len += 1;
+            address += 2;
+            if op_exit != Exit::Next {
+                exit = op_exit;
+                break;
+            }
+        }
+
+        if len == 0 {
+            self.jit.slots[pc as usize] = Slot::Interpreted;
+            return None;
+        }
+        if exit == Exit::Next {
+            assembler.set_pc(address);
+        }
+        // ret
+        assembler.emit(&[0xC3]);
+
+        let code = self.jit.code.get_or_insert_with(Code::new);
+        let offset = match code.push(&assembler.code) {
+            Some(offset) => offset,
+            None => {
+                self.jit.clear();
+                self.jit.code.as_mut()?.push(&assembler.code)?
+            }
+        };
+
+        let block = Block { offset, len, exit };
+        self.jit.slots[pc as usize] = Slot::Compiled(block);
+        for byte in &mut self.jit.covered[pc as usize..address as usize] {
+            *byte += 1;
+        }
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::rom;
+    use super::super::trace::Step;
+    use super::super::vectors::{State, Vector, PATTERNS};
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // Instructions that always go to the interpreter
+    const INTERPRETED: [&str; 13] = [
+        "00E0", "Dxyn", "Ex9E", "ExA1", "Fx07", "Fx0A", "Fx15", "Fx18", "Fx33", "Fx55", "Fx65",
+        "Cxkk", "0nnn",
+    ];
+
+    fn pair(rom: &[u8]) -> (Chip8, Chip8) {
+        let mut jit = Chip8::new();
+        let mut interpreter = Chip8::new();
+        interpreter.set_jit(false);
+        for chip8 in [&mut jit, &mut interpreter] {
+            chip8.load_bytes(rom).unwrap();
+        }
+        (jit, interpreter)
+    }
+
+    fn compiled(chip8: &Chip8, address: u16) -> bool {
+        matches!(chip8.jit.slots[address as usize], Slot::Compiled(_))
+    }
+
+    #[test]
+    fn single_instructions_match_vectors() {
+        let mut rng = StdRng::seed_from_u64(40);
+        for pattern in PATTERNS {
+            for number in 0..50 {
+                let vector = Vector::generate(&mut rng, pattern, number);
+                let mut chip8 = vector.initial.build();
+                chip8.run(1).unwrap();
+                assert_eq!(
+                    State::of(&chip8).diff(&vector.after),
+                    Vec::<String>::new(),
+                    "{}",
+                    vector.name
+                );
+
+                assert_eq!(
+                    compiled(&chip8, vector.initial.pc),
+                    !INTERPRETED.contains(&pattern),
+                    "{} compiled",
+                    vector.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn random_programs_match_interpreter() {
+        let mut rng = StdRng::seed_from_u64(40);
+        for program in 0..300 {
+            // Mostly compiled instructions with jumps and calls kept inside the program
+            let rom = (0..64)
+                .flat_map(|_| {
+                    let word: u16 = match rng.gen_range(0..10) {
+                        0 => 0x1200 | (rng.gen_range(0..64) * 2),
+                        1 => 0x2200 | (rng.gen_range(0..64) * 2),
+                        2 => 0x00EE,
+                        3 => 0xB200 | (rng.gen_range(0..64) * 2),
+                        // No RND, not even at an odd address
+                        _ => loop {
+                            let word: u16 = rng.gen();
+                            if word & 0xF000 != 0xC000 && word & 0x00F0 != 0x00C0 {
+                                break word;
+                            }
+                        },
+                    };
+                    word.to_be_bytes()
+                })
+                .collect::<Vec<u8>>();
+
+            let (mut jit, mut interpreter) = pair(&rom);
+            for chunk in 0..200 {
+                let cycles = rng.gen_range(1..40);
+                let results = (jit.run(cycles), interpreter.run(cycles));
+                let context = format!("Program {} chunk {} ({:02X?})", program, chunk, rom);
+                assert_eq!(results.0, results.1, "{}", context);
+                assert_eq!(Step::of(&jit), Step::of(&interpreter), "{}", context);
+                assert_eq!(jit.delay_timer, interpreter.delay_timer, "{}", context);
+                assert_eq!(jit.stack.0, interpreter.stack.0, "{}", context);
+                assert!(
+                    jit.memory.0 == interpreter.memory.0
+                        && jit.get_video_buffer() == interpreter.get_video_buffer(),
+                    "{}",
+                    context
+                );
+                if results.0.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn timendus_matches_interpreter() {
+        let rom = include_bytes!("../../tests/roms/timendus/chip8-test-suite.ch8");
+        // Corax+, flags and the SCHIP quirks test
+        for (test, option) in [(2, 0), (3, 0), (4, 2)] {
+            let (mut jit, mut interpreter) = pair(rom);
+            for chip8 in [&mut jit, &mut interpreter] {
+                chip8.write_memory(0x1FE, &[option, test]);
+            }
+
+            for frame in 0..400 {
+                jit.run(20).unwrap();
+                interpreter.run(20).unwrap();
+                assert_eq!(
+                    Step::of(&jit),
+                    Step::of(&interpreter),
+                    "Test {} frame {}",
+                    test,
+                    frame
+                );
+            }
+            assert_eq!(jit.get_video_buffer(), interpreter.get_video_buffer());
+            assert!(jit.jit.covered.iter().any(|count| *count > 0));
+        }
+    }
+
+    #[test]
+    fn writes_drop_blocks() {
+        // LD V2, 0x01 at 0x206 gets rewritten to LD V2, 0x07 by FX55 after it has run
+        let rom = rom(&[0x6062, 0x6107, 0xA206, 0x6201, 0xF155, 0x1206]);
+        let (mut chip8, _) = pair(&rom);
+
+        chip8.run(6).unwrap();
+        assert_eq!(chip8.registers[0x2], 0x01);
+        assert!(!compiled(&chip8, 0x200), "FX55 wrote over it");
+        assert!(compiled(&chip8, 0x20A));
+        chip8.run(1).unwrap();
+        assert_eq!(chip8.registers[0x2], 0x07);
+        assert!(compiled(&chip8, 0x206));
+
+        // Writing the second byte counts too
+        chip8.write_memory(0x207, &[0x09]);
+        assert!(!compiled(&chip8, 0x206));
+        chip8.program_counter = 0x206;
+        chip8.run(1).unwrap();
+        assert_eq!(chip8.registers[0x2], 0x09);
+
+        // After being written over twice it's left to the interpreter
+        chip8.write_memory(0x207, &[0x0B]);
+        chip8.program_counter = 0x206;
+        chip8.run(1).unwrap();
+        assert_eq!(chip8.registers[0x2], 0x0B);
+        assert!(matches!(chip8.jit.slots[0x206], Slot::Interpreted));
+
+        // Loading a ROM starts over
+        chip8.load_bytes(&rom).unwrap();
+        assert!(chip8
+            .jit
+            .slots
+            .iter()
+            .all(|slot| matches!(slot, Slot::Unknown)));
+        assert!(chip8.jit.covered.iter().all(|count| *count == 0));
+    }
+}
@@ -10,12 +10,46 @@
 // 16-bit (one byte) general purpose variable registers labled 0-F hex, ie V0-VF
 // VF is also commonly used as the flag register
 
+#[cfg(all(feature = "jit", not(all(target_arch = "x86_64", unix))))]
+compile_error!("The jit feature needs x86-64 and a unix-like OS");
+
+#[cfg(feature = "jit")]
+mod jit;
 pub mod opcodes;
+#[cfg(feature = "batch")]
+mod parts;
+mod random;
+mod runtime;
+mod state;
+#[cfg(test)]
+pub(crate) mod testing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod vectors;
 
-use log::{debug, error, info, trace, warn};
+#[cfg(feature = "log")]
+use log::{debug, error, trace, warn};
 use opcodes::OP;
-use rand;
+#[cfg(feature = "batch")]
+pub(crate) use parts::{Parts, Rest};
+pub use random::Random;
+pub use state::StateError;
+
+// Without the log feature messages are still formatted so everything they mention
+// counts as used, but go nowhere
+#[cfg(not(feature = "log"))]
+macro_rules! quiet {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+#[cfg(not(feature = "log"))]
+use {quiet as debug, quiet as error, quiet as trace, quiet as warn};
 
+// Clones carry on from the same point on their own, a cheap way to try out several
+// futures from one state
+#[derive(Clone)]
 pub struct Chip8 {
     registers: [u8; 16],
     memory: Memory,
@@ -27,7 +61,91 @@ pub struct Chip8 {
     sound_timer: u8,
     keypad: KeyPad,
     video: VideoBuffer,
+    #[allow(dead_code)]
     opcode: u16,
+    quirks: Quirks,
+    key_wait: KeyWait,
+    // Keys that went down since FX0A started waiting, one bit per key
+    new_presses: u16,
+    random: Random,
+    decoded: DecodeCache,
+    #[cfg(feature = "jit")]
+    jit: jit::Jit,
+}
+
+// Behaviour that differs between chip-8 implementations
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Quirks {
+    // FX0A finishes when the key is released rather than when it is pressed, like the VIP
+    pub wait_for_release: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            wait_for_release: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+impl KeyEvent {
+    pub fn key(&self) -> u8 {
+        match *self {
+            KeyEvent::Pressed(key) | KeyEvent::Released(key) => key,
+        }
+    }
+}
+
+// Turns two snapshots of the keypad into the presses and releases between them
+pub fn key_events(before: [bool; 16], after: [bool; 16]) -> impl Iterator<Item = KeyEvent> {
+    (0..16u8).filter_map(
+        move |key| match (before[key as usize], after[key as usize]) {
+            (false, true) => Some(KeyEvent::Pressed(key)),
+            (true, false) => Some(KeyEvent::Released(key)),
+            _ => None,
+        },
+    )
+}
+
+// Things a ROM can do that stop the interpreter, the address is where the instruction
+// was. Also ROMs that don't fit in the first place
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    StackOverflow { address: u16 },
+    StackUnderflow { address: u16 },
+    RomTooLarge { size: usize },
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::StackOverflow { address } => write!(f, "Stack overflow at {:#05X}", address),
+            Error::StackUnderflow { address } => write!(f, "Stack underflow at {:#05X}", address),
+            Error::RomTooLarge { size } => write!(
+                f,
+                "ROM is {} bytes, only {} fit in memory",
+                size,
+                4096 - Chip8::START_ADDRESS as usize
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// Where FX0A is at while it blocks
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum KeyWait {
+    Idle,
+    Press,
+    Release(u8),
 }
 
 impl Default for Chip8 {
@@ -41,8 +159,8 @@ impl Chip8 {
     #![allow(dead_code)]
 
     const START_ADDRESS: u16 = 0x200;
-    const FONT_ADDRESS: u16 = 0x50;
-    const FONT: [u8; 80] = [
+    pub const FONT_ADDRESS: u16 = 0x50;
+    pub const FONT: [u8; 80] = [
         0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
         0x20, 0x60, 0x20, 0x20, 0x70, // 1
         0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -63,6 +181,10 @@ impl Chip8 {
     pub const VIDEO_WIDTH: u8 = 64;
     pub const VIDEO_HEIGHT: u8 = 32;
     pub fn new() -> Self {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
         // Load font into memory
         let mut memory = Memory::new();
         memory.0[Chip8::FONT_ADDRESS as usize..Chip8::FONT_ADDRESS as usize + Chip8::FONT.len()]
@@ -80,9 +202,42 @@ impl Chip8 {
             keypad: KeyPad::new(),
             video: VideoBuffer::new(),
             opcode: 0,
+            quirks,
+            key_wait: KeyWait::Idle,
+            new_presses: 0,
+            random: Random::default(),
+            decoded: DecodeCache::new(),
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::new(),
         }
     }
 
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // On by default with std, turning it off decodes every instruction as it runs.
+    // Without std there is no cache and this does nothing
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decoded.set_enabled(enabled);
+    }
+
+    // On by default, turning it off leaves `run` to the interpreter
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, enabled: bool) {
+        self.jit.set_enabled(enabled);
+    }
+
+    // Seeded generators make CXKK, and so whole runs, repeatable
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    #[cfg(feature = "std")]
     pub fn load(&mut self, filepath: &std::path::Path) -> std::io::Result<()> {
         let file = match std::fs::read(filepath) {
             Ok(file) => file,
@@ -91,18 +246,53 @@ impl Chip8 {
                 return Err(e);
             }
         };
-        
+
         // To automatically load a file
         // let file = include_bytes!("example.ch8");
 
+        self.load_bytes(&file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), Error> {
         // Would be nice to have start address be usize...
-        self.memory.0[Chip8::START_ADDRESS as usize..Chip8::START_ADDRESS as usize + file.len()]
-            .copy_from_slice(&file);
+        let start = Chip8::START_ADDRESS as usize;
+        if rom.len() > self.memory.0.len() - start {
+            return Err(Error::RomTooLarge { size: rom.len() });
+        }
 
+        self.memory.0[start..start + rom.len()].copy_from_slice(rom);
+        self.decoded.clear();
+        #[cfg(feature = "jit")]
+        self.jit.clear();
         Ok(())
     }
 
-    fn execute(&mut self, op: OP) {
+    // Addresses past the end of memory wrap around. Everything that changes memory after
+    // loading has to come through here so the decode cache doesn't go stale
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let address = (address as usize + i) % self.memory.0.len();
+            self.memory.0[address] = *byte;
+            self.decoded.invalidate(address);
+            #[cfg(feature = "jit")]
+            self.jit.invalidate(address);
+        }
+    }
+
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.memory.0[address as usize % self.memory.0.len()]
+    }
+
+    // All 4k at once, for looking at where a ROM keeps things
+    pub fn memory(&self) -> &[u8] {
+        &self.memory.0
+    }
+
+    fn execute(&mut self, op: OP) -> Result<(), Error> {
+        // The program counter has already moved past the instruction
+        let address = self.program_counter.wrapping_sub(2);
+
         match op {
             OP::CLS => {
                 // Clear video buffer by creating new one
@@ -113,7 +303,7 @@ impl Chip8 {
                     Some(v) => v,
                     None => {
                         error!("Stack underflow! Instruction {} underflowed the stack.", op);
-                        todo!("Implement returning an error");
+                        return Err(Error::StackUnderflow { address });
                     }
                 };
                 self.program_counter = self.stack.0[self.stack_pointer as usize];
@@ -122,31 +312,30 @@ impl Chip8 {
                 self.program_counter = addr;
             }
             OP::CALL { addr } => {
-                self.stack.0[self.stack_pointer as usize] = self.program_counter;
-
                 // Check for overflow
-                if self.stack_pointer >= 15 {
+                if self.stack_pointer as usize >= self.stack.0.len() {
                     error!("Stack overflow! Instruction {} overflowed the stack.", op);
-                    todo!("Implement returning an error");
-                } else {
-                    self.stack_pointer += 1;
+                    return Err(Error::StackOverflow { address });
                 }
 
+                self.stack.0[self.stack_pointer as usize] = self.program_counter;
+                self.stack_pointer += 1;
+
                 self.program_counter = addr;
             }
             OP::SE { vx, byte } => {
                 if self.registers[vx as usize] == byte {
-                    self.program_counter += 2;
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::SNE { vx, byte } => {
                 if self.registers[vx as usize] != byte {
-                    self.program_counter += 2;
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::SER { vx, vy } => {
                 if self.registers[vx as usize] == self.registers[vy as usize] {
-                    self.program_counter += 2;
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::LD { vx, byte } => self.registers[vx as usize] = byte,
@@ -174,13 +363,13 @@ impl Chip8 {
                 self.registers[vx as usize] =
                     self.registers[vx as usize].wrapping_sub(self.registers[vy as usize]);
 
-                match order {
-                    std::cmp::Ordering::Greater => self.registers[0x0F] = 1,
-                    std::cmp::Ordering::Less => self.registers[0x0F] = 0,
-                    std::cmp::Ordering::Equal => {}
-                }
+                // VF is set when there was no borrow, equal operands don't borrow
+                self.registers[0x0F] = match order {
+                    core::cmp::Ordering::Less => 0,
+                    _ => 1,
+                };
             }
-            OP::SHR { vx } => {
+            OP::SHR { vx, .. } => {
                 let bit = self.registers[vx as usize] & 0x01;
                 self.registers[vx as usize] >>= 1;
                 self.registers[0x0F] = bit;
@@ -191,26 +380,25 @@ impl Chip8 {
                 self.registers[vx as usize] =
                     self.registers[vy as usize].wrapping_sub(self.registers[vx as usize]);
 
-                match order {
-                    std::cmp::Ordering::Greater => self.registers[0x0F] = 1,
-                    std::cmp::Ordering::Less => self.registers[0x0F] = 0,
-                    std::cmp::Ordering::Equal => {}
-                }
+                // VF is set when there was no borrow, equal operands don't borrow
+                self.registers[0x0F] = match order {
+                    core::cmp::Ordering::Less => 0,
+                    _ => 1,
+                };
             }
-            OP::SHL { vx } => {
+            OP::SHL { vx, .. } => {
                 let bit = (self.registers[vx as usize] & 0x80) >> 7;
                 self.registers[vx as usize] <<= 1;
                 self.registers[0x0F] = bit;
             }
             OP::SNER { vx, vy } => {
                 if self.registers[vx as usize] != self.registers[vy as usize] {
-                    self.program_counter += 2;
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::LDI { addr } => self.index = addr,
             OP::JPR { addr } => self.program_counter = self.registers[0] as u16 + addr,
-            // This should be able to be seeded
-            OP::RND { vx, byte } => self.registers[vx as usize] = rand::random::<u8>() & byte,
+            OP::RND { vx, byte } => self.registers[vx as usize] = self.random.byte() & byte,
             OP::DRW { vx, vy, height } => {
                 // Wrap if values are beyond boundries
                 let x_pos = (self.registers[vx as usize] % Chip8::VIDEO_WIDTH) as usize;
@@ -224,7 +412,7 @@ impl Chip8 {
                         break;
                     }
 
-                    let sprite_byte = self.memory.0[self.index as usize + row];
+                    let sprite_byte = self.read_memory(self.index.wrapping_add(row as u16));
 
                     // We know that sprites have a width of 8
                     for col in 0..8 {
@@ -249,100 +437,181 @@ impl Chip8 {
                     }
                 }
             }
+            // Only the low nibble picks the key, like the VIP
             OP::SKP { vx } => {
-                if self.keypad.0[self.registers[vx as usize] as usize] == 0xFF {
-                    self.program_counter += 2;
+                if self.keypad.0[(self.registers[vx as usize] & 0x0F) as usize] == 0xFF {
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::SKNP { vx } => {
-                if self.keypad.0[vx as usize] != 0xFF {
-                    self.program_counter += 2;
+                if self.keypad.0[(self.registers[vx as usize] & 0x0F) as usize] != 0xFF {
+                    self.program_counter = self.program_counter.wrapping_add(2);
                 }
             }
             OP::LDDT { vx } => self.registers[vx as usize] = self.delay_timer,
             OP::LDK { vx } => {
-                if let Some(index) = self.keypad.0.iter().position(|x| *x == 0xFF) {
-                    self.registers[vx as usize] = index as u8;
+                if let Some(key) = self.wait_for_key() {
+                    self.registers[vx as usize] = key;
                 } else {
-                    // No key was pressed
-                    self.program_counter -= 2;
+                    // Keep executing this instruction until a key comes through
+                    self.program_counter = self.program_counter.wrapping_sub(2);
                 }
             }
             OP::LDT { vx } => self.delay_timer = self.registers[vx as usize],
             OP::LDST { vx } => self.sound_timer = self.registers[vx as usize],
-            OP::ADDI { vx } => self.index += self.registers[vx as usize] as u16,
+            OP::ADDI { vx } => {
+                self.index = self.index.wrapping_add(self.registers[vx as usize] as u16)
+            }
             OP::LDF { vx } => {
                 self.index = Chip8::FONT_ADDRESS + (5 * self.registers[vx as usize] as u16)
             }
             OP::LDB { vx } => {
-                let mut value = self.registers[vx as usize];
-
-                // One digit
-                self.memory.0[self.index as usize + 2] = value % 10;
-                value /= 10;
+                let value = self.registers[vx as usize];
 
-                // Tens digit
-                self.memory.0[self.index as usize + 1] = value % 10;
-                value /= 10;
-
-                // Hundreds digit
-                self.memory.0[self.index as usize] = value % 10;
+                // Hundreds, tens and ones digits
+                self.write_memory(self.index, &[value / 100, value / 10 % 10, value % 10]);
             }
             OP::LDIA { vx } => {
-                for i in 0..=vx as usize {
-                    self.memory.0[self.index as usize + i] = self.registers[i];
-                }
+                let registers = self.registers;
+                self.write_memory(self.index, &registers[..=vx as usize]);
             }
             OP::LDRA { vx } => {
                 for i in 0..=vx as usize {
-                    self.registers[i] = self.memory.0[self.index as usize + i];
+                    self.registers[i] = self.read_memory(self.index.wrapping_add(i as u16));
                 }
             }
             OP::INV { opcode } => warn!("Attempted to execute invalid opcode: 0x{:04x}", opcode),
         }
+
+        Ok(())
     }
 
-    pub fn cycle(&mut self) {
-        let first_byte = self.memory.0[self.program_counter as usize] as u16;
-        // FIXME: Memory needs out of bounds protection
-        let second_byte = self.memory.0[self.program_counter as usize + 1];
-        let op = OP::from(first_byte << 8 | second_byte as u16);
+    // Memory reads wrap around so any program counter is fine
+    pub fn cycle(&mut self) -> Result<(), Error> {
+        let op = self.fetch();
 
         trace!("PC: {}, OP: {}", self.program_counter, op);
-        self.program_counter += 2;
+        self.program_counter = self.program_counter.wrapping_add(2);
 
-        self.execute(op);
+        let result = self.execute(op);
+        self.tick_timers(1);
+        result
+    }
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    // Same as calling `cycle` that many times, stops at the first fault. With the jit
+    // feature runs of instructions go through the recompiler
+    pub fn run(&mut self, cycles: usize) -> Result<(), Error> {
+        let mut left = cycles;
+        while left > 0 {
+            #[cfg(feature = "jit")]
+            if let Some(ran) = self.run_block(left) {
+                left -= ran;
+                continue;
+            }
+
+            self.cycle()?;
+            left -= 1;
         }
+        Ok(())
+    }
 
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+    fn fetch(&mut self) -> OP {
+        let address = self.program_counter as usize % self.memory.0.len();
+        if let Some(op) = self.decoded.get(address) {
+            return op;
         }
+
+        let first_byte = self.read_memory(self.program_counter) as u16;
+        let second_byte = self.read_memory(self.program_counter.wrapping_add(1));
+        let op = OP::from(first_byte << 8 | second_byte as u16);
+        self.decoded.insert(address, op);
+        op
     }
 
-    pub fn update_input(&mut self, pressed: &[bool]) {
-        assert!(pressed.len() <= 16, "Only 16 keys exist");
+    fn wait_for_key(&mut self) -> Option<u8> {
+        if !self.quirks.wait_for_release {
+            return self
+                .keypad
+                .0
+                .iter()
+                .position(|x| *x == 0xFF)
+                .map(|i| i as u8);
+        }
+
+        match self.key_wait {
+            KeyWait::Idle => {
+                // Keys that were already held don't count, it has to be a fresh press
+                self.new_presses = 0;
+                self.key_wait = KeyWait::Press;
+                None
+            }
+            KeyWait::Press => {
+                if self.new_presses != 0 {
+                    self.key_wait = KeyWait::Release(self.new_presses.trailing_zeros() as u8);
+                }
+                None
+            }
+            KeyWait::Release(key) => {
+                if self.keypad.0[key as usize] == 0xFF {
+                    return None;
+                }
+                self.key_wait = KeyWait::Idle;
+                Some(key)
+            }
+        }
+    }
 
-        for (i, key) in pressed.iter().enumerate() {
-            self.keypad.0[i] = match *key {
-                true => {
+    // Only keys 0-F exist, events for anything else are ignored
+    pub fn handle_key(&mut self, event: KeyEvent) {
+        let Some(key) = self.keypad.0.get_mut(event.key() as usize) else {
+            warn!("Ignored {:?}, there are only 16 keys", event);
+            return;
+        };
+        match event {
+            KeyEvent::Pressed(i) => {
+                if *key != 0xFF {
                     debug!("Pressed {}", i);
-                    0xFF
+                    self.new_presses |= 1 << i;
                 }
-                false => 0x00,
-            };
+                *key = 0xFF;
+            }
+            KeyEvent::Released(i) => {
+                if *key == 0xFF {
+                    debug!("Released {}", i);
+                }
+                *key = 0x00;
+            }
+        }
+    }
+
+    pub fn update_input(&mut self, events: &[KeyEvent]) {
+        for event in events {
+            self.handle_key(*event);
         }
     }
 
+    pub fn pressed_keys(&self) -> [bool; 16] {
+        self.keypad.0.map(|key| key == 0xFF)
+    }
+
+    // The buzzer sounds for as long as the sound timer is above zero
+    pub fn sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
     pub fn get_video_buffer(
         &self,
     ) -> [u8; Chip8::VIDEO_WIDTH as usize * Chip8::VIDEO_HEIGHT as usize] {
         self.video.0
     }
+
+    // The same without copying it, for anything looking at it every frame
+    pub fn video(&self) -> &[u8] {
+        &self.video.0
+    }
 }
 
+#[derive(Clone)]
 struct Memory([u8; 4096]);
 
 impl Memory {
@@ -351,7 +620,78 @@ impl Memory {
     }
 }
 
+// Instructions by the address they start at, decoded the first time they run.
+// Programs rewrite themselves all the time so a write drops the instruction at that
+// address and the one starting the byte before, which it's the second half of
+#[cfg(feature = "std")]
+#[derive(Clone)]
+struct DecodeCache {
+    enabled: bool,
+    ops: Vec<Option<OP>>,
+}
+
+#[cfg(feature = "std")]
+impl DecodeCache {
+    pub fn new() -> Self {
+        DecodeCache {
+            enabled: true,
+            ops: vec![None; 4096],
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    fn get(&self, address: usize) -> Option<OP> {
+        self.ops[address]
+    }
+
+    fn insert(&mut self, address: usize, op: OP) {
+        if self.enabled {
+            self.ops[address] = Some(op);
+        }
+    }
+
+    fn invalidate(&mut self, address: usize) {
+        let size = self.ops.len();
+        self.ops[address] = None;
+        self.ops[(address + size - 1) % size] = None;
+    }
+
+    fn clear(&mut self) {
+        self.ops.fill(None);
+    }
+}
+
+// Without std there's nowhere to put the cache but inside `Chip8`, which would be
+// 16KiB more on the stack of a small board, so every instruction is decoded as it runs
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+struct DecodeCache;
+
+#[cfg(not(feature = "std"))]
+impl DecodeCache {
+    pub fn new() -> Self {
+        DecodeCache
+    }
+
+    fn set_enabled(&mut self, _enabled: bool) {}
+
+    fn get(&self, _address: usize) -> Option<OP> {
+        None
+    }
+
+    fn insert(&mut self, _address: usize, _op: OP) {}
+
+    fn invalidate(&mut self, _address: usize) {}
+
+    fn clear(&mut self) {}
+}
+
 // Do I actually want to implement these like this
+#[derive(Clone)]
 struct Stack([u16; 16]);
 
 impl Stack {
@@ -360,6 +700,7 @@ impl Stack {
     }
 }
 
+#[derive(Clone)]
 struct KeyPad([u8; 16]);
 
 impl KeyPad {
@@ -367,6 +708,7 @@ impl KeyPad {
         KeyPad([0; 16])
     }
 }
+#[derive(Clone)]
 struct VideoBuffer([u8; 64 * 32]);
 
 impl VideoBuffer {
@@ -375,3 +717,822 @@ impl VideoBuffer {
         VideoBuffer([0; VideoBuffer::SIZE])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::testing::{check, fault, run, State};
+    use super::*;
+
+    fn load_program(chip8: &mut Chip8, program: &[u16]) {
+        for (i, op) in program.iter().enumerate() {
+            let address = Chip8::START_ADDRESS + i as u16 * 2;
+            chip8.write_memory(address, &op.to_be_bytes());
+        }
+    }
+
+    // Runs a frame worth of instructions after handing over the frame's key events
+    fn frame(chip8: &mut Chip8, events: &[KeyEvent]) {
+        chip8.update_input(events);
+        for _ in 0..20 {
+            chip8.cycle().unwrap();
+        }
+    }
+
+    // LD V0, K then spin on a jump
+    const WAIT_FOR_KEY: [u16; 2] = [0xF00A, 0x1202];
+
+    #[test]
+    fn key_events_from_snapshots() {
+        let mut before = [false; 16];
+        let mut after = [false; 16];
+        before[1] = true;
+        before[2] = true;
+        after[2] = true;
+        after[9] = true;
+
+        assert_eq!(
+            key_events(before, after).collect::<Vec<_>>(),
+            vec![KeyEvent::Released(1), KeyEvent::Pressed(9)]
+        );
+    }
+
+    #[test]
+    fn held_key_stays_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.handle_key(KeyEvent::Pressed(0xA));
+
+        // No new events, the key is still down frames later
+        for _ in 0..3 {
+            chip8.update_input(&[]);
+        }
+        assert!(chip8.pressed_keys()[0xA]);
+
+        chip8.handle_key(KeyEvent::Released(0xA));
+        assert_eq!(chip8.pressed_keys(), [false; 16]);
+    }
+
+    #[test]
+    fn keys_past_f_are_ignored() {
+        let mut chip8 = Chip8::new();
+        chip8.handle_key(KeyEvent::Pressed(16));
+        chip8.handle_key(KeyEvent::Released(0xFF));
+        assert_eq!(chip8.pressed_keys(), [false; 16]);
+        assert_eq!(chip8.new_presses, 0);
+    }
+
+    #[test]
+    fn wait_for_key_finishes_on_release() {
+        let mut chip8 = Chip8::new();
+        load_program(&mut chip8, &WAIT_FOR_KEY);
+
+        frame(&mut chip8, &[]);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        // Held for a few frames, still waiting
+        frame(&mut chip8, &[KeyEvent::Pressed(5)]);
+        frame(&mut chip8, &[]);
+        frame(&mut chip8, &[]);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        frame(&mut chip8, &[KeyEvent::Released(5)]);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.registers[0], 5);
+    }
+
+    #[test]
+    fn wait_for_key_needs_a_fresh_press() {
+        let mut chip8 = Chip8::new();
+        load_program(&mut chip8, &WAIT_FOR_KEY);
+
+        // Already held before FX0A ran, like a key still down from the last menu
+        chip8.handle_key(KeyEvent::Pressed(3));
+        frame(&mut chip8, &[]);
+        frame(&mut chip8, &[KeyEvent::Released(3)]);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        frame(&mut chip8, &[KeyEvent::Pressed(3)]);
+        assert_eq!(chip8.program_counter, 0x200);
+        frame(&mut chip8, &[KeyEvent::Released(3)]);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.registers[0], 3);
+    }
+
+    #[test]
+    fn wait_for_key_tap_within_a_frame() {
+        let mut chip8 = Chip8::new();
+        load_program(&mut chip8, &WAIT_FOR_KEY);
+        frame(&mut chip8, &[]);
+
+        frame(
+            &mut chip8,
+            &[KeyEvent::Pressed(0xE), KeyEvent::Released(0xE)],
+        );
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.registers[0], 0xE);
+    }
+
+    #[test]
+    fn wait_for_key_on_press_without_quirk() {
+        let mut chip8 = Chip8::with_quirks(Quirks {
+            wait_for_release: false,
+        });
+        load_program(&mut chip8, &WAIT_FOR_KEY);
+
+        frame(&mut chip8, &[]);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        frame(&mut chip8, &[KeyEvent::Pressed(7)]);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.registers[0], 7);
+        assert!(chip8.pressed_keys()[7]);
+    }
+
+    #[test]
+    fn sub_equal_operands_set_vf() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 5; LD V1, 5; SUB V0, V1; LD V2, 5; SUBN V2, V1
+        load_program(&mut chip8, &[0x6005, 0x6105, 0x8015, 0x6205, 0x8217]);
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0], 0);
+        assert_eq!(chip8.registers[0xF], 1);
+
+        chip8.registers[0xF] = 0;
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[2], 0);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn sknp_checks_key_in_register() {
+        let mut chip8 = Chip8::new();
+        // LD V3, 9; SKNP V3
+        load_program(&mut chip8, &[0x6309, 0xE3A1]);
+        chip8.handle_key(KeyEvent::Pressed(9));
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x204);
+    }
+
+    // One test per OP from here on, see testing.rs for how `check` works
+
+    #[test]
+    fn execute_cls() {
+        check(State::new().lit(0, 0).lit(63, 31), OP::CLS, |after| {
+            after.unlit(0, 0).unlit(63, 31)
+        });
+    }
+
+    #[test]
+    fn execute_ret() {
+        check(State::new().stack(&[0x204, 0x30A]), OP::RET, |after| {
+            after.stack(&[0x204]).pc(0x30A)
+        });
+    }
+
+    #[test]
+    fn execute_jp() {
+        check(State::new(), OP::JP { addr: 0xABC }, |after| {
+            after.pc(0xABC)
+        });
+    }
+
+    #[test]
+    fn execute_call() {
+        check(
+            State::new().stack(&[0x204]),
+            OP::CALL { addr: 0x400 },
+            |after| after.stack(&[0x204, 0x202]).pc(0x400),
+        );
+    }
+
+    #[test]
+    fn execute_se() {
+        let op = OP::SE {
+            vx: 0x3,
+            byte: 0x42,
+        };
+        check(State::new().v(0x3, 0x42), op, |after| after.pc(0x204));
+
+        let op = OP::SE {
+            vx: 0x3,
+            byte: 0x42,
+        };
+        check(State::new().v(0x3, 0x41), op, |after| after);
+    }
+
+    #[test]
+    fn execute_sne() {
+        let op = OP::SNE {
+            vx: 0x3,
+            byte: 0x42,
+        };
+        check(State::new().v(0x3, 0x41), op, |after| after.pc(0x204));
+
+        let op = OP::SNE {
+            vx: 0x3,
+            byte: 0x42,
+        };
+        check(State::new().v(0x3, 0x42), op, |after| after);
+    }
+
+    #[test]
+    fn execute_ser() {
+        let before = State::new().v(0x1, 7).v(0x2, 7);
+        check(before, OP::SER { vx: 0x1, vy: 0x2 }, |after| {
+            after.pc(0x204)
+        });
+
+        let before = State::new().v(0x1, 7).v(0x2, 8);
+        check(before, OP::SER { vx: 0x1, vy: 0x2 }, |after| after);
+    }
+
+    #[test]
+    fn execute_sner() {
+        let before = State::new().v(0x1, 7).v(0x2, 8);
+        check(before, OP::SNER { vx: 0x1, vy: 0x2 }, |after| {
+            after.pc(0x204)
+        });
+
+        let before = State::new().v(0x1, 7).v(0x2, 7);
+        check(before, OP::SNER { vx: 0x1, vy: 0x2 }, |after| after);
+    }
+
+    #[test]
+    fn execute_ld() {
+        check(
+            State::new(),
+            OP::LD {
+                vx: 0xE,
+                byte: 0x99,
+            },
+            |after| after.v(0xE, 0x99),
+        );
+    }
+
+    #[test]
+    fn execute_add() {
+        check(
+            State::new().v(0x2, 0x10),
+            OP::ADD {
+                vx: 0x2,
+                byte: 0x05,
+            },
+            |after| after.v(0x2, 0x15),
+        );
+
+        // Wraps and never touches VF
+        check(
+            State::new().v(0x2, 0xFF).v(0xF, 0x7),
+            OP::ADD {
+                vx: 0x2,
+                byte: 0x02,
+            },
+            |after| after.v(0x2, 0x01),
+        );
+    }
+
+    #[test]
+    fn execute_ldr() {
+        check(
+            State::new().v(0x5, 0x33),
+            OP::LDR { vx: 0x4, vy: 0x5 },
+            |after| after.v(0x4, 0x33),
+        );
+    }
+
+    #[test]
+    fn execute_or_and_xor() {
+        let before = State::new().v(0x0, 0b1100).v(0x1, 0b1010);
+        check(before.clone(), OP::OR { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0b1110)
+        });
+        check(before.clone(), OP::AND { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0b1000)
+        });
+        check(before, OP::XOR { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0b0110)
+        });
+    }
+
+    #[test]
+    fn execute_addr() {
+        let before = State::new().v(0x0, 0x10).v(0x1, 0x20).v(0xF, 1);
+        check(before, OP::ADDR { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x30).v(0xF, 0)
+        });
+
+        let before = State::new().v(0x0, 0xF0).v(0x1, 0x20);
+        check(before, OP::ADDR { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x10).v(0xF, 1)
+        });
+    }
+
+    #[test]
+    fn execute_addr_vf_operand() {
+        // The flag is written last so it replaces the sum
+        let before = State::new().v(0xF, 0xF0).v(0x1, 0x20);
+        check(before, OP::ADDR { vx: 0xF, vy: 0x1 }, |after| {
+            after.v(0xF, 1)
+        });
+
+        let before = State::new().v(0x0, 0x01).v(0xF, 0xFF);
+        check(before, OP::ADDR { vx: 0x0, vy: 0xF }, |after| {
+            after.v(0x0, 0x00).v(0xF, 1)
+        });
+    }
+
+    #[test]
+    fn execute_sub() {
+        let before = State::new().v(0x0, 0x30).v(0x1, 0x10);
+        check(before, OP::SUB { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x20).v(0xF, 1)
+        });
+
+        let before = State::new().v(0x0, 0x10).v(0x1, 0x30).v(0xF, 1);
+        check(before, OP::SUB { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0xE0).v(0xF, 0)
+        });
+
+        let before = State::new().v(0x0, 0x10).v(0x1, 0x10);
+        check(before, OP::SUB { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x00).v(0xF, 1)
+        });
+    }
+
+    #[test]
+    fn execute_sub_vf_operand() {
+        let before = State::new().v(0xF, 0x05).v(0x1, 0x10);
+        check(before, OP::SUB { vx: 0xF, vy: 0x1 }, |after| {
+            after.v(0xF, 0)
+        });
+    }
+
+    #[test]
+    fn execute_subn() {
+        let before = State::new().v(0x0, 0x10).v(0x1, 0x30);
+        check(before, OP::SUBN { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x20).v(0xF, 1)
+        });
+
+        let before = State::new().v(0x0, 0x30).v(0x1, 0x10).v(0xF, 1);
+        check(before, OP::SUBN { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0xE0).v(0xF, 0)
+        });
+
+        let before = State::new().v(0x0, 0x10).v(0x1, 0x10);
+        check(before, OP::SUBN { vx: 0x0, vy: 0x1 }, |after| {
+            after.v(0x0, 0x00).v(0xF, 1)
+        });
+    }
+
+    #[test]
+    fn execute_subn_vf_operand() {
+        let before = State::new().v(0x0, 0x10).v(0xF, 0x30);
+        check(before, OP::SUBN { vx: 0x0, vy: 0xF }, |after| {
+            after.v(0x0, 0x20).v(0xF, 1)
+        });
+    }
+
+    #[test]
+    fn execute_shr() {
+        check(
+            State::new().v(0x6, 0b101),
+            OP::SHR { vx: 0x6, vy: 0x0 },
+            |after| after.v(0x6, 0b10).v(0xF, 1),
+        );
+        check(
+            State::new().v(0x6, 0b100).v(0xF, 1),
+            OP::SHR { vx: 0x6, vy: 0x0 },
+            |after| after.v(0x6, 0b10).v(0xF, 0),
+        );
+        // Only the shifted out bit survives
+        check(
+            State::new().v(0xF, 0b11),
+            OP::SHR { vx: 0xF, vy: 0x0 },
+            |after| after.v(0xF, 1),
+        );
+    }
+
+    #[test]
+    fn execute_shl() {
+        check(
+            State::new().v(0x6, 0x81),
+            OP::SHL { vx: 0x6, vy: 0x0 },
+            |after| after.v(0x6, 0x02).v(0xF, 1),
+        );
+        check(
+            State::new().v(0x6, 0x41).v(0xF, 1),
+            OP::SHL { vx: 0x6, vy: 0x0 },
+            |after| after.v(0x6, 0x82).v(0xF, 0),
+        );
+        check(
+            State::new().v(0xF, 0x40),
+            OP::SHL { vx: 0xF, vy: 0x0 },
+            |after| after.v(0xF, 0),
+        );
+    }
+
+    #[test]
+    fn execute_ldi() {
+        check(State::new(), OP::LDI { addr: 0x123 }, |after| {
+            after.index(0x123)
+        });
+    }
+
+    #[test]
+    fn execute_jpr() {
+        check(
+            State::new().v(0x0, 0x10),
+            OP::JPR { addr: 0x300 },
+            |after| after.pc(0x310),
+        );
+    }
+
+    #[test]
+    fn execute_rnd() {
+        // Can't predict the value but the mask has to hold
+        for _ in 0..32 {
+            let chip8 = run(
+                &State::new(),
+                OP::RND {
+                    vx: 0x3,
+                    byte: 0x0F,
+                },
+            );
+            assert_eq!(chip8.registers[0x3] & 0xF0, 0);
+        }
+
+        check(
+            State::new().v(0x3, 0x55),
+            OP::RND {
+                vx: 0x3,
+                byte: 0x00,
+            },
+            |after| after.v(0x3, 0x00),
+        );
+    }
+
+    #[test]
+    fn execute_drw() {
+        let before = State::new()
+            .v(0x0, 2)
+            .v(0x1, 3)
+            .index(0x300)
+            .memory(0x300, &[0b1000_0001, 0b0100_0000]);
+        check(
+            before,
+            OP::DRW {
+                vx: 0x0,
+                vy: 0x1,
+                height: 2,
+            },
+            |after| after.lit(2, 3).lit(9, 3).lit(3, 4),
+        );
+    }
+
+    #[test]
+    fn execute_drw_collision() {
+        let before = State::new()
+            .index(0x300)
+            .memory(0x300, &[0b1100_0000])
+            .lit(0, 0)
+            .lit(5, 0);
+        check(
+            before,
+            OP::DRW {
+                vx: 0x0,
+                vy: 0x1,
+                height: 1,
+            },
+            |after| after.unlit(0, 0).lit(1, 0).v(0xF, 1),
+        );
+
+        // VF is cleared when nothing collides
+        let before = State::new().index(0x300).memory(0x300, &[0x80]).v(0xF, 1);
+        check(
+            before,
+            OP::DRW {
+                vx: 0x0,
+                vy: 0x1,
+                height: 1,
+            },
+            |after| after.lit(0, 0).v(0xF, 0),
+        );
+    }
+
+    #[test]
+    fn execute_drw_clips_at_edges() {
+        let before = State::new()
+            .v(0x0, 62)
+            .v(0x1, 31)
+            .index(0x300)
+            .memory(0x300, &[0xFF, 0xFF]);
+        check(
+            before,
+            OP::DRW {
+                vx: 0x0,
+                vy: 0x1,
+                height: 2,
+            },
+            |after| after.lit(62, 31).lit(63, 31),
+        );
+    }
+
+    #[test]
+    fn execute_drw_wraps_start() {
+        let before = State::new()
+            .v(0x0, 64 + 1)
+            .v(0x1, 32 + 2)
+            .index(0x300)
+            .memory(0x300, &[0x80]);
+        check(
+            before,
+            OP::DRW {
+                vx: 0x0,
+                vy: 0x1,
+                height: 1,
+            },
+            |after| after.lit(1, 2),
+        );
+    }
+
+    #[test]
+    fn execute_drw_vf_operand() {
+        // Position is read before VF gets cleared
+        let before = State::new().v(0xF, 4).index(0x300).memory(0x300, &[0x80]);
+        check(
+            before,
+            OP::DRW {
+                vx: 0xF,
+                vy: 0xF,
+                height: 1,
+            },
+            |after| after.lit(4, 4).v(0xF, 0),
+        );
+    }
+
+    #[test]
+    fn execute_skp() {
+        let before = State::new().v(0x2, 0xB).key(0xB);
+        check(before, OP::SKP { vx: 0x2 }, |after| after.pc(0x204));
+
+        // Key 2 is held, not the one in V2
+        let before = State::new().v(0x2, 0xB).key(0x2);
+        check(before, OP::SKP { vx: 0x2 }, |after| after);
+    }
+
+    #[test]
+    fn execute_sknp() {
+        let before = State::new().v(0x2, 0xB).key(0x2);
+        check(before, OP::SKNP { vx: 0x2 }, |after| after.pc(0x204));
+
+        let before = State::new().v(0x2, 0xB).key(0xB);
+        check(before, OP::SKNP { vx: 0x2 }, |after| after);
+    }
+
+    #[test]
+    fn execute_lddt() {
+        check(State::new().delay(40), OP::LDDT { vx: 0x7 }, |after| {
+            after.v(0x7, 40)
+        });
+    }
+
+    #[test]
+    fn execute_ldk() {
+        // Blocks by going back over itself
+        check(State::new().key(0x4), OP::LDK { vx: 0x7 }, |after| {
+            after.pc(0x200)
+        });
+
+        let quirks = Quirks {
+            wait_for_release: false,
+        };
+        check(
+            State::new().key(0x4).quirks(quirks),
+            OP::LDK { vx: 0x7 },
+            |after| after.v(0x7, 0x4),
+        );
+    }
+
+    #[test]
+    fn execute_ldt_and_ldst() {
+        check(State::new().v(0x1, 60), OP::LDT { vx: 0x1 }, |after| {
+            after.delay(60)
+        });
+        check(State::new().v(0x1, 60), OP::LDST { vx: 0x1 }, |after| {
+            after.sound(60)
+        });
+    }
+
+    #[test]
+    fn execute_addi() {
+        let before = State::new().index(0x300).v(0x5, 0x22);
+        check(before, OP::ADDI { vx: 0x5 }, |after| after.index(0x322));
+
+        // No carry into VF
+        let before = State::new().index(0xFFF).v(0x5, 0x01);
+        check(before, OP::ADDI { vx: 0x5 }, |after| after.index(0x1000));
+    }
+
+    #[test]
+    fn execute_ldf() {
+        check(State::new().v(0x0, 0xA), OP::LDF { vx: 0x0 }, |after| {
+            after.index(Chip8::FONT_ADDRESS + 50)
+        });
+    }
+
+    #[test]
+    fn execute_ldb() {
+        let before = State::new().v(0x9, 254).index(0x300);
+        check(before, OP::LDB { vx: 0x9 }, |after| {
+            after.memory(0x300, &[2, 5, 4])
+        });
+
+        let before = State::new()
+            .v(0x9, 7)
+            .index(0x300)
+            .memory(0x300, &[9, 9, 9]);
+        check(before, OP::LDB { vx: 0x9 }, |after| {
+            after.memory(0x300, &[0, 0, 7])
+        });
+    }
+
+    #[test]
+    fn execute_ldia() {
+        let before = State::new().v(0x0, 1).v(0x1, 2).v(0x2, 3).index(0x300);
+        check(before, OP::LDIA { vx: 0x1 }, |after| {
+            after.memory(0x300, &[1, 2])
+        });
+
+        // Up to and including VF, I stays put
+        let before = State::new().v(0xF, 0xEE).index(0x300);
+        check(before, OP::LDIA { vx: 0xF }, |after| {
+            after.memory(0x30F, &[0xEE])
+        });
+    }
+
+    #[test]
+    fn execute_ldra() {
+        let before = State::new().index(0x300).memory(0x300, &[1, 2, 3]);
+        check(before, OP::LDRA { vx: 0x1 }, |after| {
+            after.v(0x0, 1).v(0x1, 2)
+        });
+
+        let before = State::new().index(0x300).memory(0x30F, &[0xEE]);
+        check(before, OP::LDRA { vx: 0xF }, |after| after.v(0xF, 0xEE));
+    }
+
+    #[test]
+    fn execute_inv() {
+        check(State::new(), OP::INV { opcode: 0xFFFF }, |after| after);
+    }
+
+    #[test]
+    fn stack_faults() {
+        assert_eq!(
+            fault(&State::new().pc(0x300), OP::RET),
+            Error::StackUnderflow { address: 0x300 }
+        );
+
+        let full = State::new().stack(&[0x200; 16]);
+        assert_eq!(
+            fault(&full, OP::CALL { addr: 0x400 }),
+            Error::StackOverflow { address: 0x200 }
+        );
+
+        // The last slot is still usable
+        let mut stack = [0x300; 16];
+        stack[15] = 0x202;
+        check(
+            State::new().stack(&stack[..15]),
+            OP::CALL { addr: 0x400 },
+            |after| after.stack(&stack).pc(0x400),
+        );
+    }
+
+    #[test]
+    fn oversized_rom() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.load_bytes(&[0xFF; 4096 - 0x200]).is_ok());
+        assert!(chip8.load_bytes(&[0xFF; 4096 - 0x200 + 1]).is_err());
+    }
+
+    #[test]
+    fn memory_wraps_at_the_end() {
+        // The second byte of the instruction comes from address 0
+        let mut chip8 = State::new()
+            .pc(0xFFF)
+            .memory(0xFFF, &[0x6A])
+            .memory(0x000, &[0x42])
+            .build();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0xA], 0x42);
+        assert_eq!(chip8.program_counter, 0x1001);
+
+        let before = State::new().index(0xFFE).v(0x0, 1).v(0x1, 2).v(0x2, 3);
+        check(before, OP::LDIA { vx: 0x2 }, |after| {
+            after.memory(0xFFE, &[1, 2]).memory(0x000, &[3])
+        });
+
+        check(
+            State::new().index(0xFFFF).v(0x0, 2),
+            OP::ADDI { vx: 0x0 },
+            |after| after.index(0x0001),
+        );
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // FX55 rewrites LD V2, 0x01 at 0x206 to LD V2, 0x07 after it has run once
+        let mut chip8 = Chip8::new();
+        load_program(
+            &mut chip8,
+            &[0x6062, 0x6107, 0xA206, 0x6201, 0xF155, 0x1206],
+        );
+        for _ in 0..6 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x2], 0x01);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0x2], 0x07);
+
+        // Writing only the second byte counts too
+        chip8.write_memory(0x207, &[0x09]);
+        chip8.program_counter = 0x206;
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0x2], 0x09);
+
+        // FX33 puts 1, 2, 3 at 0x209, turning LD V3, 0x00 at 0x208 into LD V3, 0x01
+        let mut chip8 = Chip8::new();
+        load_program(
+            &mut chip8,
+            &[0x607B, 0xA209, 0x1208, 0xF033, 0x6300, 0x1206],
+        );
+        for _ in 0..6 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x3], 0x00);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0x3], 0x01);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn decode_cache_changes_nothing() {
+        use super::trace::Step;
+
+        // Timendus corax+ and flags tests, picked the same way tests/conformance.rs does
+        let rom = include_bytes!("../../tests/roms/timendus/chip8-test-suite.ch8");
+        for test in [2, 3] {
+            let mut cached = Chip8::new();
+            let mut uncached = Chip8::new();
+            uncached.set_decode_cache(false);
+            for chip8 in [&mut cached, &mut uncached] {
+                chip8.load_bytes(rom).unwrap();
+                chip8.write_memory(0x1FF, &[test]);
+            }
+
+            for cycle in 0..8000 {
+                assert_eq!(
+                    Step::of(&cached),
+                    Step::of(&uncached),
+                    "Test {} cycle {}",
+                    test,
+                    cycle
+                );
+                cached.cycle().unwrap();
+                uncached.cycle().unwrap();
+            }
+            assert_eq!(cached.get_video_buffer(), uncached.get_video_buffer());
+            assert!(cached.memory.0 == uncached.memory.0);
+        }
+    }
+
+    #[test]
+    fn seeded_random_repeats() {
+        let registers = |seed| {
+            let mut chip8 = Chip8::new();
+            chip8.set_random(Random::Seeded(seed));
+            load_program(&mut chip8, &[0xC0FF, 0xC10F, 0xC2F0, 0xC3FF]);
+            for _ in 0..4 {
+                chip8.cycle().unwrap();
+            }
+            chip8.registers
+        };
+        assert_eq!(registers(3), registers(3));
+        assert_ne!(registers(3), registers(4));
+        // Masked like always
+        assert_eq!(registers(3)[0x1] & 0xF0, 0);
+    }
+
+    #[test]
+    fn skip_on_key_uses_low_nibble() {
+        let before = State::new().v(0x2, 0x1B).key(0xB);
+        check(before, OP::SKP { vx: 0x2 }, |after| after.pc(0x204));
+    }
+}
@@ -1,6 +1,6 @@
 // These OPs will be capitalized, RFC be aware!
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum OP {
     CLS,
     RET,
@@ -18,9 +18,9 @@ pub enum OP {
     XOR { vx: u8, vy: u8 },
     ADDR { vx: u8, vy: u8 }, // This is inconsistent with the other names
     SUB { vx: u8, vy: u8 },
-    SHR { vx: u8 },
+    SHR { vx: u8, vy: u8 }, // Neither shift uses vy, it's kept so the word round trips through u16::from
     SUBN { vx: u8, vy: u8 },
-    SHL { vx: u8 },
+    SHL { vx: u8, vy: u8 },
     SNER { vx: u8, vy: u8 },
     LDI { addr: u16 },
     JPR { addr: u16 },
@@ -78,9 +78,9 @@ impl From<u16> for OP {
                     0x03 => OP::XOR { vx, vy },
                     0x04 => OP::ADDR { vx, vy },
                     0x05 => OP::SUB { vx, vy },
-                    0x06 => OP::SHR { vx }, // Potentially move vy here also? Used in orignal implemenation
+                    0x06 => OP::SHR { vx, vy },
                     0x07 => OP::SUBN { vx, vy },
-                    0x0E => OP::SHL { vx },
+                    0x0E => OP::SHL { vx, vy },
                     _ => OP::INV { opcode: v },
                 }
             }
@@ -125,8 +125,56 @@ impl From<u16> for OP {
     }
 }
 
-impl std::fmt::Display for OP {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+// Back to the instruction word, every OP other than INV re-encodes to the word it was
+// decoded from and INV keeps its word as is
+impl From<OP> for u16 {
+    fn from(op: OP) -> Self {
+        let x = |vx: u8| (vx as u16 & 0x0F) << 8;
+        let xy = |vx: u8, vy: u8| x(vx) | (vy as u16 & 0x0F) << 4;
+        let xkk = |vx: u8, byte: u8| x(vx) | byte as u16;
+
+        match op {
+            OP::CLS => 0x00E0,
+            OP::RET => 0x00EE,
+            OP::JP { addr } => 0x1000 | addr & 0x0FFF,
+            OP::CALL { addr } => 0x2000 | addr & 0x0FFF,
+            OP::SE { vx, byte } => 0x3000 | xkk(vx, byte),
+            OP::SNE { vx, byte } => 0x4000 | xkk(vx, byte),
+            OP::SER { vx, vy } => 0x5000 | xy(vx, vy),
+            OP::LD { vx, byte } => 0x6000 | xkk(vx, byte),
+            OP::ADD { vx, byte } => 0x7000 | xkk(vx, byte),
+            OP::LDR { vx, vy } => 0x8000 | xy(vx, vy),
+            OP::OR { vx, vy } => 0x8001 | xy(vx, vy),
+            OP::AND { vx, vy } => 0x8002 | xy(vx, vy),
+            OP::XOR { vx, vy } => 0x8003 | xy(vx, vy),
+            OP::ADDR { vx, vy } => 0x8004 | xy(vx, vy),
+            OP::SUB { vx, vy } => 0x8005 | xy(vx, vy),
+            OP::SHR { vx, vy } => 0x8006 | xy(vx, vy),
+            OP::SUBN { vx, vy } => 0x8007 | xy(vx, vy),
+            OP::SHL { vx, vy } => 0x800E | xy(vx, vy),
+            OP::SNER { vx, vy } => 0x9000 | xy(vx, vy),
+            OP::LDI { addr } => 0xA000 | addr & 0x0FFF,
+            OP::JPR { addr } => 0xB000 | addr & 0x0FFF,
+            OP::RND { vx, byte } => 0xC000 | xkk(vx, byte),
+            OP::DRW { vx, vy, height } => 0xD000 | xy(vx, vy) | height as u16 & 0x0F,
+            OP::SKP { vx } => 0xE09E | x(vx),
+            OP::SKNP { vx } => 0xE0A1 | x(vx),
+            OP::LDDT { vx } => 0xF007 | x(vx),
+            OP::LDK { vx } => 0xF00A | x(vx),
+            OP::LDT { vx } => 0xF015 | x(vx),
+            OP::LDST { vx } => 0xF018 | x(vx),
+            OP::ADDI { vx } => 0xF01E | x(vx),
+            OP::LDF { vx } => 0xF029 | x(vx),
+            OP::LDB { vx } => 0xF033 | x(vx),
+            OP::LDIA { vx } => 0xF055 | x(vx),
+            OP::LDRA { vx } => 0xF065 | x(vx),
+            OP::INV { opcode } => opcode,
+        }
+    }
+}
+
+impl core::fmt::Display for OP {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(match *self {
             OP::CLS => "CLS",
             OP::RET => "RET",
@@ -345,9 +393,9 @@ mod test {
 
     #[test]
     fn parse_shift_right() {
-        assert_eq!(OP::from(0x8006), OP::SHR { vx: 0x00 });
-        assert_eq!(OP::from(0x8456), OP::SHR { vx: 0x04 });
-        assert_eq!(OP::from(0x8FF6), OP::SHR { vx: 0x0F });
+        assert_eq!(OP::from(0x8006), OP::SHR { vx: 0x00, vy: 0x00 });
+        assert_eq!(OP::from(0x8456), OP::SHR { vx: 0x04, vy: 0x05 });
+        assert_eq!(OP::from(0x8FF6), OP::SHR { vx: 0x0F, vy: 0x0F });
     }
 
     #[test]
@@ -359,9 +407,9 @@ mod test {
 
     #[test]
     fn parse_shift_left() {
-        assert_eq!(OP::from(0x800E), OP::SHL { vx: 0x00 });
-        assert_eq!(OP::from(0x845E), OP::SHL { vx: 0x04 });
-        assert_eq!(OP::from(0x8FFE), OP::SHL { vx: 0x0F });
+        assert_eq!(OP::from(0x800E), OP::SHL { vx: 0x00, vy: 0x00 });
+        assert_eq!(OP::from(0x845E), OP::SHL { vx: 0x04, vy: 0x05 });
+        assert_eq!(OP::from(0x8FFE), OP::SHL { vx: 0x0F, vy: 0x0F });
     }
 
     #[test]
@@ -508,4 +556,13 @@ mod test {
         assert_eq!(OP::from(0xF065), OP::LDRA { vx: 0x00 });
         assert_eq!(OP::from(0xFF65), OP::LDRA { vx: 0x0F });
     }
+
+    #[test]
+    fn encode_round_trip() {
+        for word in [
+            0x00E0, 0x00EE, 0x1234, 0x5AB0, 0x8AB4, 0x8AB6, 0xD12F, 0xEFA1, 0xF165, 0x0123,
+        ] {
+            assert_eq!(u16::from(OP::from(word)), word);
+        }
+    }
 }
@@ -0,0 +1,76 @@
+// A machine taken apart, so `batch` can keep each piece of thousands of machines in
+// an array of its own: V0-VF of every machine together, then every I, every PC and so
+// on. To run one its pieces are swapped into a `Chip8`, and swapped back out after.
+//
+// Decoded instructions and compiled code aren't pieces. They come from whichever
+// memory was swapped in last, so every swap drops them.
+
+use super::{Chip8, KeyPad, KeyWait, Quirks, Random, VideoBuffer};
+
+// What an instruction touches less often, kept whole
+#[derive(Clone)]
+pub(crate) struct Rest {
+    keypad: KeyPad,
+    video: VideoBuffer,
+    opcode: u16,
+    quirks: Quirks,
+    key_wait: KeyWait,
+    new_presses: u16,
+    random: Random,
+}
+
+impl Default for Rest {
+    fn default() -> Self {
+        Rest {
+            keypad: KeyPad::new(),
+            video: VideoBuffer::new(),
+            opcode: 0,
+            quirks: Quirks::default(),
+            key_wait: KeyWait::Idle,
+            new_presses: 0,
+            random: Random::default(),
+        }
+    }
+}
+
+// One machine's pieces, borrowed from the arrays they're kept in
+pub(crate) struct Parts<'a> {
+    pub registers: &'a mut [u8; 16],
+    pub index: &'a mut u16,
+    pub program_counter: &'a mut u16,
+    pub stack: &'a mut [u16; 16],
+    pub stack_pointer: &'a mut u8,
+    pub delay_timer: &'a mut u8,
+    pub sound_timer: &'a mut u8,
+    pub memory: &'a mut [u8; 4096],
+    pub rest: &'a mut Rest,
+}
+
+impl Chip8 {
+    // Trades this machine for the one in `parts`, doing it again puts both back
+    pub(crate) fn swap_parts(&mut self, parts: Parts<'_>) {
+        core::mem::swap(&mut self.registers, parts.registers);
+        core::mem::swap(&mut self.index, parts.index);
+        core::mem::swap(&mut self.program_counter, parts.program_counter);
+        core::mem::swap(&mut self.stack.0, parts.stack);
+        core::mem::swap(&mut self.stack_pointer, parts.stack_pointer);
+        core::mem::swap(&mut self.delay_timer, parts.delay_timer);
+        core::mem::swap(&mut self.sound_timer, parts.sound_timer);
+        core::mem::swap(&mut self.memory.0, parts.memory);
+
+        let rest = parts.rest;
+        core::mem::swap(&mut self.keypad, &mut rest.keypad);
+        core::mem::swap(&mut self.video, &mut rest.video);
+        core::mem::swap(&mut self.opcode, &mut rest.opcode);
+        core::mem::swap(&mut self.quirks, &mut rest.quirks);
+        core::mem::swap(&mut self.key_wait, &mut rest.key_wait);
+        core::mem::swap(&mut self.new_presses, &mut rest.new_presses);
+        core::mem::swap(&mut self.random, &mut rest.random);
+
+        self.decoded.clear();
+        #[cfg(feature = "jit")]
+        if self.jit.enabled() {
+            self.jit.clear();
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Where CXKK gets its random bytes. std builds use the thread RNG like they always
+// have, no_std ones start from a fixed seed unless the board hands in something better
+
+#[derive(Clone, Copy, Debug)]
+pub enum Random {
+    // Xorshift, the same seed always gives the same bytes. 0 is swapped for 1 as
+    // xorshift never leaves it
+    Seeded(u32),
+    // A hardware RNG or anything else that can produce a byte
+    Source(fn() -> u8),
+}
+
+impl Default for Random {
+    #[cfg(feature = "std")]
+    fn default() -> Self {
+        Random::Source(rand::random::<u8>)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> Self {
+        Random::Seeded(0x2545_F491)
+    }
+}
+
+impl Random {
+    pub fn byte(&mut self) -> u8 {
+        match self {
+            Random::Seeded(state) => {
+                let mut x = (*state).max(1);
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                *state = x;
+                // The top bits are the better mixed ones
+                (x >> 24) as u8
+            }
+            Random::Source(source) => source(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_bytes_repeat() {
+        let bytes = |seed| {
+            let mut random = Random::Seeded(seed);
+            [(); 8].map(|_| random.byte())
+        };
+        assert_eq!(bytes(7), bytes(7));
+        assert_ne!(bytes(7), bytes(8));
+        assert_eq!(bytes(0), bytes(1));
+    }
+
+    #[test]
+    fn sources_are_called() {
+        let mut random = Random::Source(|| 0x5A);
+        assert_eq!(random.byte(), 0x5A);
+    }
+}
@@ -0,0 +1,46 @@
+// What code translated ahead of time by `aot` runs on. Translated blocks keep V0-VF
+// and I in locals, hand them back before anything that goes through the interpreter
+// and tick the timers for the instructions they ran themselves.
+
+use super::opcodes::OP;
+use super::{Chip8, Error};
+
+impl Chip8 {
+    pub fn registers(&self) -> ([u8; 16], u16) {
+        (self.registers, self.index)
+    }
+
+    pub fn set_registers(&mut self, v: [u8; 16], i: u16) {
+        self.registers = v;
+        self.index = i;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    // What `cycles` instructions worth of `cycle` does to the timers
+    pub fn tick_timers(&mut self, cycles: usize) {
+        let cycles = cycles.min(u8::MAX as usize) as u8;
+        self.delay_timer = self.delay_timer.saturating_sub(cycles);
+        self.sound_timer = self.sound_timer.saturating_sub(cycles);
+    }
+
+    // Runs `word` as if it had been fetched from `address`, same as `cycle` otherwise
+    pub fn execute_at(&mut self, address: u16, word: u16) -> Result<(), Error> {
+        self.program_counter = address.wrapping_add(2);
+        let result = self.execute(OP::from(word));
+        self.tick_timers(1);
+        result
+    }
+
+    // Whether memory from `address` on still holds `bytes`
+    pub fn code_matches(&self, address: u16, bytes: &[u8]) -> bool {
+        let start = address as usize;
+        self.memory.0.get(start..start + bytes.len()) == Some(bytes)
+    }
+}
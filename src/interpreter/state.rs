@@ -0,0 +1,279 @@
+// Save states, everything a running ROM can see packed into a fixed number of bytes
+// so they can live in a plain buffer without allocating. Quirks aren't in them, they
+// belong to whoever is running the machine, and neither are the caches, which are
+// cleared on load.
+//
+// Layout, multi-byte values big endian like the rest of chip-8:
+// magic and version, V0-VF, I, PC, stack, SP, DT, ST, held keys and FX0A's new
+// presses as bit masks, where FX0A is at, the random state, the screen one bit per
+// pixel, then all of memory.
+
+use super::{Chip8, KeyWait, Random};
+
+const MAGIC: &[u8; 4] = b"P8ST";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StateError {
+    // Buffer to save into or state to load isn't `Chip8::STATE_SIZE` bytes
+    Size { expected: usize, got: usize },
+    // Not a save state, or one from a layout this doesn't know
+    Unrecognised,
+}
+
+impl core::fmt::Display for StateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StateError::Size { expected, got } => {
+                write!(f, "Save states are {} bytes, this one is {}", expected, got)
+            }
+            StateError::Unrecognised => write!(f, "Not a save state this version can load"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+// Walks through a save state a field at a time
+struct Cursor<'a> {
+    bytes: &'a mut [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a mut [u8]) -> Self {
+        Cursor { bytes, at: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> &mut [u8] {
+        self.at += len;
+        &mut self.bytes[self.at - len..self.at]
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.take(bytes.len()).copy_from_slice(bytes);
+    }
+}
+
+// The same for loading
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        self.at += len;
+        &self.bytes[self.at - len..self.at]
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn word(&mut self) -> u16 {
+        let bytes = self.take(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+}
+
+fn bits(flags: impl Iterator<Item = bool>) -> u16 {
+    flags
+        .enumerate()
+        .fold(0, |mask, (n, set)| mask | (set as u16) << n)
+}
+
+impl Chip8 {
+    pub const STATE_SIZE: usize = 4 // magic
+        + 1 // version
+        + 16 // V0-VF
+        + 2 // I
+        + 2 // PC
+        + 16 * 2 // stack
+        + 1 // SP
+        + 2 // DT and ST
+        + 2 // held keys
+        + 2 // new presses
+        + 2 // FX0A
+        + 5 // random
+        + 64 * 32 / 8 // screen
+        + 4096; // memory
+
+    // `out` has to be exactly `STATE_SIZE` long
+    pub fn save_state(&self, out: &mut [u8]) -> Result<(), StateError> {
+        if out.len() != Chip8::STATE_SIZE {
+            return Err(StateError::Size {
+                expected: Chip8::STATE_SIZE,
+                got: out.len(),
+            });
+        }
+
+        let mut cursor = Cursor::new(out);
+        cursor.put(MAGIC);
+        cursor.put(&[VERSION]);
+        cursor.put(&self.registers);
+        cursor.put(&self.index.to_be_bytes());
+        cursor.put(&self.program_counter.to_be_bytes());
+        for address in self.stack.0 {
+            cursor.put(&address.to_be_bytes());
+        }
+        cursor.put(&[self.stack_pointer, self.delay_timer, self.sound_timer]);
+        cursor.put(&bits(self.pressed_keys().into_iter()).to_be_bytes());
+        cursor.put(&self.new_presses.to_be_bytes());
+        cursor.put(&match self.key_wait {
+            KeyWait::Idle => [0, 0],
+            KeyWait::Press => [1, 0],
+            KeyWait::Release(key) => [2, key],
+        });
+        // Sources can't be saved, loading keeps whatever the machine already has
+        match self.random {
+            Random::Seeded(seed) => {
+                cursor.put(&[1]);
+                cursor.put(&seed.to_be_bytes());
+            }
+            Random::Source(_) => cursor.put(&[0; 5]),
+        }
+        for pixels in self.video.0.chunks(8) {
+            cursor.put(&[bits(pixels.iter().map(|pixel| *pixel != 0)) as u8]);
+        }
+        cursor.put(&self.memory.0);
+        Ok(())
+    }
+
+    // Leaves the machine as it was if the state is no good
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() != Chip8::STATE_SIZE {
+            return Err(StateError::Size {
+                expected: Chip8::STATE_SIZE,
+                got: state.len(),
+            });
+        }
+
+        let mut reader = Reader {
+            bytes: state,
+            at: 0,
+        };
+        if reader.take(4) != MAGIC || reader.byte() != VERSION {
+            return Err(StateError::Unrecognised);
+        }
+        let registers = reader.take(16);
+        let index = reader.word();
+        let program_counter = reader.word();
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.word();
+        }
+        let [stack_pointer, delay_timer, sound_timer] =
+            [reader.byte(), reader.byte(), reader.byte()];
+        let keys = reader.word();
+        let new_presses = reader.word();
+        let key_wait = match [reader.byte(), reader.byte()] {
+            [0, _] => KeyWait::Idle,
+            [1, _] => KeyWait::Press,
+            [2, key] if key < 16 => KeyWait::Release(key),
+            _ => return Err(StateError::Unrecognised),
+        };
+        let random = match (reader.byte(), reader.take(4)) {
+            (0, _) => None,
+            (1, seed) => Some(Random::Seeded(u32::from_be_bytes(seed.try_into().unwrap()))),
+            _ => return Err(StateError::Unrecognised),
+        };
+        let video = reader.take(64 * 32 / 8);
+        let memory = reader.take(4096);
+        if stack_pointer as usize > stack.len() {
+            return Err(StateError::Unrecognised);
+        }
+
+        self.registers.copy_from_slice(registers);
+        self.index = index;
+        self.program_counter = program_counter;
+        self.stack.0 = stack;
+        self.stack_pointer = stack_pointer;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        for (key, state) in self.keypad.0.iter_mut().enumerate() {
+            *state = if keys & 1 << key != 0 { 0xFF } else { 0 };
+        }
+        self.new_presses = new_presses;
+        self.key_wait = key_wait;
+        if let Some(random) = random {
+            self.random = random;
+        }
+        for (n, pixel) in self.video.0.iter_mut().enumerate() {
+            *pixel = if video[n / 8] & 1 << (n % 8) != 0 {
+                0xFF
+            } else {
+                0
+            };
+        }
+        self.memory.0.copy_from_slice(memory);
+        self.decoded.clear();
+        #[cfg(feature = "jit")]
+        self.jit.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::testing::program;
+    use crate::interpreter::KeyEvent;
+
+    // Draws a digit, waits for a key, then counts up in V1 forever
+    fn running() -> Chip8 {
+        let mut chip8 = program(&[0x6007, 0xF029, 0xD005, 0xF30A, 0xF318, 0x7101, 0x120A]);
+        chip8.set_random(Random::Seeded(99));
+        chip8.run(4).unwrap();
+        chip8.handle_key(KeyEvent::Pressed(0xB));
+        chip8.run(2).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut chip8 = running();
+        let mut state = [0; Chip8::STATE_SIZE];
+        chip8.save_state(&mut state).unwrap();
+        // Mid FX0A with B held
+        assert_eq!(chip8.key_wait, KeyWait::Release(0xB));
+
+        let mut loaded = Chip8::new();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.get_video_buffer(), chip8.get_video_buffer());
+        assert_eq!(loaded.pressed_keys(), chip8.pressed_keys());
+
+        // Both carry on the same way from there
+        for chip8 in [&mut chip8, &mut loaded] {
+            chip8.handle_key(KeyEvent::Released(0xB));
+            chip8.run(50).unwrap();
+        }
+        let mut again = [0; Chip8::STATE_SIZE];
+        loaded.save_state(&mut again).unwrap();
+        chip8.save_state(&mut state).unwrap();
+        assert_eq!(state, again);
+        assert_eq!(loaded.registers().0[3], 0xB);
+        assert_ne!(loaded.registers().0[1], 0);
+    }
+
+    #[test]
+    fn bad_states_change_nothing() {
+        let chip8 = running();
+        let mut state = [0; Chip8::STATE_SIZE];
+        assert_eq!(
+            chip8.save_state(&mut state[..10]),
+            Err(StateError::Size {
+                expected: Chip8::STATE_SIZE,
+                got: 10
+            })
+        );
+        chip8.save_state(&mut state).unwrap();
+
+        let mut fresh = Chip8::new();
+        state[4] = VERSION + 1;
+        assert_eq!(fresh.load_state(&state), Err(StateError::Unrecognised));
+        assert_eq!(fresh.program_counter(), 0x200);
+        assert!(fresh.load_state(&state[1..]).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+// Test support for running single instructions.
+//
+// A `State` describes a machine declaratively, anything not mentioned is left as
+// it is after `Chip8::new`. `check` builds the machine, runs one OP and compares
+// every part of the result against the expected state, listing what differs:
+//
+//   check(
+//       State::new().v(0x0, 5).v(0x1, 5),
+//       OP::SUB { vx: 0x0, vy: 0x1 },
+//       |after| after.v(0x0, 0).v(0xF, 1),
+//   );
+//
+// The expectation starts from the initial state with the program counter already
+// moved past the instruction, so only the changes need spelling out.
+//
+// Tests elsewhere in the crate that need a whole program rather than one instruction
+// build it from words with `rom` or `program`.
+
+use super::{opcodes::OP, Chip8, Error, Quirks};
+
+#[derive(Clone, Debug)]
+pub struct State {
+    registers: [u8; 16],
+    index: u16,
+    program_counter: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    keys: [bool; 16],
+    // Applied in order so later writes win
+    memory: Vec<(u16, Vec<u8>)>,
+    pixels: Vec<(usize, usize, bool)>,
+    quirks: Quirks,
+}
+
+impl State {
+    pub fn new() -> Self {
+        State {
+            registers: [0; 16],
+            index: 0,
+            program_counter: Chip8::START_ADDRESS,
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            keys: [false; 16],
+            memory: Vec::new(),
+            pixels: Vec::new(),
+            quirks: Quirks::default(),
+        }
+    }
+
+    pub fn v(mut self, register: u8, value: u8) -> Self {
+        self.registers[register as usize] = value;
+        self
+    }
+
+    pub fn index(mut self, index: u16) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn pc(mut self, address: u16) -> Self {
+        self.program_counter = address;
+        self
+    }
+
+    // Return addresses, oldest first
+    pub fn stack(mut self, stack: &[u16]) -> Self {
+        self.stack = stack.to_vec();
+        self
+    }
+
+    pub fn delay(mut self, timer: u8) -> Self {
+        self.delay_timer = timer;
+        self
+    }
+
+    pub fn sound(mut self, timer: u8) -> Self {
+        self.sound_timer = timer;
+        self
+    }
+
+    pub fn key(mut self, key: u8) -> Self {
+        self.keys[key as usize] = true;
+        self
+    }
+
+    pub fn memory(mut self, address: u16, bytes: &[u8]) -> Self {
+        self.memory.push((address, bytes.to_vec()));
+        self
+    }
+
+    pub fn lit(mut self, x: usize, y: usize) -> Self {
+        self.pixels.push((x, y, true));
+        self
+    }
+
+    pub fn unlit(mut self, x: usize, y: usize) -> Self {
+        self.pixels.push((x, y, false));
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn build(&self) -> Chip8 {
+        let mut chip8 = Chip8::with_quirks(self.quirks);
+        chip8.registers = self.registers;
+        chip8.index = self.index;
+        chip8.program_counter = self.program_counter;
+        chip8.stack.0[..self.stack.len()].copy_from_slice(&self.stack);
+        chip8.stack_pointer = self.stack.len() as u8;
+        chip8.delay_timer = self.delay_timer;
+        chip8.sound_timer = self.sound_timer;
+
+        for (key, held) in self.keys.iter().enumerate() {
+            if *held {
+                chip8.keypad.0[key] = 0xFF;
+            }
+        }
+        for (address, bytes) in &self.memory {
+            chip8.write_memory(*address, bytes);
+        }
+        for (x, y, lit) in &self.pixels {
+            chip8.video.0[x + y * Chip8::VIDEO_WIDTH as usize] = if *lit { 0xFF } else { 0x00 };
+        }
+
+        chip8
+    }
+}
+
+// Everything about a machine that an instruction can change
+#[derive(PartialEq, Debug)]
+pub struct Snapshot {
+    registers: [u8; 16],
+    index: u16,
+    program_counter: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    keys: [bool; 16],
+    memory: Vec<u8>,
+    video: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn of(chip8: &Chip8) -> Self {
+        Snapshot {
+            registers: chip8.registers,
+            index: chip8.index,
+            program_counter: chip8.program_counter,
+            // Entries above the stack pointer are leftovers, they don't count
+            stack: chip8.stack.0[..chip8.stack_pointer as usize].to_vec(),
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            keys: chip8.pressed_keys(),
+            memory: chip8.memory.0.to_vec(),
+            video: chip8.video.0.to_vec(),
+        }
+    }
+
+    // One line per difference, empty when the snapshots match
+    pub fn diff(&self, expected: &Snapshot) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut compare = |name: String, expected: String, actual: String| {
+            if expected != actual {
+                lines.push(format!("{}: expected {}, got {}", name, expected, actual));
+            }
+        };
+
+        for (i, (e, a)) in expected.registers.iter().zip(&self.registers).enumerate() {
+            compare(format!("V{:X}", i), hex(*e), hex(*a));
+        }
+        compare("I".into(), hex(expected.index), hex(self.index));
+        compare(
+            "PC".into(),
+            hex(expected.program_counter),
+            hex(self.program_counter),
+        );
+        compare(
+            "stack".into(),
+            format!("{:04X?}", expected.stack),
+            format!("{:04X?}", self.stack),
+        );
+        compare(
+            "DT".into(),
+            expected.delay_timer.to_string(),
+            self.delay_timer.to_string(),
+        );
+        compare(
+            "ST".into(),
+            expected.sound_timer.to_string(),
+            self.sound_timer.to_string(),
+        );
+        compare(
+            "keys".into(),
+            held_keys(&expected.keys),
+            held_keys(&self.keys),
+        );
+
+        for (address, (e, a)) in expected.memory.iter().zip(&self.memory).enumerate() {
+            compare(format!("memory[{:#05X}]", address), hex(*e), hex(*a));
+        }
+
+        for (i, (e, a)) in expected.video.iter().zip(&self.video).enumerate() {
+            let (x, y) = (
+                i % Chip8::VIDEO_WIDTH as usize,
+                i / Chip8::VIDEO_WIDTH as usize,
+            );
+            compare(format!("pixel ({}, {})", x, y), pixel(*e), pixel(*a));
+        }
+
+        lines
+    }
+}
+
+fn hex<T: std::fmt::UpperHex>(value: T) -> String {
+    format!("{:#04X}", value)
+}
+
+fn pixel(value: u8) -> String {
+    if value != 0 { "lit" } else { "unlit" }.to_string()
+}
+
+fn held_keys(keys: &[bool; 16]) -> String {
+    let held = (0..16)
+        .filter(|key| keys[*key])
+        .map(|key| format!("{:X}", key))
+        .collect::<Vec<String>>();
+    format!("[{}]", held.join(", "))
+}
+
+// Runs a single OP the way `cycle` would, the program counter moves past it first
+pub fn run(state: &State, op: OP) -> Chip8 {
+    let mut chip8 = state.build();
+    chip8.program_counter += 2;
+    if let Err(e) = chip8.execute(op) {
+        panic!("{:?} failed: {}", op, e);
+    }
+    chip8
+}
+
+pub fn fault(state: &State, op: OP) -> Error {
+    let mut chip8 = state.build();
+    chip8.program_counter += 2;
+    chip8
+        .execute(op)
+        .expect_err(&format!("{:?} should have failed", op))
+}
+
+pub fn check<F: FnOnce(State) -> State>(before: State, op: OP, expect: F) {
+    let name = format!("{:?}", op);
+    let actual = Snapshot::of(&run(&before, op));
+
+    let pc = before.program_counter + 2;
+    let expected = Snapshot::of(&expect(before.pc(pc)).build());
+
+    let diff = actual.diff(&expected);
+    assert!(
+        diff.is_empty(),
+        "{} ended up wrong:\n  {}",
+        name,
+        diff.join("\n  ")
+    );
+}
+
+// Counts up in V0 while 5 is held, keeping it in BCD at 0x300
+#[cfg(feature = "std")]
+pub(crate) const COUNTER: &[u16] = &[0x6505, 0xA300, 0xE5A1, 0x7001, 0xF033, 0x1204];
+
+// Instruction words as the bytes of a ROM
+pub(crate) fn rom(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+// A fresh machine with `words` loaded at 0x200
+pub(crate) fn program(words: &[u16]) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.load_bytes(&rom(words)).unwrap();
+    chip8
+}
@@ -0,0 +1,581 @@
+// Instruction traces, the machine state right before every instruction runs.
+//
+// Traces are written as CSV or as a compact binary file and can be read back along
+// with logs exported from other emulators, then compared to find the first
+// instruction where two runs went different ways.
+//
+//   csv     a header naming the columns then one row per step, values in hex
+//   binary  "P8TRACE1" then 25 bytes per step: pc, opcode, v0-vf, i, sp, dt, st with
+//           the 16 bit values big endian
+//   log     one step per line as KEY:VALUE pairs in hex, i.e
+//           "PC:0200 OP:00E0 V0:00 ... VF:00 I:0000 SP:0 DT:00 ST:00". Keys can be
+//           upper or lower case, '=' works as well as ':', there can be a space
+//           after either and anything unknown is skipped, which covers what most
+//           emulators print. Fields a log doesn't have aren't compared. Written as
+//           .log, read from .log or .txt
+
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::Chip8;
+use super::OP;
+
+const MAGIC: &[u8; 8] = b"P8TRACE1";
+const RECORD_SIZE: usize = 25;
+
+// Every field a step has, in file order
+pub const FIELDS: [&str; 22] = [
+    "pc", "opcode", "v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "va", "vb", "vc",
+    "vd", "ve", "vf", "i", "sp", "dt", "st",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Step {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl Step {
+    // State before the instruction at PC runs
+    pub fn of(chip8: &Chip8) -> Self {
+        let pc = chip8.program_counter;
+        Step {
+            pc,
+            opcode: u16::from_be_bytes([
+                chip8.read_memory(pc),
+                chip8.read_memory(pc.wrapping_add(1)),
+            ]),
+            v: chip8.registers,
+            i: chip8.index,
+            sp: chip8.stack_pointer,
+            dt: chip8.delay_timer,
+            st: chip8.sound_timer,
+        }
+    }
+
+    // Value of FIELDS[field]
+    pub fn get(&self, field: usize) -> u16 {
+        match field {
+            0 => self.pc,
+            1 => self.opcode,
+            2..=17 => self.v[field - 2] as u16,
+            18 => self.i,
+            19 => self.sp as u16,
+            20 => self.dt as u16,
+            21 => self.st as u16,
+            _ => panic!("No trace field {}", field),
+        }
+    }
+
+    fn set(&mut self, field: usize, value: u16) {
+        match field {
+            0 => self.pc = value,
+            1 => self.opcode = value,
+            2..=17 => self.v[field - 2] = value as u8,
+            18 => self.i = value,
+            19 => self.sp = value as u8,
+            20 => self.dt = value as u8,
+            21 => self.st = value as u8,
+            _ => panic!("No trace field {}", field),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..2].copy_from_slice(&self.pc.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.opcode.to_be_bytes());
+        bytes[4..20].copy_from_slice(&self.v);
+        bytes[20..22].copy_from_slice(&self.i.to_be_bytes());
+        bytes[22] = self.sp;
+        bytes[23] = self.dt;
+        bytes[24] = self.st;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut v = [0; 16];
+        v.copy_from_slice(&bytes[4..20]);
+        Step {
+            pc: u16::from_be_bytes([bytes[0], bytes[1]]),
+            opcode: u16::from_be_bytes([bytes[2], bytes[3]]),
+            v,
+            i: u16::from_be_bytes([bytes[20], bytes[21]]),
+            sp: bytes[22],
+            dt: bytes[23],
+            st: bytes[24],
+        }
+    }
+
+    // Log line with just the fields given
+    pub fn line(&self, fields: &[usize]) -> String {
+        let mut line = String::new();
+        for &field in fields {
+            let digits = match field {
+                0 | 1 | 18 => 4,
+                19 => 1,
+                _ => 2,
+            };
+            let name = if field == 1 { "op" } else { FIELDS[field] };
+            let _ = write!(
+                line,
+                "{}:{:0width$X} ",
+                name.to_uppercase(),
+                self.get(field),
+                width = digits
+            );
+        }
+        line.trim_end().to_string()
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = (0..FIELDS.len()).collect::<Vec<usize>>();
+        write!(f, "{}  {}", self.line(&all), OP::from(self.opcode))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Csv,
+    Binary,
+    Log,
+}
+
+impl Format {
+    // .csv and .log or .txt by extension, binary otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("log") | Some("txt") => Format::Log,
+            _ => Format::Binary,
+        }
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: Format,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, format: Format) -> io::Result<Self> {
+        match format {
+            Format::Csv => writeln!(out, "{}", FIELDS.join(","))?,
+            Format::Binary => out.write_all(MAGIC)?,
+            Format::Log => {}
+        }
+        Ok(TraceWriter { out, format })
+    }
+
+    pub fn write(&mut self, step: &Step) -> io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                let values = (0..FIELDS.len())
+                    .map(|field| format!("{:X}", step.get(field)))
+                    .collect::<Vec<String>>();
+                writeln!(self.out, "{}", values.join(","))
+            }
+            Format::Binary => self.out.write_all(&step.to_bytes()),
+            Format::Log => writeln!(self.out, "{}", step),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl TraceWriter<io::BufWriter<std::fs::File>> {
+    // Format picked by extension, see `Format::from_path`
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        TraceWriter::new(io::BufWriter::new(file), Format::from_path(path))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    // Indices into FIELDS the trace actually has, anything else reads as zero
+    pub fields: Vec<usize>,
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Line {}: {}", line + 1, message),
+    )
+}
+
+fn parse_hex(value: &str) -> Option<u16> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u16::from_str_radix(value, 16).ok()
+}
+
+// Field for a key in someone else's log, None for keys we don't know
+fn log_field(key: &str) -> Option<usize> {
+    match key.to_lowercase().as_str() {
+        "op" | "opcode" | "instr" => Some(1),
+        "idx" | "index" => Some(18),
+        "delay" => Some(20),
+        "sound" => Some(21),
+        key => FIELDS.iter().position(|field| *field == key),
+    }
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Trace::parse(&std::fs::read(path)?)
+    }
+
+    // Works out the format from the contents
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if let Some(records) = bytes.strip_prefix(MAGIC) {
+            return Trace::parse_binary(records);
+        }
+
+        let text = std::str::from_utf8(bytes).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Trace isn't binary or text")
+        })?;
+        if text.starts_with(&FIELDS.join(",")) {
+            Trace::parse_csv(text)
+        } else {
+            Trace::parse_log(text)
+        }
+    }
+
+    fn parse_binary(records: &[u8]) -> io::Result<Self> {
+        if !records.len().is_multiple_of(RECORD_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Binary trace ends part way through a step",
+            ));
+        }
+
+        Ok(Trace {
+            steps: records.chunks(RECORD_SIZE).map(Step::from_bytes).collect(),
+            fields: (0..FIELDS.len()).collect(),
+        })
+    }
+
+    fn parse_csv(text: &str) -> io::Result<Self> {
+        let mut steps = Vec::new();
+        for (n, line) in text.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let values = line.split(',').collect::<Vec<&str>>();
+            if values.len() != FIELDS.len() {
+                return Err(invalid(
+                    n,
+                    format!("Expected {} values, got {}", FIELDS.len(), values.len()),
+                ));
+            }
+
+            let mut step = Step::default();
+            for (field, value) in values.iter().enumerate() {
+                let value = parse_hex(value)
+                    .ok_or_else(|| invalid(n, format!("Bad {}: {}", FIELDS[field], value)))?;
+                step.set(field, value);
+            }
+            steps.push(step);
+        }
+
+        Ok(Trace {
+            steps,
+            fields: (0..FIELDS.len()).collect(),
+        })
+    }
+
+    fn parse_log(text: &str) -> io::Result<Self> {
+        let mut steps = Vec::new();
+        let mut fields: Option<Vec<usize>> = None;
+
+        for (n, line) in text.lines().enumerate() {
+            let mut step = Step::default();
+            let mut seen = Vec::new();
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                let Some((key, mut value)) = token.split_once([':', '=']) else {
+                    continue;
+                };
+                // "PC: 0200", the value is the next token along
+                if value.is_empty() {
+                    match tokens.next() {
+                        Some(next) => value = next,
+                        None => continue,
+                    }
+                }
+                let Some(field) = log_field(key) else {
+                    continue;
+                };
+                let value = parse_hex(value)
+                    .ok_or_else(|| invalid(n, format!("Bad {}: {}", key, value)))?;
+                step.set(field, value);
+                if !seen.contains(&field) {
+                    seen.push(field);
+                }
+            }
+
+            // Lines with no fields at all are headers, blank lines and the like
+            if seen.is_empty() {
+                continue;
+            }
+            seen.sort_unstable();
+
+            match &fields {
+                None => fields = Some(seen),
+                Some(fields) if *fields != seen => {
+                    return Err(invalid(
+                        n,
+                        "Different fields than the first step".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+            steps.push(step);
+        }
+
+        if !fields.as_ref().is_some_and(|fields| fields.contains(&0)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Log has no steps with a PC",
+            ));
+        }
+
+        Ok(Trace {
+            steps,
+            fields: fields.unwrap_or_default(),
+        })
+    }
+}
+
+// Where two traces first went different ways
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    // Index of the first step that differs
+    pub step: usize,
+    // i.e "v3: expected 0x04, got 0x05", or a note that one trace ran out
+    pub differences: Vec<String>,
+    // Fields both traces have, the ones that were compared
+    pub fields: Vec<usize>,
+    // The steps leading up to and including the divergence, from each trace
+    pub expected: Vec<Step>,
+    pub actual: Vec<Step>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Traces diverge at step {}", self.step)?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+
+        let first = self.step + 1 - self.expected.len().max(self.actual.len());
+        for (name, steps) in [("expected", &self.expected), ("actual", &self.actual)] {
+            writeln!(f, "\n{}:", name)?;
+            for (n, step) in steps.iter().enumerate() {
+                let marker = if first + n == self.step { '>' } else { ' ' };
+                writeln!(
+                    f,
+                    "{} {:>8}  {}  {}",
+                    marker,
+                    first + n,
+                    step.line(&self.fields),
+                    OP::from(step.opcode)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// First step that differs in any field both traces have, with up to `context` steps
+// before it. None when they agree all the way through
+pub fn diverge(expected: &Trace, actual: &Trace, context: usize) -> Option<Divergence> {
+    let fields = expected
+        .fields
+        .iter()
+        .copied()
+        .filter(|field| actual.fields.contains(field))
+        .collect::<Vec<usize>>();
+
+    let length = expected.steps.len().max(actual.steps.len());
+    for n in 0..length {
+        let differences = match (expected.steps.get(n), actual.steps.get(n)) {
+            (Some(want), Some(got)) => fields
+                .iter()
+                .filter(|&&field| want.get(field) != got.get(field))
+                .map(|&field| {
+                    format!(
+                        "{}: expected {:#04X}, got {:#04X}",
+                        FIELDS[field],
+                        want.get(field),
+                        got.get(field)
+                    )
+                })
+                .collect::<Vec<String>>(),
+            (Some(_), None) => vec![format!("actual trace ends after {} steps", n)],
+            (None, _) => vec![format!("expected trace ends after {} steps", n)],
+        };
+
+        if !differences.is_empty() {
+            let from = n.saturating_sub(context);
+            let window =
+                |steps: &[Step]| steps[from.min(steps.len())..(n + 1).min(steps.len())].to_vec();
+            return Some(Divergence {
+                step: n,
+                differences,
+                fields,
+                expected: window(&expected.steps),
+                actual: window(&actual.steps),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(rom: &[u8], steps: usize) -> Vec<Step> {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(rom).unwrap();
+        (0..steps)
+            .map(|_| {
+                let step = Step::of(&chip8);
+                chip8.cycle().unwrap();
+                step
+            })
+            .collect()
+    }
+
+    // LD V0, 5; LD V1, 3; ADD V0, V1; LD I, 300; LD DT, V0; JP 20A
+    const ROM: [u8; 12] = [
+        0x60, 0x05, 0x61, 0x03, 0x80, 0x14, 0xA3, 0x00, 0xF0, 0x15, 0x12, 0x0A,
+    ];
+
+    fn write(steps: &[Step], format: Format) -> Vec<u8> {
+        let mut writer = TraceWriter::new(Vec::new(), format).unwrap();
+        for step in steps {
+            writer.write(step).unwrap();
+        }
+        writer.out
+    }
+
+    #[test]
+    fn steps_are_recorded_before_running() {
+        let steps = record(&ROM, 6);
+        assert_eq!(steps[0].pc, 0x200);
+        assert_eq!(steps[0].opcode, 0x6005);
+        assert_eq!(steps[0].v[0], 0);
+        assert_eq!(steps[3].v[0], 8);
+        assert_eq!(steps[3].i, 0);
+        // Timers tick at the end of the cycle that set them
+        assert_eq!(steps[5].dt, 7);
+        assert_eq!(steps[5].opcode, 0x120A);
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        let steps = record(&ROM, 20);
+        for format in [Format::Csv, Format::Binary, Format::Log] {
+            let trace = Trace::parse(&write(&steps, format)).unwrap();
+            assert_eq!(trace.steps, steps, "{:?}", format);
+            assert_eq!(trace.fields.len(), FIELDS.len(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn other_emulator_logs() {
+        let log = "\
+            Emulator started\n\
+            pc=0x200 instr=6005 v0=00 v1=00 i=000 delay=00\n\
+            pc=0x202 instr=6103 v0=05 v1=00 i=000 delay=00\n\
+            \n\
+            pc=0x204 instr=8014 v0=05 v1=03 i=000 delay=00\n";
+        let trace = Trace::parse(log.as_bytes()).unwrap();
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(trace.fields, vec![0, 1, 2, 3, 18, 20]);
+        assert_eq!(trace.steps[2].v[1], 3);
+
+        // Only the fields the log has get compared
+        let ours = Trace {
+            steps: record(&ROM, 3),
+            fields: (0..FIELDS.len()).collect(),
+        };
+        assert_eq!(diverge(&trace, &ours, 2), None);
+
+        // A space after the colon
+        let spaced = Trace::parse(b"PC: 0200 V0: 00 I= 000\nPC: 0202 V0: 05 I= 000\n").unwrap();
+        assert_eq!(spaced.fields, vec![0, 2, 18]);
+        assert_eq!((spaced.steps[1].pc, spaced.steps[1].v[0]), (0x202, 5));
+
+        assert!(Trace::parse(b"v0=00\nv0=01\n").is_err(), "No PC");
+        assert!(
+            Trace::parse(b"pc=200 v0=00\npc=202\n").is_err(),
+            "Missing field"
+        );
+        assert!(Trace::parse(b"pc=2G0\n").is_err(), "Not hex");
+    }
+
+    #[test]
+    fn finds_first_divergence() {
+        let expected = Trace::parse(&write(&record(&ROM, 10), Format::Binary)).unwrap();
+        assert_eq!(diverge(&expected, &expected, 3), None);
+
+        // Same program but ADD V0, V1 became OR V0, V1
+        let mut rom = ROM;
+        rom[5] = 0x11;
+        let actual = Trace::parse(&write(&record(&rom, 10), Format::Csv)).unwrap();
+
+        let divergence = diverge(&expected, &actual, 3).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(
+            divergence.differences,
+            vec!["opcode: expected 0x8014, got 0x8011"]
+        );
+        assert_eq!(divergence.expected.len(), 3, "Only 2 steps before it");
+        assert_eq!(divergence.actual[2].opcode, 0x8011);
+
+        let report = divergence.to_string();
+        assert!(report.contains("diverge at step 2"), "{}", report);
+        assert!(report.contains(">        2  PC:0204 OP:8014"), "{}", report);
+    }
+
+    #[test]
+    fn shorter_trace_diverges() {
+        let steps = record(&ROM, 10);
+        let full = Trace {
+            steps: steps.clone(),
+            fields: (0..FIELDS.len()).collect(),
+        };
+        let short = Trace {
+            steps: steps[..7].to_vec(),
+            fields: full.fields.clone(),
+        };
+
+        let divergence = diverge(&full, &short, 2).unwrap();
+        assert_eq!(divergence.step, 7);
+        assert_eq!(
+            divergence.differences,
+            vec!["actual trace ends after 7 steps"]
+        );
+        assert_eq!(divergence.expected.len(), 3);
+        assert_eq!(divergence.actual.len(), 2);
+        assert_eq!(diverge(&short, &full, 2).unwrap().step, 7);
+    }
+}
@@ -0,0 +1,274 @@
+// Single step test vectors, in the spirit of the SingleStepTests CPU suites.
+//
+// A vector is a machine state, the opcode at PC and the state after one `cycle`,
+// which includes the timers ticking down once. States are stored sparsely so the
+// JSON stays readable and easy to load from other implementations:
+//
+//   ram    [address, value] for every byte that differs from a fresh machine, which is
+//          all zeros apart from the font at 0x50
+//   video  [x, y] for every lit pixel
+//   stack  return addresses, oldest first
+//   keys   chip-8 keys that are held down
+//
+// CXKK is left out since its result depends on the random number generator.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::Chip8;
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct State {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub stack: Vec<u16>,
+    pub dt: u8,
+    pub st: u8,
+    pub keys: Vec<u8>,
+    pub ram: Vec<(u16, u8)>,
+    pub video: Vec<(u8, u8)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Vector {
+    pub name: String,
+    pub opcode: u16,
+    pub initial: State,
+    #[serde(rename = "final")]
+    pub after: State,
+}
+
+// Opcode patterns that get vectors, uppercase hex digits are fixed and the rest random
+pub const PATTERNS: [&str; 33] = [
+    "00E0", "00EE", "1nnn", "2nnn", "3xkk", "4xkk", "5xy0", "6xkk", "7xkk", "8xy0", "8xy1", "8xy2",
+    "8xy3", "8xy4", "8xy5", "8xy6", "8xy7", "8xyE", "9xy0", "Annn", "Bnnn", "Dxyn", "Ex9E", "ExA1",
+    "Fx07", "Fx0A", "Fx15", "Fx18", "Fx1E", "Fx29", "Fx33", "Fx55", "Fx65",
+];
+
+impl State {
+    pub fn of(chip8: &Chip8) -> Self {
+        let blank = Chip8::new();
+        let ram = chip8
+            .memory
+            .0
+            .iter()
+            .zip(blank.memory.0.iter())
+            .enumerate()
+            .filter(|(_, (byte, blank))| byte != blank)
+            .map(|(address, (byte, _))| (address as u16, *byte))
+            .collect();
+
+        let video = chip8
+            .video
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| **pixel != 0)
+            .map(|(i, _)| {
+                let width = Chip8::VIDEO_WIDTH as usize;
+                ((i % width) as u8, (i / width) as u8)
+            })
+            .collect();
+
+        State {
+            pc: chip8.program_counter,
+            i: chip8.index,
+            v: chip8.registers,
+            stack: chip8.stack.0[..chip8.stack_pointer as usize].to_vec(),
+            dt: chip8.delay_timer,
+            st: chip8.sound_timer,
+            keys: (0..16)
+                .filter(|key| chip8.keypad.0[*key as usize] == 0xFF)
+                .collect(),
+            ram,
+            video,
+        }
+    }
+
+    pub fn build(&self) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.program_counter = self.pc;
+        chip8.index = self.i;
+        chip8.registers = self.v;
+        chip8.stack.0[..self.stack.len()].copy_from_slice(&self.stack);
+        chip8.stack_pointer = self.stack.len() as u8;
+        chip8.delay_timer = self.dt;
+        chip8.sound_timer = self.st;
+
+        for key in &self.keys {
+            chip8.keypad.0[*key as usize] = 0xFF;
+        }
+        for (address, byte) in &self.ram {
+            chip8.write_memory(*address, &[*byte]);
+        }
+        for (x, y) in &self.video {
+            chip8.video.0[*x as usize + *y as usize * Chip8::VIDEO_WIDTH as usize] = 0xFF;
+        }
+
+        chip8
+    }
+
+    // Field by field differences, empty when they match
+    pub fn diff(&self, expected: &State) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut compare = |name: &str, expected: String, actual: String| {
+            if expected != actual {
+                lines.push(format!("{}: expected {}, got {}", name, expected, actual));
+            }
+        };
+
+        compare(
+            "pc",
+            format!("{:#05X}", expected.pc),
+            format!("{:#05X}", self.pc),
+        );
+        compare(
+            "i",
+            format!("{:#05X}", expected.i),
+            format!("{:#05X}", self.i),
+        );
+        compare(
+            "v",
+            format!("{:02X?}", expected.v),
+            format!("{:02X?}", self.v),
+        );
+        compare(
+            "stack",
+            format!("{:03X?}", expected.stack),
+            format!("{:03X?}", self.stack),
+        );
+        compare("dt", expected.dt.to_string(), self.dt.to_string());
+        compare("st", expected.st.to_string(), self.st.to_string());
+        compare(
+            "keys",
+            format!("{:X?}", expected.keys),
+            format!("{:X?}", self.keys),
+        );
+        compare(
+            "ram",
+            format!("{:X?}", expected.ram),
+            format!("{:X?}", self.ram),
+        );
+        compare(
+            "video",
+            format!("{:?}", expected.video),
+            format!("{:?}", self.video),
+        );
+
+        lines
+    }
+}
+
+impl Vector {
+    // Random machine that runs one instruction matching `pattern` without faulting
+    pub fn generate<R: Rng>(rng: &mut R, pattern: &str, number: usize) -> Vector {
+        let opcode = pattern.chars().fold(0u16, |opcode, c| {
+            let digit = c.to_digit(16).filter(|_| !c.is_ascii_lowercase());
+            (opcode << 4) | digit.unwrap_or_else(|| rng.gen_range(0..16)) as u16
+        });
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+
+        let mut chip8 = Chip8::new();
+        rng.fill(&mut chip8.registers);
+        // I has to leave room for a full sprite or all 16 registers
+        chip8.index = rng.gen_range(0..=0xFEF);
+        chip8.delay_timer = rng.gen();
+        chip8.sound_timer = rng.gen();
+
+        // RET needs something to return to and CALL needs room to push
+        let depth = match opcode {
+            0x00EE => rng.gen_range(1..=15),
+            0x2000..=0x2FFF => rng.gen_range(0..15),
+            _ => rng.gen_range(0..=15),
+        };
+        for entry in chip8.stack.0[..depth].iter_mut() {
+            *entry = rng.gen_range(0x100..0x800) * 2;
+        }
+        chip8.stack_pointer = depth as u8;
+
+        for key in chip8.keypad.0.iter_mut() {
+            *key = if rng.gen_bool(0.25) { 0xFF } else { 0x00 };
+        }
+        // Only CLS and DRW look at the screen, a blank one keeps the other vectors small
+        if opcode == 0x00E0 || opcode & 0xF000 == 0xD000 {
+            for pixel in chip8.video.0.iter_mut() {
+                *pixel = if rng.gen_bool(0.25) { 0xFF } else { 0x00 };
+            }
+        }
+
+        // Half the time make the comparisons come out equal, otherwise they almost never do
+        let equal = rng.gen_bool(0.5);
+        match opcode & 0xF00F {
+            0x3000..=0x4FFF if equal => chip8.registers[x] = opcode as u8,
+            0x5000 | 0x9000 if equal => chip8.registers[x] = chip8.registers[y],
+            // Skips on key index into the keypad with Vx
+            0xE00E | 0xE001 => chip8.registers[x] &= 0x0F,
+            _ => {}
+        }
+
+        let mut data = [0; 16];
+        rng.fill(&mut data);
+        chip8.write_memory(chip8.index, &data);
+
+        chip8.program_counter = rng.gen_range(0x100..0x800) * 2;
+        chip8.write_memory(chip8.program_counter, &opcode.to_be_bytes());
+
+        let initial = State::of(&chip8);
+        chip8.cycle().expect("Generated states shouldn't fault");
+
+        Vector {
+            name: format!("{:04x} {}", opcode, number),
+            opcode,
+            initial,
+            after: State::of(&chip8),
+        }
+    }
+
+    // Runs the vector on a fresh interpreter, returns what came out different
+    pub fn replay(&self) -> Vec<String> {
+        let mut chip8 = self.initial.build();
+        if let Err(e) = chip8.cycle() {
+            return vec![e.to_string()];
+        }
+        State::of(&chip8).diff(&self.after)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn generated_vectors_replay() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for pattern in PATTERNS {
+            for number in 0..20 {
+                let vector = Vector::generate(&mut rng, pattern, number);
+                assert_eq!(vector.replay(), Vec::<String>::new(), "{}", vector.name);
+            }
+        }
+    }
+
+    #[test]
+    fn pattern_fixes_digits() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for number in 0..20 {
+            let vector = Vector::generate(&mut rng, "8xyE", number);
+            assert_eq!(vector.opcode & 0xF00F, 0x800E);
+            assert_eq!(vector.name, format!("{:04x} {}", vector.opcode, number));
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let vector = Vector::generate(&mut rng, "Dxyn", 0);
+
+        let json = serde_json::to_string(&vector).unwrap();
+        assert!(json.contains("\"final\":"));
+        assert_eq!(serde_json::from_str::<Vector>(&json).unwrap(), vector);
+    }
+}
@@ -1,2 +1,28 @@
-pub mod app;
+// Without the std feature this is just the interpreter and the frontend traits, no_std
+// and without allocating, so it can run on a microcontroller
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "std")]
+pub mod aot;
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(any(feature = "cli", feature = "script"))]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod env;
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod golden;
+#[cfg(feature = "cli")]
+pub mod headless;
 pub mod interpreter;
+#[cfg(all(feature = "std", feature = "log"))]
+pub mod logger;
+#[cfg(feature = "std")]
+pub mod ocr;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod upscale;
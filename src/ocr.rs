@@ -0,0 +1,269 @@
+// Reads text back off the screen so tests can assert on what a ROM printed instead of
+// keeping a golden frame around.
+//
+// A glyph only matches where its whole box lines up pixel for pixel and the pixels
+// right around the box are dark, so parts of bigger sprites don't read as letters.
+// Matches on the same row become a line, a gap wider than a glyph becomes a space.
+
+use crate::interpreter::Chip8;
+
+const WIDTH: usize = Chip8::VIDEO_WIDTH as usize;
+const HEIGHT: usize = Chip8::VIDEO_HEIGHT as usize;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Glyph {
+    pub ch: char,
+    pub width: usize,
+    // One byte per row, leftmost pixel in the high bit like chip-8 sprites
+    pub rows: Vec<u8>,
+}
+
+impl Glyph {
+    fn lit(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & (0x80 >> x) != 0
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct GlyphSet {
+    glyphs: Vec<Glyph>,
+}
+
+impl GlyphSet {
+    pub fn empty() -> Self {
+        GlyphSet::default()
+    }
+
+    // The built in hex digits, 4x5 each
+    pub fn font() -> Self {
+        let mut set = GlyphSet::empty();
+        for (digit, rows) in Chip8::FONT.chunks(5).enumerate() {
+            let ch = std::char::from_digit(digit as u32, 16)
+                .unwrap()
+                .to_ascii_uppercase();
+            set = set.with(ch, 4, rows);
+        }
+        set
+    }
+
+    pub fn with(mut self, ch: char, width: usize, rows: &[u8]) -> Self {
+        assert!((1..=8).contains(&width), "Glyphs are 1 to 8 pixels wide");
+        assert!(!rows.is_empty(), "Glyphs need at least one row");
+        self.glyphs.push(Glyph {
+            ch,
+            width,
+            rows: rows.to_vec(),
+        });
+        self
+    }
+
+    // Glyph drawn as text art, '#' lit and anything else dark:
+    //   with_art('K', "#.#\n##.\n#.#")
+    pub fn with_art(self, ch: char, art: &str) -> Self {
+        let lines = art
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<&str>>();
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let rows = lines
+            .iter()
+            .map(|line| {
+                line.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c == '#')
+                    .fold(0u8, |row, (x, _)| row | 0x80 >> x)
+            })
+            .collect::<Vec<u8>>();
+        self.with(ch, width, &rows)
+    }
+
+    // Both sets together, glyphs from `self` win when two look the same
+    pub fn and(mut self, other: GlyphSet) -> Self {
+        self.glyphs.extend(other.glyphs);
+        self
+    }
+
+    pub fn glyphs(&self) -> &[Glyph] {
+        &self.glyphs
+    }
+}
+
+fn lit(video: &[u8], x: isize, y: isize) -> bool {
+    if x < 0 || y < 0 || x >= WIDTH as isize || y >= HEIGHT as isize {
+        return false;
+    }
+    video[x as usize + y as usize * WIDTH] != 0
+}
+
+fn matches(video: &[u8], glyph: &Glyph, x: usize, y: usize) -> bool {
+    let (x, y) = (x as isize, y as isize);
+    let (width, height) = (glyph.width as isize, glyph.rows.len() as isize);
+
+    // The box and a one pixel dark border around it
+    for gy in -1..=height {
+        for gx in -1..=width {
+            let inside = (0..width).contains(&gx) && (0..height).contains(&gy);
+            let expected = inside && glyph.lit(gx as usize, gy as usize);
+            if lit(video, x + gx, y + gy) != expected {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// Glyph and where its top left corner is
+pub fn find(video: &[u8], glyphs: &GlyphSet) -> Vec<(usize, usize, char)> {
+    assert_eq!(video.len(), WIDTH * HEIGHT, "Video buffer size mismatch");
+
+    let mut found = Vec::new();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let glyph = glyphs
+                .glyphs
+                .iter()
+                .find(|glyph| matches(video, glyph, x, y));
+            if let Some(glyph) = glyph {
+                found.push((x, y, glyph.ch));
+            }
+        }
+    }
+    found
+}
+
+pub fn read(video: &[u8], glyphs: &GlyphSet) -> String {
+    let widths = glyphs
+        .glyphs
+        .iter()
+        .map(|glyph| (glyph.ch, glyph.width))
+        .collect::<Vec<(char, usize)>>();
+    let width_of = |ch: char| widths.iter().find(|(c, _)| *c == ch).unwrap().1;
+
+    // `find` goes row by row so the matches are already sorted by y and then x
+    let mut lines: Vec<(usize, String, usize)> = Vec::new();
+    for (x, y, ch) in find(video, glyphs) {
+        match lines.last_mut() {
+            Some((line_y, text, end)) if *line_y == y => {
+                // Glyphs are usually a pixel apart, anything more than a glyph is a space
+                if x > *end + width_of(ch) {
+                    text.push(' ');
+                }
+                text.push(ch);
+                *end = x + width_of(ch);
+            }
+            _ => lines.push((y, ch.to_string(), x + width_of(ch))),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(_, text, _)| text)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Text written with the built in font
+pub fn screen_text(video: &[u8]) -> String {
+    read(video, &GlyphSet::font())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Draws each digit in `text` with FX29 and DXY5, spaces skip a glyph
+    fn print(text: &str, x: u8, y: u8) -> Chip8 {
+        let mut program = vec![0x6000 | x as u16, 0x6100 | y as u16];
+        for c in text.chars() {
+            if let Some(digit) = c.to_digit(16) {
+                program.extend([0x6200 | digit as u16, 0xF229, 0xD015]);
+            }
+            program.push(0x7005);
+        }
+
+        let bytes = program
+            .iter()
+            .flat_map(|op| op.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(&bytes).unwrap();
+        for _ in 0..program.len() {
+            chip8.cycle().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn reads_every_digit() {
+        let video = print("0123456789AB", 0, 0).get_video_buffer();
+        assert_eq!(screen_text(&video), "0123456789AB");
+
+        let video = print("CDEF", 0, 10).get_video_buffer();
+        assert_eq!(screen_text(&video), "CDEF");
+    }
+
+    #[test]
+    fn lines_and_spaces() {
+        let mut video = print("C8 0", 2, 3).get_video_buffer();
+        let below = print("1", 20, 20).get_video_buffer();
+        for (pixel, other) in video.iter_mut().zip(below.iter()) {
+            *pixel |= other;
+        }
+
+        assert_eq!(screen_text(&video), "C8 0\n1");
+        assert_eq!(
+            find(&video, &GlyphSet::font())[0],
+            (2, 3, 'C'),
+            "Position is the top left of the box"
+        );
+    }
+
+    #[test]
+    fn ignores_touching_sprites() {
+        let mut video = print("7", 0, 0).get_video_buffer();
+        assert_eq!(screen_text(&video), "7");
+
+        // A pixel right next to the glyph spoils it
+        video[4] = 0xFF;
+        assert_eq!(screen_text(&video), "");
+
+        // So does a solid block the glyph is part of
+        assert_eq!(screen_text(&[0xFF; WIDTH * HEIGHT]), "");
+    }
+
+    #[test]
+    fn custom_glyphs() {
+        let glyphs = GlyphSet::empty()
+            .with_art(
+                'O',
+                "
+                ###
+                #.#
+                ###
+                ",
+            )
+            .with_art('K', "#.#\n##.\n#.#")
+            .and(GlyphSet::font());
+
+        let mut video = [0u8; WIDTH * HEIGHT];
+        for (x, y) in [
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (0, 1),
+            (2, 1),
+            (0, 2),
+            (1, 2),
+            (2, 2),
+        ] {
+            video[10 + x + (5 + y) * WIDTH] = 0xFF;
+        }
+        for (x, y) in [(0, 0), (2, 0), (0, 1), (1, 1), (0, 2), (2, 2)] {
+            video[14 + x + (5 + y) * WIDTH] = 0xFF;
+        }
+
+        assert_eq!(read(&video, &glyphs), "OK");
+        assert_eq!(screen_text(&video), "");
+    }
+}
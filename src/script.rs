@@ -0,0 +1,499 @@
+// Rhai scripts for automating runs and hooking into ROMs, loaded with `--script` on
+// the headless runner or from the side panel in the GUI. The top level of a script
+// runs once when it's started, and can register functions to run after every frame
+// or before every instruction:
+//
+//   // Press 5 every 30 frames, print the score and stop once VF is set
+//   every_frame(|frame| {
+//       if frame % 30 == 0 { press(5) } else { release(5) }
+//       print(peek(0x2F0));
+//       if v(15) == 1 { stop() }
+//   });
+//
+// Scripts get
+//
+//   v(x), set_v(x, value)                V0-VF
+//   i(), set_i(value)                    the index register
+//   pc(), set_pc(address)                the program counter
+//   peek(address), poke(address, value)  memory a byte at a time
+//   press(key), release(key)             held along with whatever the frontend holds
+//   frames(n)                            runs n frames there and then, hooks and all
+//   frame()                              how many frames have run since the start
+//   every_frame(f)                       calls f(frame) after every frame
+//   every_instruction(f)                 calls f(pc) before every instruction
+//   screenshot(path)                     saves a PNG of the screen
+//   stop()                               ends a headless run, pauses the GUI
+//
+// Per instruction hooks take frames off the fast path, so they're a lot slower than
+// the rest.
+
+use std::{cell::RefCell, fmt, io, path::Path, rc::Rc};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, ParseError, AST};
+
+use crate::capture;
+use crate::frontend::{Audio, Clock, Display, Input, DEFAULT_CYCLES_PER_FRAME};
+use crate::interpreter::{self, Chip8};
+use crate::upscale::{Palette, Pipeline};
+
+const WIDTH: usize = Chip8::VIDEO_WIDTH as usize;
+const HEIGHT: usize = Chip8::VIDEO_HEIGHT as usize;
+
+type Outcome<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse(ParseError),
+    Run(Box<EvalAltResult>),
+    // The ROM faulted, in a frame the script ran or one the frontend did
+    Fault(interpreter::Error),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(e) => write!(f, "{}", e),
+            ScriptError::Parse(e) => write!(f, "{}", e),
+            ScriptError::Run(e) => write!(f, "{}", e),
+            ScriptError::Fault(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(e: io::Error) -> Self {
+        ScriptError::Io(e)
+    }
+}
+
+impl From<ParseError> for ScriptError {
+    fn from(e: ParseError) -> Self {
+        ScriptError::Parse(e)
+    }
+}
+
+// What the functions scripts call work on. The machine is only in here while the
+// script is running, `Script::attached` swaps the caller's in and back out again
+struct Host {
+    chip8: Chip8,
+    keys: [bool; 16],
+    frame: i64,
+    cycles_per_frame: usize,
+    every_frame: Vec<FnPtr>,
+    every_instruction: Vec<FnPtr>,
+    pipeline: Pipeline,
+    palette: Palette,
+    stop: bool,
+    // So a fault in a frame the script ran comes back out as a fault rather than
+    // a script error
+    fault: Option<interpreter::Error>,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    host: Rc<RefCell<Host>>,
+}
+
+impl Script {
+    pub fn new(source: &str) -> Result<Script, ScriptError> {
+        let host = Rc::new(RefCell::new(Host {
+            chip8: Chip8::new(),
+            keys: [false; 16],
+            frame: 0,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            every_frame: Vec::new(),
+            every_instruction: Vec::new(),
+            pipeline: "nearest8".parse().unwrap(),
+            palette: Palette::default(),
+            stop: false,
+            fault: None,
+        }));
+        let mut engine = Engine::new();
+        register(&mut engine, &host);
+        let ast = engine.compile(source)?;
+        Ok(Script { engine, ast, host })
+    }
+
+    pub fn load(path: &Path) -> Result<Script, ScriptError> {
+        Script::new(&std::fs::read_to_string(path)?)
+    }
+
+    // Instructions per frame for the frames `frames(n)` runs
+    pub fn cycles_per_frame(self, cycles: usize) -> Self {
+        self.host.borrow_mut().cycles_per_frame = cycles;
+        self
+    }
+
+    // How `screenshot` draws the screen
+    pub fn capture(self, pipeline: Pipeline, palette: Palette) -> Self {
+        {
+            let mut host = self.host.borrow_mut();
+            host.pipeline = pipeline;
+            host.palette = palette;
+        }
+        self
+    }
+
+    // Where `print` goes, stdout otherwise
+    pub fn on_print(&mut self, print: impl Fn(&str) + 'static) {
+        self.engine.on_print(print);
+    }
+
+    // Runs the top level of the script, which is where hooks get registered
+    pub fn start(&mut self, chip8: &mut Chip8) -> Result<(), ScriptError> {
+        self.attached(chip8, |script| script.engine.run_ast(&script.ast))
+    }
+
+    // `frontend::frame` with the script's keys held too and its hooks called. Frames
+    // the clock gives no instructions, like the GUI's while it's paused, don't count
+    pub fn frame(
+        &mut self,
+        chip8: &mut Chip8,
+        input: &mut impl Input,
+        clock: &mut impl Clock,
+        audio: &mut impl Audio,
+        display: &mut impl Display,
+    ) -> Result<(), ScriptError> {
+        let pressed = input.pressed();
+        let cycles = clock.cycles();
+        let result = self.attached(chip8, |script| {
+            let (engine, ast) = (&script.engine, &script.ast);
+            run_frame(&script.host, pressed, cycles, &mut |hook, argument| {
+                hook.call::<Dynamic>(engine, ast, (argument,)).map(drop)
+            })
+        });
+
+        audio.buzz(chip8.sound_playing());
+        display.present(chip8.video(), WIDTH, HEIGHT);
+        result
+    }
+
+    // Whether the script has called `stop` since the last time this was asked
+    pub fn take_stop_request(&mut self) -> bool {
+        std::mem::take(&mut self.host.borrow_mut().stop)
+    }
+
+    fn attached(
+        &mut self,
+        chip8: &mut Chip8,
+        run: impl FnOnce(&Self) -> Outcome<()>,
+    ) -> Result<(), ScriptError> {
+        std::mem::swap(chip8, &mut self.host.borrow_mut().chip8);
+        let result = run(self);
+        let mut host = self.host.borrow_mut();
+        std::mem::swap(chip8, &mut host.chip8);
+        result.map_err(|e| match host.fault.take() {
+            Some(fault) => ScriptError::Fault(fault),
+            None => ScriptError::Run(e),
+        })
+    }
+}
+
+type Call<'a> = dyn FnMut(&FnPtr, i64) -> Outcome<()> + 'a;
+
+// A frame with `pressed` and the script's keys held, calling hooks through `call`.
+// The host is never borrowed while a hook runs, since hooks borrow it themselves
+fn run_frame(
+    host: &RefCell<Host>,
+    pressed: [bool; 16],
+    cycles: usize,
+    call: &mut Call,
+) -> Outcome<()> {
+    if cycles == 0 {
+        return Ok(());
+    }
+    let every_instruction = {
+        let mut host = host.borrow_mut();
+        let held = core::array::from_fn(|key| pressed[key] || host.keys[key]);
+        for event in interpreter::key_events(host.chip8.pressed_keys(), held) {
+            host.chip8.handle_key(event);
+        }
+        host.every_instruction.clone()
+    };
+
+    if every_instruction.is_empty() {
+        let result = host.borrow_mut().chip8.run(cycles);
+        fault(host, result)?;
+    } else {
+        for _ in 0..cycles {
+            let pc = host.borrow().chip8.program_counter() as i64;
+            for hook in &every_instruction {
+                call(hook, pc)?;
+            }
+            let result = host.borrow_mut().chip8.cycle();
+            fault(host, result)?;
+        }
+    }
+
+    let (frame, every_frame) = {
+        let mut host = host.borrow_mut();
+        host.frame += 1;
+        (host.frame, host.every_frame.clone())
+    };
+    for hook in &every_frame {
+        call(hook, frame)?;
+    }
+    Ok(())
+}
+
+fn fault(host: &RefCell<Host>, result: Result<(), interpreter::Error>) -> Outcome<()> {
+    result.map_err(|e| {
+        host.borrow_mut().fault = Some(e);
+        e.to_string().into()
+    })
+}
+
+// Script numbers are i64, anything out of range is an error rather than wrapping
+fn within(value: i64, end: usize, what: &str) -> Outcome<usize> {
+    match usize::try_from(value) {
+        Ok(value) if value < end => Ok(value),
+        _ => Err(format!("{} {} is out of range", what, value).into()),
+    }
+}
+
+fn register(engine: &mut Engine, host: &Rc<RefCell<Host>>) {
+    let h = Rc::clone(host);
+    engine.register_fn("v", move |x: i64| -> Outcome<i64> {
+        Ok(h.borrow().chip8.registers().0[within(x, 16, "Register")?] as i64)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("set_v", move |x: i64, value: i64| -> Outcome<()> {
+        let (x, value) = (within(x, 16, "Register")?, within(value, 0x100, "Byte")?);
+        let mut host = h.borrow_mut();
+        let (mut v, i) = host.chip8.registers();
+        v[x] = value as u8;
+        host.chip8.set_registers(v, i);
+        Ok(())
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("i", move || h.borrow().chip8.registers().1 as i64);
+    let h = Rc::clone(host);
+    engine.register_fn("set_i", move |value: i64| -> Outcome<()> {
+        let value = within(value, 0x10000, "Address")?;
+        let mut host = h.borrow_mut();
+        let (v, _) = host.chip8.registers();
+        host.chip8.set_registers(v, value as u16);
+        Ok(())
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("pc", move || h.borrow().chip8.program_counter() as i64);
+    let h = Rc::clone(host);
+    engine.register_fn("set_pc", move |address: i64| -> Outcome<()> {
+        let address = within(address, 0x10000, "Address")?;
+        h.borrow_mut().chip8.set_program_counter(address as u16);
+        Ok(())
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("peek", move |address: i64| -> Outcome<i64> {
+        let host = h.borrow();
+        let memory = host.chip8.memory();
+        Ok(memory[within(address, memory.len(), "Address")?] as i64)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("poke", move |address: i64, value: i64| -> Outcome<()> {
+        let mut host = h.borrow_mut();
+        let address = within(address, host.chip8.memory().len(), "Address")?;
+        let value = within(value, 0x100, "Byte")?;
+        host.chip8.write_memory(address as u16, &[value as u8]);
+        Ok(())
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("press", move |key: i64| -> Outcome<()> {
+        h.borrow_mut().keys[within(key, 16, "Key")?] = true;
+        Ok(())
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("release", move |key: i64| -> Outcome<()> {
+        h.borrow_mut().keys[within(key, 16, "Key")?] = false;
+        Ok(())
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn(
+        "frames",
+        move |context: NativeCallContext, frames: i64| -> Outcome<()> {
+            let cycles = h.borrow().cycles_per_frame;
+            for _ in 0..frames {
+                run_frame(&h, [false; 16], cycles, &mut |hook, argument| {
+                    hook.call_within_context::<Dynamic>(&context, (argument,))
+                        .map(drop)
+                })?;
+            }
+            Ok(())
+        },
+    );
+    let h = Rc::clone(host);
+    engine.register_fn("frame", move || h.borrow().frame);
+    let h = Rc::clone(host);
+    engine.register_fn("every_frame", move |hook: FnPtr| {
+        h.borrow_mut().every_frame.push(hook)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("every_instruction", move |hook: FnPtr| {
+        h.borrow_mut().every_instruction.push(hook)
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("screenshot", move |path: &str| -> Outcome<()> {
+        let host = h.borrow();
+        let image = host
+            .pipeline
+            .render(host.chip8.video(), WIDTH, HEIGHT, host.palette);
+        capture::save_png(Path::new(path), &image)
+            .map_err(|e| format!("Could not save {}: {}", path, e).into())
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("stop", move || h.borrow_mut().stop = true);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::{FixedClock, NullAudio, NullDisplay, NullInput};
+    use crate::interpreter::testing::{program, COUNTER};
+
+    fn frame(script: &mut Script, chip8: &mut Chip8) -> Result<(), ScriptError> {
+        script.frame(
+            chip8,
+            &mut NullInput::default(),
+            &mut FixedClock(10),
+            &mut NullAudio::default(),
+            &mut NullDisplay::default(),
+        )
+    }
+
+    #[test]
+    fn hooks_press_keys_and_read_memory() {
+        let mut chip8 = program(COUNTER);
+        let mut script = Script::new(
+            "
+            every_frame(|frame| {
+                if frame % 4 == 0 { press(5) } else { release(5) }
+                if v(0) >= 3 { stop() }
+            });
+            ",
+        )
+        .unwrap();
+        script.start(&mut chip8).unwrap();
+
+        let mut frames = 0;
+        while !script.take_stop_request() {
+            frame(&mut script, &mut chip8).unwrap();
+            frames += 1;
+        }
+        // Pressed after frame 4, counting through frame 5 with the last ADD already
+        // past the key check when it's let go
+        assert_eq!(frames, 6);
+        assert_eq!(chip8.registers().0[0], 3);
+        assert!(!script.take_stop_request());
+    }
+
+    #[test]
+    fn scripts_drive_the_machine() {
+        let mut chip8 = program(COUNTER);
+        let mut script = Script::new(
+            "
+            press(5);
+            frames(2);
+            release(5);
+            set_v(0, 200);
+            frames(1);
+            poke(0x400, v(0));
+            set_i(0x123);
+            if frame() != 3 || pc() < 0x200 { throw \"wrong\" }
+            ",
+        )
+        .unwrap()
+        .cycles_per_frame(4);
+        script.start(&mut chip8).unwrap();
+        assert_eq!(chip8.read_memory(0x400), 200);
+        assert_eq!(chip8.registers().1, 0x123);
+
+        // Paused frames don't count
+        script
+            .frame(
+                &mut chip8,
+                &mut NullInput::default(),
+                &mut FixedClock(0),
+                &mut NullAudio::default(),
+                &mut NullDisplay::default(),
+            )
+            .unwrap();
+        assert_eq!(script.host.borrow().frame, 3);
+    }
+
+    #[test]
+    fn every_instruction() {
+        let mut chip8 = program(COUNTER);
+        let mut script = Script::new(
+            "
+            every_instruction(|pc| {
+                if pc == 0x206 { set_pc(0x208) }
+            });
+            press(5);
+            ",
+        )
+        .unwrap();
+        script.start(&mut chip8).unwrap();
+        frame(&mut script, &mut chip8).unwrap();
+        // The ADD is always jumped over
+        assert_eq!(chip8.registers().0[0], 0);
+        assert_eq!(chip8.registers().1, 0x300);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(Script::new("let = 1"), Err(ScriptError::Parse(_))));
+        assert!(matches!(
+            Script::load(Path::new("no/such/script.rhai")),
+            Err(ScriptError::Io(_))
+        ));
+
+        let mut chip8 = program(COUNTER);
+        for source in ["v(16)", "set_v(0, 256)", "poke(4096, 0)", "press(-1)"] {
+            let result = Script::new(source).unwrap().start(&mut chip8);
+            assert!(matches!(result, Err(ScriptError::Run(_))), "{}", source);
+        }
+
+        // RET with nothing to return to, in a frame run by the script or the frontend
+        let mut chip8 = program(&[0x00EE]);
+        let mut script = Script::new("every_frame(|frame| { throw \"unreachable\" })").unwrap();
+        script.start(&mut chip8).unwrap();
+        let fault = Some(interpreter::Error::StackUnderflow { address: 0x200 });
+        assert_eq!(
+            frame(&mut script, &mut chip8).err().and_then(|e| match e {
+                ScriptError::Fault(e) => Some(e),
+                _ => None,
+            }),
+            fault
+        );
+        let mut chip8 = program(&[0x00EE]);
+        match Script::new("frames(1)").unwrap().start(&mut chip8) {
+            Err(ScriptError::Fault(e)) => assert_eq!(Some(e), fault),
+            _ => panic!("Expected a fault"),
+        }
+    }
+
+    #[test]
+    fn screenshots() {
+        let path =
+            std::env::temp_dir().join(format!("paulson-8-{}-script.png", std::process::id()));
+        let mut chip8 = program(&[0xF029, 0xD005, 0x1204]);
+        let mut script = Script::new(&format!("frames(1); screenshot({:?});", path))
+            .unwrap()
+            .capture(Pipeline::new(), Palette::default());
+        script.start(&mut chip8).unwrap();
+
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let size = (reader.info().width, reader.info().height);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(size, (64, 32));
+    }
+}
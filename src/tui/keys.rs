@@ -0,0 +1,112 @@
+// Terminals only say when a key goes down, and then again every time it repeats.
+// A key counts as held until `hold` has passed without hearing from it, which wants
+// to be a bit longer than the terminal's repeat delay so holding a key down doesn't
+// flicker between repeats.
+
+use std::time::{Duration, Instant};
+
+use crate::frontend::Input;
+
+// Same spots as the GUI's default layout
+const LAYOUT: [(char, u8); 16] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+// Chip-8 key for a character typed in the terminal
+pub fn key_for(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    LAYOUT
+        .iter()
+        .find(|(bound, _)| *bound == c)
+        .map(|(_, key)| *key)
+}
+
+pub struct HeldKeys {
+    hold: Duration,
+    // When each held key lets go unless it comes through again
+    until: [Option<Instant>; 16],
+}
+
+impl HeldKeys {
+    pub fn new(hold: Duration) -> Self {
+        HeldKeys {
+            hold,
+            until: [None; 16],
+        }
+    }
+
+    // A press or a repeat
+    pub fn press(&mut self, key: u8, now: Instant) {
+        self.until[key as usize] = Some(now + self.hold);
+    }
+
+    // For the terminals that do report key releases
+    pub fn release(&mut self, key: u8) {
+        self.until[key as usize] = None;
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        for until in self.until.iter_mut() {
+            if until.is_some_and(|until| until <= now) {
+                *until = None;
+            }
+        }
+    }
+}
+
+impl Input for HeldKeys {
+    fn pressed(&mut self) -> [bool; 16] {
+        self.until.map(|until| until.is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        assert_eq!(key_for('1'), Some(0x1));
+        assert_eq!(key_for('V'), Some(0xF));
+        assert_eq!(key_for('x'), Some(0x0));
+        assert_eq!(key_for('p'), None);
+    }
+
+    #[test]
+    fn keys_let_go_after_the_hold() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut keys = HeldKeys::new(ms(200));
+
+        keys.press(0x5, start);
+        keys.expire(start + ms(150));
+        assert!(keys.pressed()[0x5]);
+
+        // Repeats keep it down
+        keys.press(0x5, start + ms(180));
+        keys.expire(start + ms(300));
+        assert!(keys.pressed()[0x5]);
+
+        keys.expire(start + ms(380));
+        assert_eq!(keys.pressed(), [false; 16]);
+
+        keys.press(0xA, start);
+        keys.release(0xA);
+        assert_eq!(keys.pressed(), [false; 16]);
+    }
+}
@@ -0,0 +1,335 @@
+// Plays ROMs in a terminal, for when there's no display to open a window on. The
+// screen is drawn with half blocks or braille, registers and the last few log lines
+// go underneath it.
+//
+// `Tui` is a `frontend::Display` writing to anything that implements `Write`, so
+// what ends up on the terminal can be captured into a buffer in tests.
+
+pub mod keys;
+pub mod render;
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor, event,
+    event::{Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    terminal::{self, ClearType},
+};
+use log::{error, LevelFilter};
+
+use crate::frontend::{frame, Audio, Display, FixedClock, DEFAULT_CYCLES_PER_FRAME};
+use crate::interpreter::trace::Step;
+use crate::interpreter::Chip8;
+use crate::logger;
+use keys::HeldKeys;
+use render::Style;
+
+pub const USAGE: &str = "Usage: paulson-8-tui <rom> [options]
+
+Options:
+    --cycles <n>  Instructions per frame (default 20)
+    --hold <ms>   How long a key stays down after the terminal last sent it (default 200)
+    --braille     Draw with braille instead of half blocks, a quarter of the size
+
+Keys are laid out like the GUI's, 1234 QWER ASDF ZXCV. Escape quits.";
+
+// Log lines kept under the screen
+const LOG_LINES: usize = 5;
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub cycles_per_frame: usize,
+    pub hold: Duration,
+    pub style: Style,
+}
+
+impl Options {
+    pub fn new(rom: PathBuf) -> Self {
+        Options {
+            rom,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            hold: Duration::from_millis(200),
+            style: Style::HalfBlocks,
+        }
+    }
+
+    // Takes the arguments without the program name
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut rom = None;
+        let mut options = Options::new(PathBuf::new());
+
+        while let Some(arg) = args.next() {
+            let mut number = |name: &str| {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", name))?;
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("{} expects a number, got {}", name, value))
+            };
+
+            match arg.as_str() {
+                "--cycles" => options.cycles_per_frame = number(&arg)?,
+                "--hold" => options.hold = Duration::from_millis(number(&arg)? as u64),
+                "--braille" => options.style = Style::Braille,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        options.rom = rom.ok_or("No ROM given")?;
+        Ok(options)
+    }
+}
+
+pub struct Tui<W: Write> {
+    out: W,
+    style: Style,
+    // Last frame, drawn by `draw` along with everything else
+    screen: Vec<String>,
+}
+
+impl<W: Write> Tui<W> {
+    pub fn new(out: W, style: Style) -> Self {
+        Tui {
+            out,
+            style,
+            screen: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Redraws from the top left, every line cleared to the end so nothing is left over
+    // from a longer one before
+    pub fn draw(&mut self, chip8: &Chip8, log: &VecDeque<String>) -> io::Result<()> {
+        queue!(self.out, cursor::MoveTo(0, 0))?;
+
+        let lines = self
+            .screen
+            .iter()
+            .chain(std::iter::once(&String::new()))
+            .chain(render::status(&Step::of(chip8)).iter())
+            .chain(std::iter::once(&String::new()))
+            .chain(log.iter())
+            .cloned()
+            .collect::<Vec<String>>();
+        for line in lines {
+            write!(self.out, "{}", line)?;
+            queue!(self.out, terminal::Clear(ClearType::UntilNewLine))?;
+            // Raw mode doesn't go back to the start of the line by itself
+            write!(self.out, "\r\n")?;
+        }
+        queue!(self.out, terminal::Clear(ClearType::FromCursorDown))?;
+        self.out.flush()
+    }
+
+    pub fn bell(&mut self) -> io::Result<()> {
+        write!(self.out, "\x07")
+    }
+}
+
+impl<W: Write> Display for Tui<W> {
+    fn present(&mut self, video: &[u8], width: usize, height: usize) {
+        self.screen = self.style.render(video, width, height);
+    }
+}
+
+// The terminal bell, rung when the buzzer comes on since that's all a terminal can do
+#[derive(Default)]
+pub struct Bell {
+    on: bool,
+    ring: bool,
+}
+
+impl Bell {
+    pub fn take(&mut self) -> bool {
+        std::mem::take(&mut self.ring)
+    }
+}
+
+impl Audio for Bell {
+    fn buzz(&mut self, on: bool) {
+        self.ring |= on && !self.on;
+        self.on = on;
+    }
+}
+
+// Puts the terminal back however `run` finishes
+struct Raw;
+
+impl Raw {
+    fn enter() -> io::Result<Raw> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Raw)
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn run(options: &Options) -> io::Result<()> {
+    let mut chip8 = Chip8::new();
+    chip8.load(&options.rom)?;
+    let logs = logger::init(LevelFilter::Info).map_err(io::Error::other)?;
+
+    let _raw = Raw::enter()?;
+    let mut tui = Tui::new(io::stdout(), options.style);
+    let mut keys = HeldKeys::new(options.hold);
+    let mut clock = FixedClock(options.cycles_per_frame);
+    let mut bell = Bell::default();
+    let mut log = VecDeque::new();
+
+    let mut next = Instant::now();
+    loop {
+        // Keys until it's time for the next frame, or whatever's waiting when behind
+        next += FRAME;
+        loop {
+            if !event::poll(next.saturating_duration_since(Instant::now()))? {
+                break;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.code == KeyCode::Esc || ctrl_c {
+                return Ok(());
+            }
+            let chip8_key = match key.code {
+                KeyCode::Char(c) => keys::key_for(c),
+                _ => None,
+            };
+            if let Some(chip8_key) = chip8_key {
+                match key.kind {
+                    KeyEventKind::Release => keys.release(chip8_key),
+                    _ => keys.press(chip8_key, Instant::now()),
+                }
+            }
+        }
+        // Running behind, don't try to catch up on the frames that were missed
+        next = next.max(Instant::now() - FRAME);
+        keys.expire(Instant::now());
+
+        // Stops on a fault, leaving the state on screen to look at
+        if let Err(e) = frame(&mut chip8, &mut keys, &mut clock, &mut bell, &mut tui) {
+            error!("{}, stopped", e);
+            clock = FixedClock(0);
+        }
+
+        for entry in logs.try_iter() {
+            log.push_back(format!("{:<5} {}", entry.level, entry.text));
+        }
+        while log.len() > LOG_LINES {
+            log.pop_front();
+        }
+        if bell.take() {
+            tui.bell()?;
+        }
+        tui.draw(&chip8, &log)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::NullInput;
+    use crate::interpreter::testing::program;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(parse(&["rom.ch8"]), Ok(Options::new("rom.ch8".into())));
+
+        let options = parse(&["--braille", "rom.ch8", "--hold", "80", "--cycles", "9"]).unwrap();
+        assert_eq!(options.style, Style::Braille);
+        assert_eq!(options.hold, Duration::from_millis(80));
+        assert_eq!(options.cycles_per_frame, 9);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["rom.ch8", "--hold", "long"]).is_err());
+        assert!(parse(&["rom.ch8", "--color"]).is_err());
+    }
+
+    // Escape sequences taken out so only the text is left
+    fn text(output: &[u8]) -> String {
+        let output = String::from_utf8(output.to_vec()).unwrap();
+        let mut text = String::new();
+        let mut chars = output.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // CSI, parameters then a final letter
+                chars.next();
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else if c != '\r' {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn draws_screen_registers_and_log() {
+        // Draws the 0 digit in the top left
+        let mut chip8 = program(&[0x6000, 0xF029, 0xD005, 0x6307, 0x1208]);
+
+        let mut tui = Tui::new(Vec::new(), Style::HalfBlocks);
+        let mut bell = Bell::default();
+        frame(
+            &mut chip8,
+            &mut NullInput::default(),
+            &mut FixedClock(5),
+            &mut bell,
+            &mut tui,
+        )
+        .unwrap();
+        let log = VecDeque::from(["INFO  hello".to_string()]);
+        tui.draw(&chip8, &log).unwrap();
+
+        let text = text(&tui.into_inner());
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 16 + 1 + 3 + 1 + 1);
+        assert_eq!(lines[0], format!("{:<64}", "█▀▀█"));
+        assert_eq!(lines[1], format!("{:<64}", "█  █"));
+        assert_eq!(lines[2], format!("{:<64}", "▀▀▀▀"));
+        assert_eq!(lines[17], "PC 208  1208  I 050  SP 0  DT 00  ST 00");
+        assert!(lines[18].starts_with("V0 00  V1 00  V2 00  V3 07"));
+        assert_eq!(lines[21], "INFO  hello");
+        assert!(!bell.take());
+    }
+
+    #[test]
+    fn bell_rings_once_per_beep() {
+        let mut bell = Bell::default();
+        bell.buzz(true);
+        bell.buzz(true);
+        assert!(bell.take());
+        assert!(!bell.take());
+        bell.buzz(false);
+        bell.buzz(true);
+        assert!(bell.take());
+    }
+}
@@ -0,0 +1,116 @@
+// Drawing the screen with text. Half blocks fit two pixels in a character cell, one
+// above the other, braille fits eight so the screen takes a quarter of the space.
+
+use crate::interpreter::trace::Step;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Style {
+    HalfBlocks,
+    Braille,
+}
+
+impl Style {
+    // One line per row of characters, without line endings
+    pub fn render(self, video: &[u8], width: usize, height: usize) -> Vec<String> {
+        match self {
+            Style::HalfBlocks => half_blocks(video, width, height),
+            Style::Braille => braille(video, width, height),
+        }
+    }
+}
+
+fn lit(video: &[u8], width: usize, height: usize, x: usize, y: usize) -> bool {
+    x < width && y < height && video[x + y * width] != 0
+}
+
+pub fn half_blocks(video: &[u8], width: usize, height: usize) -> Vec<String> {
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let top = lit(video, width, height, x, y);
+                    let bottom = lit(video, width, height, x, y + 1);
+                    match (top, bottom) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Bit for each dot of a braille cell, two wide and four high
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub fn braille(video: &[u8], width: usize, height: usize) -> Vec<String> {
+    (0..height)
+        .step_by(4)
+        .map(|y| {
+            (0..width)
+                .step_by(2)
+                .map(|x| {
+                    let mut dots = 0;
+                    for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                        for (dx, bit) in row.iter().enumerate() {
+                            if lit(video, width, height, x + dx, y + dy) {
+                                dots |= bit;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + dots).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Registers and timers for under the screen
+pub fn status(step: &Step) -> Vec<String> {
+    let registers = |range: std::ops::Range<usize>| {
+        range
+            .map(|n| format!("V{:X} {:02X}", n, step.v[n]))
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+    vec![
+        format!(
+            "PC {:03X}  {:04X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}",
+            step.pc, step.opcode, step.i, step.sp, step.dt, step.st
+        ),
+        registers(0..8),
+        registers(8..16),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 4x4 with the top left and bottom right corners lit and the left column of the
+    // bottom row
+    fn video() -> Vec<u8> {
+        let mut video = vec![0; 16];
+        for (x, y) in [(0, 0), (3, 3), (0, 3), (1, 1)] {
+            video[x + y * 4] = 0xFF;
+        }
+        video
+    }
+
+    #[test]
+    fn half_blocks_pair_rows() {
+        assert_eq!(half_blocks(&video(), 4, 4), vec!["▀▄  ", "▄  ▄"]);
+        // Odd heights leave the bottom half of the last line empty
+        assert_eq!(half_blocks(&[0xFF; 3], 1, 3), vec!["█", "▀"]);
+    }
+
+    #[test]
+    fn braille_dots() {
+        // Top left, the one diagonal from it, bottom left of the first cell and bottom
+        // right of the second
+        assert_eq!(braille(&video(), 4, 4), vec!["\u{2851}\u{2880}"]);
+    }
+}
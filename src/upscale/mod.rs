@@ -0,0 +1,466 @@
+// CPU side upscaling of the monochrome video buffer into RGBA images.
+//
+// Everything in here works on plain `Image`s so the same pipeline can feed the
+// GUI texture, screenshots and anything else that wants pixels.
+
+mod scalex;
+mod smooth;
+
+use std::{fmt, str::FromStr};
+
+pub type Rgba = [u8; 4];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+    pub background: Rgba,
+    pub foreground: Rgba,
+}
+
+impl Palette {
+    // The first one is the colors the GUI has always drawn with
+    pub const PRESETS: [(&'static str, Palette); 4] = [
+        (
+            "classic",
+            Palette {
+                background: [0xFF, 0xFF, 0xFF, 0xFF],
+                foreground: [0x00, 0x00, 0x00, 0xFF],
+            },
+        ),
+        (
+            "inverted",
+            Palette {
+                background: [0x00, 0x00, 0x00, 0xFF],
+                foreground: [0xFF, 0xFF, 0xFF, 0xFF],
+            },
+        ),
+        (
+            "amber",
+            Palette {
+                background: [0x1A, 0x10, 0x00, 0xFF],
+                foreground: [0xFF, 0xB0, 0x00, 0xFF],
+            },
+        ),
+        (
+            "phosphor",
+            Palette {
+                background: [0x00, 0x1A, 0x0A, 0xFF],
+                foreground: [0x33, 0xFF, 0x66, 0xFF],
+            },
+        ),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::PRESETS[0].1
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgba>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, fill: Rgba) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    // `video` is one byte per pixel, anything non zero is lit
+    pub fn from_video(video: &[u8], width: usize, height: usize, palette: Palette) -> Self {
+        assert_eq!(video.len(), width * height, "Video buffer size mismatch");
+
+        Image {
+            width,
+            height,
+            pixels: video
+                .iter()
+                .map(|pixel| match *pixel {
+                    0 => palette.background,
+                    _ => palette.foreground,
+                })
+                .collect(),
+        }
+    }
+
+    // Out of bounds reads are clamped to the nearest edge pixel
+    pub fn get(&self, x: isize, y: isize) -> Rgba {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[x + y * self.width]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Rgba) {
+        self.pixels[x + y * self.width] = color;
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest(usize),
+    Scale2x,
+    Scale3x,
+    Smooth,
+}
+
+impl Filter {
+    pub fn factor(&self) -> usize {
+        match *self {
+            Filter::Nearest(factor) => factor,
+            Filter::Scale2x => 2,
+            Filter::Scale3x => 3,
+            Filter::Smooth => 2,
+        }
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        match *self {
+            Filter::Nearest(factor) => nearest(image, factor),
+            Filter::Scale2x => scalex::scale2x(image),
+            Filter::Scale3x => scalex::scale3x(image),
+            Filter::Smooth => smooth::smooth2x(image),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Filter::Nearest(factor) => write!(f, "Nearest {}x", factor),
+            Filter::Scale2x => f.write_str("Scale2x"),
+            Filter::Scale3x => f.write_str("Scale3x"),
+            Filter::Smooth => f.write_str("Smooth"),
+        }
+    }
+}
+
+// Parses the names used on the command line: nearest, nearest4, scale2x, scale3x, smooth
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "scale2x" | "epx" => Ok(Filter::Scale2x),
+            "scale3x" => Ok(Filter::Scale3x),
+            "smooth" => Ok(Filter::Smooth),
+            "nearest" => Ok(Filter::Nearest(1)),
+            name => match name.strip_prefix("nearest").map(str::parse::<usize>) {
+                Some(Ok(factor)) if factor > 0 => Ok(Filter::Nearest(factor)),
+                _ => Err(format!("Unknown filter: {}", s)),
+            },
+        }
+    }
+}
+
+// Filters are run in order, each one working on the output of the last
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Pipeline {
+    filters: Vec<Filter>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline {
+            filters: Vec::new(),
+        }
+    }
+
+    pub fn then(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    pub fn scale(&self) -> usize {
+        self.filters.iter().map(Filter::factor).product()
+    }
+
+    pub fn apply(&self, image: Image) -> Image {
+        self.filters
+            .iter()
+            .fold(image, |image, filter| filter.apply(&image))
+    }
+
+    pub fn render(&self, video: &[u8], width: usize, height: usize, palette: Palette) -> Image {
+        self.apply(Image::from_video(video, width, height, palette))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.filters.is_empty() {
+            return f.write_str("None");
+        }
+
+        for (i, filter) in self.filters.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" + ")?;
+            }
+            write!(f, "{}", filter)?;
+        }
+        Ok(())
+    }
+}
+
+// A comma separated list of filters, i.e "scale2x,nearest4"
+impl FromStr for Pipeline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|name| !name.trim().is_empty())
+            .try_fold(Pipeline::new(), |pipeline, name| {
+                Ok(pipeline.then(name.trim().parse()?))
+            })
+    }
+}
+
+fn nearest(image: &Image, factor: usize) -> Image {
+    assert!(factor > 0, "Scale factor must be at least 1");
+
+    let mut output = Image::new(image.width * factor, image.height * factor, [0; 4]);
+    for y in 0..output.height {
+        for x in 0..output.width {
+            output.set(x, y, image.pixels[x / factor + (y / factor) * image.width]);
+        }
+    }
+    output
+}
+
+// Averages two colors, used by the smoothing filter
+fn blend(a: Rgba, b: Rgba) -> Rgba {
+    let mut out = [0; 4];
+    for i in 0..4 {
+        out[i] = ((a[i] as u16 + b[i] as u16) / 2) as u8;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PALETTE: Palette = Palette {
+        background: [0xFF, 0xFF, 0xFF, 0xFF],
+        foreground: [0x00, 0x00, 0x00, 0xFF],
+    };
+
+    // Text art: '#' is lit, '.' is unlit and '+' is a half blend of the two
+    fn from_art(art: &str) -> Image {
+        let rows = art.split_whitespace().collect::<Vec<_>>();
+        let width = rows[0].len();
+        let video = rows
+            .iter()
+            .flat_map(|row| row.bytes())
+            .map(|c| match c {
+                b'#' => 0xFF,
+                _ => 0x00,
+            })
+            .collect::<Vec<u8>>();
+        Image::from_video(&video, width, rows.len(), PALETTE)
+    }
+
+    fn to_art(image: &Image) -> String {
+        let half = blend(PALETTE.background, PALETTE.foreground);
+        let mut art = String::new();
+        for row in image.pixels.chunks(image.width) {
+            for pixel in row {
+                art.push(match *pixel {
+                    p if p == PALETTE.foreground => '#',
+                    p if p == PALETTE.background => '.',
+                    p if p == half => '+',
+                    _ => '?',
+                });
+            }
+            art.push('\n');
+        }
+        art
+    }
+
+    fn golden(art: &str) -> String {
+        art.split_whitespace()
+            .map(|row| format!("{}\n", row))
+            .collect()
+    }
+
+    // A diagonal and an 'A' from the chip-8 font, enough to hit every rule
+    const INPUT: &str = "
+        #...####
+        .#..#..#
+        ..#.####
+        ...##..#
+        ....#..#
+    ";
+
+    #[test]
+    fn from_video_uses_palette() {
+        let image = Image::from_video(&[0x00, 0xFF, 0x01, 0x00], 2, 2, PALETTE);
+        assert_eq!(
+            image.pixels,
+            vec![
+                PALETTE.background,
+                PALETTE.foreground,
+                PALETTE.foreground,
+                PALETTE.background
+            ]
+        );
+        assert_eq!(image.to_rgba8().len(), 16);
+    }
+
+    #[test]
+    fn nearest_golden() {
+        let output = Filter::Nearest(2).apply(&from_art(INPUT));
+        assert_eq!(
+            to_art(&output),
+            golden(
+                "
+                ##......########
+                ##......########
+                ..##....##....##
+                ..##....##....##
+                ....##..########
+                ....##..########
+                ......####....##
+                ......####....##
+                ........##....##
+                ........##....##
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn scale2x_golden() {
+        let output = Filter::Scale2x.apply(&from_art(INPUT));
+        assert_eq!(
+            to_art(&output),
+            golden(
+                "
+                ##......########
+                #.#.....########
+                .###....##....##
+                ..###...##....##
+                ...###..########
+                ....##..########
+                .....######..###
+                ......####....##
+                .......###....##
+                ........##....##
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn scale3x_golden() {
+        let output = Filter::Scale3x.apply(&from_art(INPUT));
+        assert_eq!(
+            to_art(&output),
+            golden(
+                "
+                ###.........############
+                ##.#........############
+                #..#........############
+                .#####......###......###
+                ...###......###......###
+                ...####.....###......###
+                .....####...############
+                ......###...############
+                ......###...############
+                ........#########..#####
+                .........#######....####
+                .........######......###
+                ...........####......###
+                ...........####......###
+                ............###......###
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn smooth_golden() {
+        let output = Filter::Smooth.apply(&from_art(INPUT));
+        assert_eq!(
+            to_art(&output),
+            golden(
+                "
+                ##......########
+                #++.....########
+                .+##....##....##
+                ..##+...##....##
+                ...+##..########
+                ....##..########
+                .....+####+..+##
+                ......####....##
+                .......+##....##
+                ........##....##
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn pipeline_chains_filters() {
+        let pipeline = Pipeline::new()
+            .then(Filter::Scale2x)
+            .then(Filter::Nearest(3));
+        assert_eq!(pipeline.scale(), 6);
+        assert_eq!(pipeline.to_string(), "Scale2x + Nearest 3x");
+
+        let output = pipeline.apply(from_art(INPUT));
+        assert_eq!(output.width, 48);
+        assert_eq!(output.height, 30);
+        assert_eq!(
+            output,
+            Filter::Nearest(3).apply(&Filter::Scale2x.apply(&from_art(INPUT)))
+        );
+    }
+
+    #[test]
+    fn parse_pipeline() {
+        assert_eq!(
+            "scale2x, nearest4".parse::<Pipeline>(),
+            Ok(Pipeline::new()
+                .then(Filter::Scale2x)
+                .then(Filter::Nearest(4)))
+        );
+        assert_eq!("Smooth".parse::<Filter>(), Ok(Filter::Smooth));
+        assert_eq!("nearest".parse::<Filter>(), Ok(Filter::Nearest(1)));
+        assert!("nearest0".parse::<Filter>().is_err());
+        assert!("bilinear".parse::<Pipeline>().is_err());
+    }
+
+    #[test]
+    fn palette_presets() {
+        assert_eq!(Palette::from_name("Classic"), Some(Palette::default()));
+        assert_eq!(Palette::from_name("sepia"), None);
+    }
+
+    #[test]
+    fn empty_pipeline_is_identity() {
+        let pipeline = Pipeline::new();
+        assert_eq!(pipeline.scale(), 1);
+        assert_eq!(pipeline.apply(from_art(INPUT)), from_art(INPUT));
+    }
+}
@@ -0,0 +1,96 @@
+// Scale2x (also known as EPX) and Scale3x, see https://www.scale2x.it/algorithm
+//
+// Neighbours are named like the reference implementation:
+//   A B C
+//   D E F
+//   G H I
+
+use super::Image;
+
+pub fn scale2x(image: &Image) -> Image {
+    let mut output = Image::new(image.width * 2, image.height * 2, [0; 4]);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let b = image.get(xi, yi - 1);
+            let d = image.get(xi - 1, yi);
+            let e = image.get(xi, yi);
+            let f = image.get(xi + 1, yi);
+            let h = image.get(xi, yi + 1);
+
+            let mut block = [e; 4];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = d;
+                }
+                if b == f {
+                    block[1] = f;
+                }
+                if d == h {
+                    block[2] = d;
+                }
+                if h == f {
+                    block[3] = f;
+                }
+            }
+
+            for (i, color) in block.iter().enumerate() {
+                output.set(x * 2 + i % 2, y * 2 + i / 2, *color);
+            }
+        }
+    }
+    output
+}
+
+pub fn scale3x(image: &Image) -> Image {
+    let mut output = Image::new(image.width * 3, image.height * 3, [0; 4]);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let a = image.get(xi - 1, yi - 1);
+            let b = image.get(xi, yi - 1);
+            let c = image.get(xi + 1, yi - 1);
+            let d = image.get(xi - 1, yi);
+            let e = image.get(xi, yi);
+            let f = image.get(xi + 1, yi);
+            let g = image.get(xi - 1, yi + 1);
+            let h = image.get(xi, yi + 1);
+            let i = image.get(xi + 1, yi + 1);
+
+            let mut block = [e; 9];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = d;
+                }
+                if (d == b && e != c) || (b == f && e != a) {
+                    block[1] = b;
+                }
+                if b == f {
+                    block[2] = f;
+                }
+                if (d == b && e != g) || (d == h && e != a) {
+                    block[3] = d;
+                }
+                if (b == f && e != i) || (h == f && e != c) {
+                    block[5] = f;
+                }
+                if d == h {
+                    block[6] = d;
+                }
+                if (d == h && e != i) || (h == f && e != g) {
+                    block[7] = h;
+                }
+                if h == f {
+                    block[8] = f;
+                }
+            }
+
+            for (n, color) in block.iter().enumerate() {
+                output.set(x * 3 + n % 3, y * 3 + n / 3, *color);
+            }
+        }
+    }
+    output
+}
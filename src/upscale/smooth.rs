@@ -0,0 +1,41 @@
+// A cheap take on hqx. It uses the same corner rules as Scale2x but instead of
+// copying the neighbour into the corner it blends it with the center pixel,
+// which softens diagonals without inventing new shapes.
+
+use super::{blend, Image};
+
+pub fn smooth2x(image: &Image) -> Image {
+    let mut output = Image::new(image.width * 2, image.height * 2, [0; 4]);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let b = image.get(xi, yi - 1);
+            let d = image.get(xi - 1, yi);
+            let e = image.get(xi, yi);
+            let f = image.get(xi + 1, yi);
+            let h = image.get(xi, yi + 1);
+
+            let mut block = [e; 4];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = blend(e, d);
+                }
+                if b == f {
+                    block[1] = blend(e, f);
+                }
+                if d == h {
+                    block[2] = blend(e, d);
+                }
+                if h == f {
+                    block[3] = blend(e, f);
+                }
+            }
+
+            for (i, color) in block.iter().enumerate() {
+                output.set(x * 2 + i % 2, y * 2 + i / 2, *color);
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,41 @@
+// Translates the Timendus suite into a crate of its own under the test's temporary
+// directory, builds it without its window and runs tests/aot/check.rs in it, which
+// checks the translation stays in step with the interpreter frame by frame.
+
+use std::path::Path;
+use std::process::Command;
+
+use paulson_8::aot;
+
+const ROM: &str = "tests/roms/timendus/chip8-test-suite.ch8";
+
+#[test]
+fn matches_interpreter() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("timendus");
+    let rom = std::fs::read(root.join(ROM)).unwrap();
+
+    for (path, contents) in aot::package(&rom, "Timendus", &root.join("runtime")) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    std::fs::create_dir_all(dir.join("src/bin")).unwrap();
+    std::fs::copy(
+        root.join("tests/aot/check.rs"),
+        dir.join("src/bin/check.rs"),
+    )
+    .unwrap();
+    // Same versions as here, and nothing to fetch
+    if !dir.join("Cargo.lock").exists() {
+        std::fs::copy(root.join("Cargo.lock"), dir.join("Cargo.lock")).unwrap();
+    }
+
+    let status = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--no-default-features", "--bin", "check"])
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "Translation differs from the interpreter");
+}
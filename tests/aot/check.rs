@@ -0,0 +1,43 @@
+// Runs the translated Timendus suite next to the interpreter and fails on the first
+// frame where they differ. tests/aot.rs copies this into the generated crate as
+// src/bin/check.rs, it doesn't build as part of paulson-8.
+
+#[allow(dead_code)]
+#[path = "../program.rs"]
+mod program;
+
+use paulson_8_runtime::{machine, run, Chip8};
+use program::Timendus;
+
+fn state(chip8: &Chip8) -> Vec<u8> {
+    let mut state = vec![0; Chip8::STATE_SIZE];
+    chip8.save_state(&mut state).unwrap();
+    state
+}
+
+fn main() {
+    // corax+, flags and quirks with the SCHIP option, none of them touch RND
+    for (test, option) in [(2, 0), (3, 0), (4, 2)] {
+        let setup = || {
+            let mut chip8 = machine::<Timendus>();
+            chip8.write_memory(0x1FE, &[option]);
+            chip8.write_memory(0x1FF, &[test]);
+            chip8
+        };
+        let mut translated = setup();
+        let mut interpreted = setup();
+
+        for frame in 0..400 {
+            run::<Timendus>(&mut translated, 20).unwrap();
+            interpreted.run(20).unwrap();
+
+            assert!(
+                state(&translated) == state(&interpreted),
+                "Test {} option {} differs after frame {}",
+                test,
+                option,
+                frame
+            );
+        }
+    }
+}
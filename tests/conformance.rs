@@ -0,0 +1,68 @@
+// Runs the Timendus chip-8 test suite headlessly and compares the final screen of
+// each test against a golden frame in tests/golden/timendus.
+//
+// The suite is a single ROM, the test to run is picked by writing its number to
+// 0x1FF (and a sub option to 0x1FE) before starting, same as the ROM's own menu does.
+// Also a small ROM that only draws at random, with a fixed seed, see tests/roms/random.
+// Run with BLESS=1 to update the goldens.
+
+use paulson_8::golden::{GoldenTest, InputScript};
+
+const ROM: &str = "tests/roms/timendus/chip8-test-suite.ch8";
+
+fn case(name: &str, test: u8, option: u8, frames: usize, input: InputScript) -> GoldenTest {
+    GoldenTest::new(ROM, format!("tests/golden/timendus/{}.txt", name))
+        .frames(frames)
+        .input(input)
+        .poke(0x1FE, &[option])
+        .poke(0x1FF, &[test])
+}
+
+#[test]
+fn timendus_test_suite() {
+    // Held keys are drawn inverted for EX9E and the other way round for EXA1
+    let held = InputScript::new()
+        .press(30, 0x1)
+        .press(30, 0x5)
+        .press(30, 0xA);
+    let tap = InputScript::new().press(30, 0x7).release(40, 0x7);
+    let none = InputScript::new;
+
+    let cases = [
+        ("logo", case("logo", 1, 0, 100, none())),
+        ("corax", case("corax", 2, 0, 200, none())),
+        ("flags", case("flags", 3, 0, 400, none())),
+        // Option 2 is SCHIP, the closest to what we do. Jumping shows a cross since
+        // BNNN still jumps relative to V0 like the original chip-8
+        ("quirks-schip", case("quirks-schip", 4, 2, 300, none())),
+        ("keypad-ex9e", case("keypad-ex9e", 5, 1, 100, held.clone())),
+        ("keypad-exa1", case("keypad-exa1", 5, 2, 100, held)),
+        ("keypad-fx0a", case("keypad-fx0a", 5, 3, 60, tap)),
+    ];
+
+    let mut failed = Vec::new();
+    for (name, test) in &cases {
+        match test.check() {
+            Ok(()) => println!("{:<14} pass", name),
+            Err(report) => {
+                println!("{:<14} FAIL\n{}", name, report);
+                failed.push(*name);
+            }
+        }
+    }
+
+    assert!(failed.is_empty(), "Failed: {}", failed.join(", "));
+}
+
+#[test]
+fn seeded_random() {
+    let test = GoldenTest::new(
+        "tests/roms/random/digits.ch8",
+        "tests/golden/random/digits.txt",
+    )
+    .frames(30)
+    .seed(1234);
+    if let Err(report) = test.check() {
+        panic!("{}", report);
+    }
+}
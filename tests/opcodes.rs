@@ -0,0 +1,144 @@
+// Walks every possible instruction word through the decoder and encoder, and keeps
+// docs/opcodes.md in sync with what the decoder actually does.
+//
+// After an intentional change to decoding regenerate the table with
+//   BLESS=1 cargo test --test opcodes
+
+use std::collections::HashMap;
+
+use paulson_8::interpreter::opcodes::OP;
+
+const TABLE: &str = "docs/opcodes.md";
+
+// Words that mean something on later platforms, all of them decode to something else
+// here. Pattern, SCHIP and XO-CHIP
+const OTHER_PLATFORMS: [(&str, &str, &str); 16] = [
+    ("00Cn", "Scroll down n rows", "Scroll down n rows"),
+    ("00Dn", "", "Scroll up n rows"),
+    ("00FB", "Scroll right 4 pixels", "Scroll right 4 pixels"),
+    ("00FC", "Scroll left 4 pixels", "Scroll left 4 pixels"),
+    ("00FD", "Exit", "Exit"),
+    ("00FE", "Low resolution", "Low resolution"),
+    ("00FF", "High resolution", "High resolution"),
+    ("5xy2", "", "Save vx to vy at I"),
+    ("5xy3", "", "Load vx to vy from I"),
+    ("Bxnn", "Jump to xnn + vx", ""),
+    ("Dxy0", "16x16 sprite", "16x16 sprite"),
+    ("F000", "", "Load I with the next word"),
+    ("Fx01", "", "Select bit planes x"),
+    ("F002", "", "Load audio pattern from I"),
+    ("Fx30", "Large font digit vx", "Large font digit vx"),
+    ("Fx3A", "", "Pitch from vx"),
+];
+
+// Like "8xy6", lowercase letters are operands and the rest is fixed by the OP
+fn pattern(word: u16, op: &OP) -> String {
+    let mut digits = format!("{:04X}", word).chars().collect::<Vec<char>>();
+
+    if let OP::INV { .. } = op {
+        // Group invalid words by the family they fall in
+        return match digits[0] {
+            '0' => "0nnn".to_string(),
+            '5' | '8' | '9' => format!("{}xy{}", digits[0], digits[3]),
+            first => format!("{}xkk", first),
+        };
+    }
+
+    // Operand names straight from the Debug output, i.e "SHR { vx: 1, vy: 2 }"
+    let debug = format!("{:?}", op);
+    for field in debug.split(['{', ',']).skip(1) {
+        let (nibbles, letter): (&[usize], char) = match field.split(':').next().unwrap().trim() {
+            "addr" => (&[1, 2, 3], 'n'),
+            "vx" => (&[1], 'x'),
+            "vy" => (&[2], 'y'),
+            "byte" => (&[2, 3], 'k'),
+            "height" => (&[3], 'n'),
+            // INV is handled above, anything else is a new operand
+            other => panic!("Unknown operand {}", other),
+        };
+        for nibble in nibbles {
+            digits[*nibble] = letter;
+        }
+    }
+    digits.into_iter().collect()
+}
+
+fn variant(op: &OP) -> String {
+    let debug = format!("{:?}", op);
+    debug.split_whitespace().next().unwrap().to_string()
+}
+
+fn table() -> String {
+    let mut rows: Vec<(String, String)> = Vec::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for word in 0..=u16::MAX {
+        let op = OP::from(word);
+        let pattern = pattern(word, &op);
+        if !counts.contains_key(&pattern) {
+            rows.push((pattern.clone(), variant(&op)));
+        }
+        *counts.entry(pattern).or_default() += 1;
+    }
+
+    let mut text = String::from(
+        "# Opcodes\n\n\
+         Generated by `tests/opcodes.rs` from the decoder, regenerate with \
+         `BLESS=1 cargo test --test opcodes`.\n\n\
+         ## CHIP-8\n\n\
+         Every instruction word and the OP it decodes to. Lowercase letters are operands, \
+         words is how many of the 65536 words match the row.\n\n\
+         | Pattern | OP | Words |\n\
+         |---------|----|-------|\n",
+    );
+    for (pattern, variant) in &rows {
+        text += &format!("| {} | {} | {} |\n", pattern, variant, counts[pattern]);
+    }
+
+    text += "\n## Other platforms\n\n\
+             Words later platforms gave a meaning to, and what Paulson-8 does with them.\n\n\
+             | Pattern | SCHIP | XO-CHIP | Paulson-8 |\n\
+             |---------|-------|---------|-----------|\n";
+    for (pattern, schip, xo_chip) in OTHER_PLATFORMS {
+        // Any operands will do, zeros it is
+        let word =
+            u16::from_str_radix(&pattern.replace(|c: char| c.is_lowercase(), "0"), 16).unwrap();
+        let op = OP::from(word);
+        text += &format!(
+            "| {} | {} | {} | {} |\n",
+            pattern,
+            schip,
+            xo_chip,
+            variant(&op)
+        );
+    }
+
+    text
+}
+
+#[test]
+fn every_word_round_trips() {
+    for word in 0..=u16::MAX {
+        let op = OP::from(word);
+        assert_eq!(u16::from(op), word, "{:?} came from {:#06X}", op, word);
+
+        if let OP::INV { opcode } = op {
+            assert_eq!(opcode, word);
+        }
+    }
+}
+
+#[test]
+fn table_is_up_to_date() {
+    let table = table();
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::write(TABLE, &table).unwrap();
+        return;
+    }
+
+    let current = std::fs::read_to_string(TABLE).unwrap_or_default();
+    assert!(
+        current == table,
+        "{} is out of date, regenerate it with BLESS=1 cargo test --test opcodes",
+        TABLE
+    );
+}
@@ -0,0 +1,32 @@
+// Replays the checked in single step vectors, regenerate them with
+//   cargo run --features cli --bin paulson-8-vectors -- tests/vectors --count 10
+// after an intentional change in behaviour.
+
+use paulson_8::interpreter::vectors::Vector;
+
+#[test]
+fn single_step_vectors() {
+    let mut files = std::fs::read_dir("tests/vectors")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert!(!files.is_empty(), "No vectors in tests/vectors");
+
+    let mut failed = Vec::new();
+    for path in files {
+        let json = std::fs::read_to_string(&path).unwrap();
+        let vectors: Vec<Vector> = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("Couldn't parse {}: {}", path.display(), e));
+
+        for vector in vectors {
+            let diff = vector.replay();
+            if !diff.is_empty() {
+                println!("{} FAIL\n  {}", vector.name, diff.join("\n  "));
+                failed.push(vector.name);
+            }
+        }
+    }
+
+    assert!(failed.is_empty(), "Failed: {}", failed.join(", "));
+}